@@ -0,0 +1,6 @@
+fn main() {
+    // Con la integración de ESP-IDF, las mismas cfg del sdkconfig que ven los
+    // firmwares (esp_idf_eth_use_openeth, esp_idf_nvs_encryption...)
+    #[cfg(feature = "espidf")]
+    embuild::espidf::sysenv::output();
+}
@@ -0,0 +1,146 @@
+// Ejecución de comandos sobre los LEDs y el buzzer de ESP32 #2. Vive en su
+// propia tarea: un beep puede bloquear hasta 5 s sin afectar a nadie más.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+use crate::clock::Clock;
+use crate::command::{Command, CommandError, ErrorCode};
+use crate::drivers::led::LED_COUNT;
+use crate::drivers::{BuzzerController, BuzzerError, LedController, LedError, Tone};
+use crate::event::CommandOutcome;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActuatorError {
+    MissingLedId,
+    Unsupported,
+    Led(LedError),
+    Buzzer(BuzzerError),
+}
+
+impl From<LedError> for ActuatorError {
+    fn from(error: LedError) -> Self {
+        ActuatorError::Led(error)
+    }
+}
+
+impl From<BuzzerError> for ActuatorError {
+    fn from(error: BuzzerError) -> Self {
+        ActuatorError::Buzzer(error)
+    }
+}
+
+impl core::fmt::Display for ActuatorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ActuatorError::MissingLedId => f.write_str("LED ID requerido"),
+            ActuatorError::Unsupported => f.write_str("Comando no implementado"),
+            ActuatorError::Led(e) => e.fmt(f),
+            ActuatorError::Buzzer(e) => e.fmt(f),
+        }
+    }
+}
+
+impl CommandError for ActuatorError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ActuatorError::MissingLedId => ErrorCode::InvalidParameter,
+            ActuatorError::Unsupported => ErrorCode::UnsupportedCommand,
+            ActuatorError::Led(e) => e.code(),
+            ActuatorError::Buzzer(e) => e.code(),
+        }
+    }
+}
+
+pub struct Actuator<P: OutputPin, T: Tone, D: DelayNs, C: Clock> {
+    leds: LedController<P, C>,
+    buzzer: BuzzerController<T, D, C>,
+    emergency_stop: bool,
+}
+
+impl<P: OutputPin, T: Tone, D: DelayNs, C: Clock> Actuator<P, T, D, C> {
+    pub fn new(leds: LedController<P, C>, buzzer: BuzzerController<T, D, C>) -> Self {
+        Actuator {
+            leds,
+            buzzer,
+            emergency_stop: false,
+        }
+    }
+
+    // LED_ALL_OFF con "emergency":true salta el rate limiting y hace sonar la alarma
+    pub fn with_emergency_stop(mut self) -> Self {
+        self.emergency_stop = true;
+        self
+    }
+
+    pub fn leds(&mut self) -> &mut LedController<P, C> {
+        &mut self.leds
+    }
+
+    pub fn buzzer(&mut self) -> &mut BuzzerController<T, D, C> {
+        &mut self.buzzer
+    }
+
+    pub fn led_states(&self) -> [bool; LED_COUNT] {
+        self.leds.states()
+    }
+
+    // Ejecuta el comando y devuelve el resultado para responder al emisor,
+    // con los tonos que sonaron de verdad para cobrarlos de la cuota
+    pub fn handle(&mut self, command: Command) -> CommandOutcome {
+        let tones = self.buzzer.tones();
+        let mut outcome = match self.execute(&command) {
+            Ok(message) => CommandOutcome::ok(command, message),
+            Err(e) => CommandOutcome::failed(command, &e),
+        };
+        outcome.beeps = self.buzzer.tones() - tones;
+        outcome
+    }
+
+    pub fn execute(&mut self, command: &Command) -> Result<String, ActuatorError> {
+        match command.command.as_str() {
+            "LED_ON" => {
+                let led_id = command.led_id.ok_or(ActuatorError::MissingLedId)?;
+                self.leds.set_led(led_id, true)?;
+                Ok(format!("LED {} encendido", led_id))
+            },
+            "LED_OFF" => {
+                let led_id = command.led_id.ok_or(ActuatorError::MissingLedId)?;
+                self.leds.set_led(led_id, false)?;
+                Ok(format!("LED {} apagado", led_id))
+            },
+            "LED_TOGGLE" => {
+                let led_id = command.led_id.ok_or(ActuatorError::MissingLedId)?;
+                let new_state = self.leds.toggle_led(led_id)?;
+                Ok(format!("LED {} {}", led_id, if new_state { "encendido" } else { "apagado" }))
+            },
+            "LED_ALL_ON" => {
+                self.leds.turn_on_all();
+                Ok("Todos los LEDs encendidos".to_string())
+            },
+            "LED_ALL_OFF" if self.emergency_stop && command.emergency == Some(true) => {
+                self.leds.emergency_shutdown();
+                let _ = self.buzzer.emergency_beep();
+                Ok("Emergency shutdown ejecutado".to_string())
+            },
+            "LED_ALL_OFF" => {
+                self.leds.turn_off_all();
+                Ok("Todos los LEDs apagados".to_string())
+            },
+            "BUZZER" => {
+                let duration = command.duration.unwrap_or(500);
+                self.buzzer.beep(1000, duration)?;
+                Ok(format!("Buzzer activado por {}ms", duration))
+            },
+            "BUZZER_TRIPLE" => {
+                self.buzzer.triple_beep()?;
+                Ok("Triple beep ejecutado".to_string())
+            },
+            "ACKNOWLEDGE" => {
+                let _ = self.buzzer.beep(750, 300);
+                Ok("Acknowledge recibido".to_string())
+            },
+            _ => Err(ActuatorError::Unsupported),
+        }
+    }
+}
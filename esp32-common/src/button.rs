@@ -0,0 +1,53 @@
+// Detección de flancos con debouncing para los botones de ambos dispositivos
+
+// Tiempo mínimo entre dos pulsaciones del mismo botón
+pub const DEBOUNCE_MS: u64 = 250;
+
+pub struct Debouncer<const N: usize> {
+    last_states: [bool; N],
+    last_press_time: [Option<u64>; N],
+    debounce_ms: u64,
+}
+
+impl<const N: usize> Debouncer<N> {
+    pub fn new() -> Self {
+        Self::with_debounce(DEBOUNCE_MS)
+    }
+
+    pub fn with_debounce(debounce_ms: u64) -> Self {
+        Debouncer {
+            last_states: [false; N],
+            last_press_time: [None; N],
+            debounce_ms,
+        }
+    }
+
+    // `pressed[i]` es true mientras el botón i está presionado (pin en bajo).
+    // Devuelve el id (1..=N) del primer botón con un flanco válido.
+    pub fn update(&mut self, pressed: [bool; N], now_ms: u64) -> Option<u8> {
+        for (i, &current) in pressed.iter().enumerate() {
+            let last = self.last_states[i];
+            self.last_states[i] = current;
+
+            if current && !last {
+                // Detecta flanco descendente (botón presionado)
+                let debounced = match self.last_press_time[i] {
+                    Some(t) => now_ms.saturating_sub(t) > self.debounce_ms,
+                    None => true,
+                };
+                if debounced {
+                    self.last_press_time[i] = Some(now_ms);
+                    return Some(i as u8 + 1);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<const N: usize> Default for Debouncer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
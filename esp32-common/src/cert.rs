@@ -0,0 +1,233 @@
+// Lectura mínima de certificados X.509 en DER: solo lo necesario para saber
+// si el certificado embebido en el firmware sigue vigente. La verificación
+// criptográfica la hace mbedTLS durante el handshake; esto existe para fallar
+// con un mensaje claro al compilar (y en el dispositivo, en cuanto SNTP da la
+// hora) en vez de con un handshake rechazado sin explicación.
+
+use core::fmt;
+
+// Aviso al compilar si el certificado caduca antes de este margen
+pub const EXPIRY_WARNING_SECS: u64 = 30 * 24 * 3600;
+
+// En el dispositivo la vigencia se revisa de nuevo cada hora
+pub const RECHECK_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertError {
+    // No es un certificado DER que sepamos leer
+    Malformed,
+    NotYetValid { not_before: u64 },
+    Expired { not_after: u64 },
+}
+
+impl fmt::Display for CertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertError::Malformed => write!(f, "Certificate is not valid DER X.509"),
+            CertError::NotYetValid { not_before } => {
+                write!(f, "Certificate is not valid before {} (unix time)", not_before)
+            }
+            CertError::Expired { not_after } => {
+                write!(f, "Certificate expired at {} (unix time)", not_after)
+            }
+        }
+    }
+}
+
+// Periodo de validez en segundos unix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Validity {
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl Validity {
+    pub fn check(&self, now: u64) -> Result<(), CertError> {
+        if now < self.not_before {
+            Err(CertError::NotYetValid { not_before: self.not_before })
+        } else if now > self.not_after {
+            Err(CertError::Expired { not_after: self.not_after })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn remaining_secs(&self, now: u64) -> u64 {
+        self.not_after.saturating_sub(now)
+    }
+}
+
+// Cuándo revisar la vigencia en el dispositivo. Al arrancar el reloj está en
+// 1970 y cualquier fecha daría un resultado falso: la primera revisión es en
+// cuanto hay hora real y después cada RECHECK_INTERVAL_SECS, porque un
+// firmware que lleva meses encendido también ve caducar sus certificados
+#[derive(Debug, Default)]
+pub struct ExpiryCheck {
+    last: Option<u64>,
+}
+
+impl ExpiryCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // La hora (segundos unix) con la que revisar, o None si no toca o aún
+    // no hay hora sincronizada
+    pub fn due(&mut self, now: Option<u64>) -> Option<u64> {
+        let now = now?;
+        if self.last.is_some_and(|last| now.saturating_sub(last) < RECHECK_INTERVAL_SECS) {
+            return None;
+        }
+        self.last = Some(now);
+        Some(now)
+    }
+}
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0C;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_VERSION: u8 = 0xA0;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+
+// OID 2.5.4.3 (commonName)
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+// Un elemento TLV: etiqueta, contenido y lo que queda detrás
+fn read_tlv(der: &[u8]) -> Result<(u8, &[u8], &[u8]), CertError> {
+    let (&tag, rest) = der.split_first().ok_or(CertError::Malformed)?;
+    let (&first, rest) = rest.split_first().ok_or(CertError::Malformed)?;
+
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 3 || rest.len() < count {
+            return Err(CertError::Malformed);
+        }
+        let len = rest[..count].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[count..])
+    };
+
+    if rest.len() < len {
+        return Err(CertError::Malformed);
+    }
+    Ok((tag, &rest[..len], &rest[len..]))
+}
+
+fn expect(der: &[u8], tag: u8) -> Result<(&[u8], &[u8]), CertError> {
+    match read_tlv(der)? {
+        (t, content, rest) if t == tag => Ok((content, rest)),
+        _ => Err(CertError::Malformed),
+    }
+}
+
+// Certificate ::= SEQUENCE { tbsCertificate, ... }
+// TBSCertificate ::= SEQUENCE { [0] version OPTIONAL, serialNumber,
+//                               signature, issuer, validity, subject, ... }
+// Devuelve el contenido de validity y lo que le sigue, empezando por subject
+fn validity_and_subject(der: &[u8]) -> Result<(&[u8], &[u8]), CertError> {
+    let (certificate, _) = expect(der, TAG_SEQUENCE)?;
+    let (tbs, _) = expect(certificate, TAG_SEQUENCE)?;
+
+    let mut fields = tbs;
+    if fields.first() == Some(&TAG_VERSION) {
+        fields = read_tlv(fields)?.2;
+    }
+    let (_, fields) = expect(fields, TAG_INTEGER)?;
+    let (_, fields) = expect(fields, TAG_SEQUENCE)?;
+    let (_, fields) = expect(fields, TAG_SEQUENCE)?;
+    expect(fields, TAG_SEQUENCE)
+}
+
+pub fn validity(der: &[u8]) -> Result<Validity, CertError> {
+    let (validity, _) = validity_and_subject(der)?;
+    let (not_before, rest) = read_time(validity)?;
+    let (not_after, _) = read_time(rest)?;
+    Ok(Validity { not_before, not_after })
+}
+
+// CN del subject. Con use_identity_as_username el broker lo usa como usuario
+// en la ACL, así que tiene que coincidir con el device_id del dispositivo.
+// Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value }
+pub fn common_name(der: &[u8]) -> Result<&str, CertError> {
+    let (_, rest) = validity_and_subject(der)?;
+    let (mut names, _) = expect(rest, TAG_SEQUENCE)?;
+
+    while !names.is_empty() {
+        let (set, next) = expect(names, TAG_SET)?;
+        let (attribute, _) = expect(set, TAG_SEQUENCE)?;
+        let (oid, value) = expect(attribute, TAG_OID)?;
+        if oid == OID_COMMON_NAME {
+            return match read_tlv(value)? {
+                (TAG_UTF8_STRING | TAG_PRINTABLE_STRING, text, _) => {
+                    core::str::from_utf8(text).map_err(|_| CertError::Malformed)
+                },
+                _ => Err(CertError::Malformed),
+            };
+        }
+        names = next;
+    }
+    Err(CertError::Malformed)
+}
+
+fn read_time(der: &[u8]) -> Result<(u64, &[u8]), CertError> {
+    let (tag, content, rest) = read_tlv(der)?;
+    let time = match tag {
+        TAG_UTC_TIME => parse_time(content, false)?,
+        TAG_GENERALIZED_TIME => parse_time(content, true)?,
+        _ => return Err(CertError::Malformed),
+    };
+    Ok((time, rest))
+}
+
+// UTCTime "YYMMDDHHMMSSZ" o GeneralizedTime "YYYYMMDDHHMMSSZ"
+fn parse_time(text: &[u8], four_digit_year: bool) -> Result<u64, CertError> {
+    let digits = if four_digit_year { 14 } else { 12 };
+    if text.len() != digits + 1 || text[digits] != b'Z' {
+        return Err(CertError::Malformed);
+    }
+
+    let number = |range: core::ops::Range<usize>| -> Result<u64, CertError> {
+        text[range].iter().try_fold(0u64, |acc, &b| {
+            if b.is_ascii_digit() {
+                Ok(acc * 10 + (b - b'0') as u64)
+            } else {
+                Err(CertError::Malformed)
+            }
+        })
+    };
+
+    let (year, rest) = if four_digit_year {
+        (number(0..4)?, 4)
+    } else {
+        // RFC 5280: YY >= 50 es 19YY, si no 20YY
+        let yy = number(0..2)?;
+        (if yy >= 50 { 1900 + yy } else { 2000 + yy }, 2)
+    };
+    let month = number(rest..rest + 2)?;
+    let day = number(rest + 2..rest + 4)?;
+    let hour = number(rest + 4..rest + 6)?;
+    let minute = number(rest + 6..rest + 8)?;
+    let second = number(rest + 8..rest + 10)?;
+
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return Err(CertError::Malformed);
+    }
+
+    Ok(days_since_epoch(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// Días desde 1970-01-01 para una fecha del calendario gregoriano
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,77 @@
+// Fuente de tiempo para todo lo que depende del tiempo (rate limiting,
+// debouncing, ventanas de comandos). En el firmware se usa esp_timer; en los
+// tests un reloj que se avanza a mano.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub trait Clock {
+    // Milisegundos desde el arranque (monótono)
+    fn now_ms(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_ms(&self) -> u64 {
+        (**self).now_ms()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for std::sync::Arc<C> {
+    fn now_ms(&self) -> u64 {
+        (**self).now_ms()
+    }
+}
+
+// Hora Unix en ms (SNTP en el firmware); None si el reloj aún no se ha
+// sincronizado y sigue cerca de 1970
+pub fn wall_clock_ms() -> Option<u64> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_millis() as u64;
+    (now >= crate::signature::MIN_WALL_CLOCK_MS).then_some(now)
+}
+
+// Reloj real basado en esp_timer_get_time()
+#[cfg(target_os = "espidf")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EspTimerClock;
+
+#[cfg(target_os = "espidf")]
+extern "C" {
+    fn esp_timer_get_time() -> i64;
+}
+
+#[cfg(target_os = "espidf")]
+impl Clock for EspTimerClock {
+    fn now_ms(&self) -> u64 {
+        (unsafe { esp_timer_get_time() } / 1000) as u64
+    }
+}
+
+// Reloj para tests: solo avanza cuando se le pide
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_ms: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start_ms: u64) -> Self {
+        ManualClock {
+            now_ms: AtomicU64::new(start_ms),
+        }
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now_ms.fetch_add(ms, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, ms: u64) {
+        self.now_ms.store(ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
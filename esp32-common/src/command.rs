@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+use crate::message::{decode_command, DecodeError};
+use crate::mqtt5::ReplyTo;
+use crate::quota::Reservation;
+use crate::signature::Signed;
+
+// Comandos que entienden los dispositivos
+pub const ALLOWED_COMMANDS: &[&str] = &[
+    "LED_ON",
+    "LED_OFF",
+    "LED_TOGGLE",
+    "LED_ALL_ON",
+    "LED_ALL_OFF",
+    "BUZZER",
+    "BUZZER_TRIPLE",
+    "ACKNOWLEDGE",
+    // Lo que queda de las cuotas diarias (esp32_common::quota)
+    "QUOTA_STATUS",
+];
+
+pub fn validate_command(command: &str) -> bool {
+    ALLOWED_COMMANDS.contains(&command)
+}
+
+// Estructura para comando recibido por esp32/{device_id}/cmd
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub from: String,
+    pub to: String,
+    pub command: String,
+    pub led_id: Option<u8>,
+    pub duration: Option<u64>,
+    pub emergency: Option<bool>,
+    pub security: Option<String>,
+    pub request_id: Option<String>,
+    // Firma HMAC si el emisor la incluyó (en caja, como reply_to)
+    pub signed: Option<Box<Signed>>,
+    // Response topic y correlation data si llegó por MQTT 5 (en caja: casi
+    // nunca está y así Command no crece)
+    pub reply_to: Option<Box<ReplyTo>>,
+    // Cuota reservada al aceptarlo (quota::Quotas::consume); None si no gastó
+    pub quota: Option<Reservation>,
+}
+
+impl Command {
+    // Comando sin parámetros opcionales (p. ej. generado por un botón local)
+    pub fn new(from: &str, to: &str, command: &str) -> Self {
+        Command {
+            from: from.to_string(),
+            to: to.to_string(),
+            command: command.to_string(),
+            led_id: None,
+            duration: None,
+            emergency: None,
+            security: None,
+            request_id: None,
+            signed: None,
+            reply_to: None,
+            quota: None,
+        }
+    }
+
+    pub fn from_json(json_str: &str) -> Result<Self, DecodeError> {
+        decode_command(json_str.as_bytes())
+    }
+
+    pub fn validate_parameters(&self) -> Result<(), ParameterError> {
+        // Validar LED ID
+        if let Some(led_id) = self.led_id {
+            if !(1..=3).contains(&led_id) {
+                return Err(ParameterError::InvalidLedId);
+            }
+        }
+
+        // Validar duration
+        if let Some(duration) = self.duration {
+            if duration > 10000 {
+                return Err(ParameterError::DurationTooLong);
+            }
+            if duration < 50 {
+                return Err(ParameterError::DurationTooShort);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterError {
+    InvalidLedId,
+    DurationTooLong,
+    DurationTooShort,
+}
+
+impl core::fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ParameterError::InvalidLedId => "LED ID must be between 1 and 3",
+            ParameterError::DurationTooLong => "Duration cannot exceed 10000ms",
+            ParameterError::DurationTooShort => "Duration too short (minimum 50ms)",
+        })
+    }
+}
+
+impl CommandError for ParameterError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::InvalidParameter
+    }
+}
+
+// Código estable del motivo de fallo de un comando, para que los clientes no
+// dependan del texto del error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidParameter,
+    Unauthorized,
+    RateLimited,
+    QuotaExceeded,
+    UnsupportedCommand,
+    HardwareError,
+    Busy,
+}
+
+// Errores con los que puede terminar un comando (validador, cuotas, actuador
+// y drivers): cada uno lleva su código, así que cambiar el texto del mensaje
+// no cambia lo que ve el cliente
+pub trait CommandError: core::fmt::Display {
+    fn code(&self) -> ErrorCode;
+}
+
+// Genera ids de petición únicos por emisor: "<device_id>-<secuencia>"
+pub struct RequestIds {
+    prefix: String,
+    next: u32,
+}
+
+impl RequestIds {
+    pub fn new(device_id: &str) -> Self {
+        RequestIds {
+            prefix: device_id.to_string(),
+            next: 1,
+        }
+    }
+
+    pub fn next_id(&mut self) -> String {
+        let id = format!("{}-{}", self.prefix, self.next);
+        self.next = self.next.wrapping_add(1);
+        id
+    }
+}
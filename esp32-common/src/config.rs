@@ -0,0 +1,152 @@
+use crate::credentials::{Credentials, Requirements, WifiCredentials};
+use crate::discovery::Endpoint;
+use crate::link::NetworkBackend;
+use crate::message::Encoding;
+use crate::mqtt5::{ProtocolPreference, COMMAND_EXPIRY_SECS};
+use crate::outbox::{OverflowPolicy, DEFAULT_CAPACITY};
+use crate::quota::{QuotaLimits, DEFAULT_BUZZER_PER_DAY, DEFAULT_COMMANDS_PER_DAY};
+use crate::signature::Keyring;
+use crate::topics;
+
+// Configuración de seguridad
+pub struct SecurityConfig {
+    pub network: NetworkBackend,         // WiFi o Ethernet (QEMU)
+    pub wifi: Option<WifiCredentials>,  // Siempre presente con NETWORK=wifi
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub mqtt_broker: Option<Endpoint>,   // None: se busca por mDNS
+    pub api_server: Option<Endpoint>,    // None: se busca por mDNS
+    pub device_id: String,
+    pub groups: Vec<String>,             // Grupos de comandos (esp32/group/{g}/cmd)
+    pub peer_device_id: Option<String>,  // Dispositivo con el que se hace la comunicación cruzada
+    pub max_command_rate: u32, // Comandos máximos por minuto de cada emisor
+    pub outbox_capacity: usize,           // Registros de telemetría guardados sin conexión
+    pub outbox_policy: OverflowPolicy,    // Qué se pierde cuando el outbox se llena
+    pub mqtt_protocol: ProtocolPreference, // MQTT 5, 3.1.1 o 5 con vuelta a 3.1.1
+    pub command_expiry_secs: u32,         // Vida en el broker de los comandos enviados (MQTT 5)
+    pub payload_encoding: Encoding,       // JSON o CBOR para todo lo que publica el dispositivo
+    pub command_keys: Keyring,            // Claves HMAC por emisor; vacías solo fuera de la variante segura
+    pub quota: QuotaLimits,               // Cuotas diarias de buzzer y comandos
+}
+
+impl SecurityConfig {
+    // NETWORK=ethernet para placas sin WiFi o el QEMU de Espressif
+    pub fn network_backend() -> Result<NetworkBackend, &'static str> {
+        match option_env!("NETWORK") {
+            Some(value) => NetworkBackend::parse(value).ok_or("NETWORK must be wifi or ethernet"),
+            None => Ok(NetworkBackend::Wifi),
+        }
+    }
+
+    // Credenciales que exige esta compilación antes de arrancar la red
+    pub fn requirements(signed_commands: bool) -> Result<Requirements, &'static str> {
+        Ok(Requirements::new(Self::network_backend()?, signed_commands))
+    }
+
+    // Los secretos (id, WiFi, MQTT, claves) llegan de la NVS cifrada; el resto
+    // de ajustes, que no son secretos, se definen al compilar
+    pub fn load(credentials: Credentials) -> Result<Self, &'static str> {
+        // DEVICE_GROUPS="actuators,planta-1"
+        let groups: Vec<String> = option_env!("DEVICE_GROUPS")
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !groups.iter().all(|g| topics::is_valid_id(g)) {
+            return Err("DEVICE_GROUPS contains an invalid group name");
+        }
+
+        // OUTBOX_CAPACITY=256 OUTBOX_OVERFLOW=drop_newest
+        let outbox_capacity = match option_env!("OUTBOX_CAPACITY") {
+            Some(value) => match value.parse::<usize>() {
+                Ok(capacity) if capacity > 0 => capacity,
+                _ => return Err("OUTBOX_CAPACITY must be a positive number of records"),
+            },
+            None => DEFAULT_CAPACITY,
+        };
+        let outbox_policy = match option_env!("OUTBOX_OVERFLOW") {
+            Some(value) => OverflowPolicy::parse(value)
+                .ok_or("OUTBOX_OVERFLOW must be drop_oldest or drop_newest")?,
+            None => OverflowPolicy::DropOldest,
+        };
+
+        // MQTT_PROTOCOL=3.1.1 COMMAND_EXPIRY_SECS=30
+        let mqtt_protocol = match option_env!("MQTT_PROTOCOL") {
+            Some(value) => ProtocolPreference::parse(value)
+                .ok_or("MQTT_PROTOCOL must be auto, 5 or 3.1.1")?,
+            None => ProtocolPreference::Auto,
+        };
+        let command_expiry_secs = match option_env!("COMMAND_EXPIRY_SECS") {
+            Some(value) => match value.parse::<u32>() {
+                Ok(secs) if secs > 0 => secs,
+                _ => return Err("COMMAND_EXPIRY_SECS must be a positive number of seconds"),
+            },
+            None => COMMAND_EXPIRY_SECS,
+        };
+
+        // PAYLOAD_ENCODING=cbor
+        let payload_encoding = match option_env!("PAYLOAD_ENCODING") {
+            Some(value) => Encoding::parse(value).ok_or("PAYLOAD_ENCODING must be json or cbor")?,
+            None => Encoding::Json,
+        };
+
+        // BUZZER_DAILY_QUOTA=200 COMMAND_DAILY_QUOTA=2000 QUOTA_UTC_OFFSET=-300
+        let daily_quota = |value: Option<&str>, default| match value {
+            Some(value) => value.parse::<u32>().ok().filter(|quota| *quota > 0),
+            None => Some(default),
+        };
+        let quota = QuotaLimits {
+            buzzer_per_day: daily_quota(option_env!("BUZZER_DAILY_QUOTA"), DEFAULT_BUZZER_PER_DAY)
+                .ok_or("BUZZER_DAILY_QUOTA must be a positive number of beeps")?,
+            commands_per_day: daily_quota(option_env!("COMMAND_DAILY_QUOTA"), DEFAULT_COMMANDS_PER_DAY)
+                .ok_or("COMMAND_DAILY_QUOTA must be a positive number of commands")?,
+            // Minutos respecto a UTC: el día de las cuotas empieza a medianoche local
+            utc_offset_minutes: match option_env!("QUOTA_UTC_OFFSET") {
+                Some(value) => value
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|offset| (-720..=840).contains(offset))
+                    .ok_or("QUOTA_UTC_OFFSET must be minutes between -720 and 840")?,
+                None => 0,
+            },
+        };
+
+        let network = Self::network_backend()?;
+        if network == NetworkBackend::Wifi && credentials.wifi.is_none() {
+            return Err("NETWORK=wifi requires the wifi_ssid and wifi_pass credentials");
+        }
+
+        // MQTT_BROKER=192.168.1.100 o MQTT_BROKER=broker.lan:1884; sin definir se
+        // descubren por mDNS
+        let mqtt_broker = match option_env!("MQTT_BROKER") {
+            Some(value) => Some(Endpoint::parse(value).ok_or("MQTT_BROKER must be a host or host:port")?),
+            None => None,
+        };
+        let api_server = match option_env!("API_SERVER") {
+            Some(value) => Some(Endpoint::parse(value).ok_or("API_SERVER must be a host or host:port")?),
+            None => None,
+        };
+
+        Ok(SecurityConfig {
+            network,
+            wifi: credentials.wifi,
+            mqtt_username: credentials.mqtt_username,
+            mqtt_password: credentials.mqtt_password,
+            mqtt_broker,
+            api_server,
+            device_id: credentials.device_id,
+            groups,
+            peer_device_id: option_env!("PEER_DEVICE_ID").map(str::to_string),
+            max_command_rate: 60, // Máximo 60 comandos por minuto por emisor
+            outbox_capacity,
+            outbox_policy,
+            mqtt_protocol,
+            command_expiry_secs,
+            payload_encoding,
+            command_keys: credentials.command_keys,
+            quota,
+        })
+    }
+}
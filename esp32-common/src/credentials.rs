@@ -0,0 +1,259 @@
+// Credenciales del dispositivo: id, WiFi, usuario MQTT y claves de firma. No
+// se compilan en el binario: viven en la partición NVS cifrada "creds" y se
+// escriben una sola vez en el aprovisionamiento. Sin ellas el firmware no
+// arranca la red y espera a que se aprovisione por la consola serie:
+//
+//   device_id=esp32-actuator-01
+//   wifi_ssid=MiRed
+//   wifi_pass=secreto
+//   cmd_keys=telegram-bot=<hex>,esp32-actuator-01=<hex>
+//   save
+//
+// Cuáles son obligatorias depende de la compilación (Requirements): el WiFi
+// solo con NETWORK=wifi y las claves de firma en la variante segura.
+
+use core::fmt;
+
+use crate::link::NetworkBackend;
+use crate::signature::Keyring;
+use crate::topics;
+
+// Partición NVS (cifrada con las claves de "nvs_keys") y namespace
+pub const PARTITION: &str = "creds";
+pub const KEYS_PARTITION: &str = "nvs_keys";
+pub const NAMESPACE: &str = "credentials";
+
+// Claves NVS (15 caracteres como máximo)
+pub const DEVICE_ID: &str = "device_id";
+pub const WIFI_SSID: &str = "wifi_ssid";
+pub const WIFI_PASSWORD: &str = "wifi_pass";
+pub const MQTT_USERNAME: &str = "mqtt_user";
+pub const MQTT_PASSWORD: &str = "mqtt_pass";
+pub const COMMAND_KEYS: &str = "cmd_keys";
+
+pub const ALL: &[&str] = &[DEVICE_ID, WIFI_SSID, WIFI_PASSWORD, MQTT_USERNAME, MQTT_PASSWORD, COMMAND_KEYS];
+
+// Credenciales sin las que el firmware no arranca la red
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Requirements {
+    // SSID y contraseña: solo hacen falta con el backend WiFi
+    pub wifi: bool,
+    // cmd_keys con la clave del propio dispositivo: la variante segura no
+    // acepta ni envía comandos sin firma
+    pub command_keys: bool,
+}
+
+impl Requirements {
+    pub fn new(network: NetworkBackend, signed_commands: bool) -> Self {
+        Requirements { wifi: network == NetworkBackend::Wifi, command_keys: signed_commands }
+    }
+
+    pub fn required(&self) -> Vec<&'static str> {
+        ALL.iter().copied().filter(|key| self.requires(key)).collect()
+    }
+
+    pub fn optional(&self) -> Vec<&'static str> {
+        ALL.iter().copied().filter(|key| !self.requires(key)).collect()
+    }
+
+    fn requires(&self, key: &str) -> bool {
+        match key {
+            DEVICE_ID => true,
+            WIFI_SSID | WIFI_PASSWORD => self.wifi,
+            COMMAND_KEYS => self.command_keys,
+            _ => false,
+        }
+    }
+}
+
+// Longitud máxima de un valor (buffer de lectura en el firmware)
+pub const MAX_VALUE_LEN: usize = 512;
+
+// Almacén de secretos: NVS cifrada en el firmware, un mapa en los tests
+pub trait SecretStore {
+    type Error: fmt::Debug;
+
+    fn get(&self, key: &str) -> Result<Option<String>, Self::Error>;
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialError<E> {
+    // Falta una credencial obligatoria: hay que aprovisionar
+    Missing(&'static str),
+    // Una credencial guardada no es válida
+    Invalid(&'static str),
+    Storage(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for CredentialError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::Missing(key) => write!(f, "Missing credential: {}", key),
+            CredentialError::Invalid(key) => write!(f, "Invalid credential: {}", key),
+            CredentialError::Storage(e) => write!(f, "Credential storage error: {:?}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiCredentials {
+    pub ssid: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub device_id: String,
+    // None si no se aprovisionó y la red no es WiFi
+    pub wifi: Option<WifiCredentials>,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub command_keys: Keyring,
+}
+
+impl Credentials {
+    pub fn load<S: SecretStore>(store: &S, requirements: Requirements) -> Result<Self, CredentialError<S::Error>> {
+        let get = |key| store.get(key).map_err(CredentialError::Storage);
+        let require = |key| get(key)?.ok_or(CredentialError::Missing(key));
+
+        let device_id = require(DEVICE_ID)?;
+        check(DEVICE_ID, &device_id).map_err(|_| CredentialError::Invalid(DEVICE_ID))?;
+
+        let wifi = match get(WIFI_SSID)? {
+            Some(ssid) => {
+                check(WIFI_SSID, &ssid).map_err(|_| CredentialError::Invalid(WIFI_SSID))?;
+                Some(WifiCredentials { ssid, password: require(WIFI_PASSWORD)? })
+            },
+            None if requirements.wifi => return Err(CredentialError::Missing(WIFI_SSID)),
+            None => None,
+        };
+
+        let command_keys = match get(COMMAND_KEYS)? {
+            Some(value) => Keyring::parse(&value).ok_or(CredentialError::Invalid(COMMAND_KEYS))?,
+            None if requirements.command_keys => return Err(CredentialError::Missing(COMMAND_KEYS)),
+            None => Keyring::default(),
+        };
+        // Con firma obligatoria el dispositivo también firma lo que envía
+        if requirements.command_keys && command_keys.key(&device_id).is_none() {
+            return Err(CredentialError::Invalid(COMMAND_KEYS));
+        }
+
+        Ok(Credentials {
+            device_id,
+            wifi,
+            mqtt_username: get(MQTT_USERNAME)?.filter(|u| !u.is_empty()),
+            mqtt_password: get(MQTT_PASSWORD)?.filter(|p| !p.is_empty()),
+            command_keys,
+        })
+    }
+}
+
+// Valida un valor antes de guardarlo
+fn check(key: &str, value: &str) -> Result<(), String> {
+    if value.len() > MAX_VALUE_LEN {
+        return Err(format!("{} is longer than {} bytes", key, MAX_VALUE_LEN));
+    }
+    match key {
+        DEVICE_ID if !topics::is_valid_id(value) => Err("device_id cannot be used as an MQTT topic level".to_string()),
+        // Límites de la configuración WiFi del ESP-IDF
+        WIFI_SSID if value.is_empty() || value.len() > 32 => Err("wifi_ssid must be 1 to 32 bytes".to_string()),
+        WIFI_PASSWORD if value.len() > 64 => Err("wifi_pass must be at most 64 bytes".to_string()),
+        COMMAND_KEYS if Keyring::parse(value).is_none() => {
+            Err("cmd_keys must be sender=hexkey pairs with keys of at least 16 bytes".to_string())
+        },
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    // Valor aceptado, pendiente de guardar
+    Set(&'static str),
+    // Todo guardado: el firmware se reinicia con estas credenciales
+    Saved(Credentials),
+}
+
+// Aprovisionamiento línea a línea: "clave=valor" y "save" para guardar. No
+// se escribe nada en la NVS hasta que están todas las obligatorias y juntas
+// forman unas credenciales válidas.
+#[derive(Debug)]
+pub struct Provisioning {
+    requirements: Requirements,
+    values: Vec<(&'static str, String)>,
+}
+
+impl Provisioning {
+    pub fn new(requirements: Requirements) -> Self {
+        Provisioning { requirements, values: Vec::new() }
+    }
+
+    // Credenciales obligatorias que aún faltan
+    pub fn missing(&self) -> Vec<&'static str> {
+        self.requirements
+            .required()
+            .into_iter()
+            .filter(|key| !self.values.iter().any(|(k, _)| k == key))
+            .collect()
+    }
+
+    pub fn line<S: SecretStore>(&mut self, line: &str, store: &mut S) -> Result<Reply, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim() == "save" {
+            return self.save(store);
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| "Expected key=value or save".to_string())?;
+        let key = ALL
+            .iter()
+            .copied()
+            .find(|k| *k == key.trim())
+            .ok_or_else(|| format!("Unknown credential: {}", key.trim()))?;
+        // Las contraseñas pueden empezar o acabar en espacio; el resto no
+        let value = if key == WIFI_PASSWORD || key == MQTT_PASSWORD { value } else { value.trim() };
+        check(key, value)?;
+
+        self.values.retain(|(k, _)| *k != key);
+        self.values.push((key, value.to_string()));
+        Ok(Reply::Set(key))
+    }
+
+    fn save<S: SecretStore>(&mut self, store: &mut S) -> Result<Reply, String> {
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(format!("Missing credentials: {}", missing.join(", ")));
+        }
+        // Por ejemplo, cmd_keys sin la clave del propio device_id
+        Credentials::load(&Pending { values: &self.values, store }, self.requirements).map_err(|e| e.to_string())?;
+
+        for (key, value) in &self.values {
+            store
+                .set(key, value)
+                .map_err(|e| format!("Could not store {}: {:?}", key, e))?;
+        }
+        Credentials::load(store, self.requirements).map(Reply::Saved).map_err(|e| e.to_string())
+    }
+}
+
+// Lo tecleado encima de lo que ya hay guardado, para validar antes de escribir
+struct Pending<'a, S> {
+    values: &'a [(&'static str, String)],
+    store: &'a S,
+}
+
+impl<S: SecretStore> SecretStore for Pending<'_, S> {
+    type Error = S::Error;
+
+    fn get(&self, key: &str) -> Result<Option<String>, S::Error> {
+        match self.values.iter().find(|(k, _)| *k == key) {
+            Some((_, value)) => Ok(Some(value.clone())),
+            None => self.store.get(key),
+        }
+    }
+
+    fn set(&mut self, _key: &str, _value: &str) -> Result<(), S::Error> {
+        unreachable!("solo se usa para leer")
+    }
+}
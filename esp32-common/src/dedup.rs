@@ -0,0 +1,127 @@
+// Comandos recibidos hace poco, por emisor y request_id. Con QoS 1 el broker
+// puede entregar el mismo comando más de una vez (y ESP32 #1 reenvía si no le
+// llega la respuesta): un LED_TOGGLE repetido dejaría el LED al revés y un
+// BUZZER repetido gastaría la cuota de beeps. Los duplicados se contestan con
+// el resultado original sin volver a ejecutarse.
+
+use std::collections::VecDeque;
+
+use crate::command::Command;
+use crate::event::CommandOutcome;
+
+// Comandos recordados como máximo; al llenarse se olvida el más antiguo
+pub const MAX_RECENT: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Seen {
+    // Primera vez: hay que ejecutarlo
+    New,
+    // Duplicado de un comando que aún se está ejecutando; su resultado ya se
+    // publicará al terminar
+    InProgress,
+    // Duplicado de un comando terminado: se responde con este resultado (en
+    // caja, como los payloads grandes de Event)
+    Done(Box<CommandOutcome>),
+}
+
+struct Entry {
+    from: String,
+    request_id: String,
+    outcome: Option<CommandOutcome>,
+}
+
+pub struct RecentCommands {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl Default for RecentCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecentCommands {
+    pub fn new() -> Self {
+        Self::with_capacity(MAX_RECENT)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        RecentCommands {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, from: &str, request_id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.request_id == request_id && e.from == from)
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    // Consulta un comando entrante y, si es nuevo, lo marca en curso. Los
+    // comandos sin request_id no se pueden reconocer y siempre son nuevos.
+    pub fn check(&mut self, command: &Command) -> Seen {
+        let Some(request_id) = command.request_id.as_deref() else {
+            return Seen::New;
+        };
+
+        match self.position(&command.from, request_id) {
+            Some(index) => match &self.entries[index].outcome {
+                Some(outcome) => Seen::Done(Box::new(outcome.clone())),
+                None => Seen::InProgress,
+            },
+            None => {
+                self.insert(Entry {
+                    from: command.from.clone(),
+                    request_id: request_id.to_string(),
+                    outcome: None,
+                });
+                Seen::New
+            }
+        }
+    }
+
+    // Guarda el resultado que se envió al emisor
+    pub fn complete(&mut self, outcome: &CommandOutcome) {
+        let command = &outcome.command;
+        let Some(request_id) = command.request_id.as_deref() else {
+            return;
+        };
+
+        match self.position(&command.from, request_id) {
+            Some(index) => self.entries[index].outcome = Some(outcome.clone()),
+            None => self.insert(Entry {
+                from: command.from.clone(),
+                request_id: request_id.to_string(),
+                outcome: Some(outcome.clone()),
+            }),
+        }
+    }
+
+    // Olvida un comando que no llegó a ejecutarse (p. ej. cola llena) para que
+    // un reintento sí se ejecute
+    pub fn forget(&mut self, command: &Command) {
+        if let Some(request_id) = command.request_id.as_deref() {
+            if let Some(index) = self.position(&command.from, request_id) {
+                self.entries.remove(index);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Descubrimiento del broker y del servidor por mDNS/DNS-SD. Si MQTT_BROKER (o
+// API_SERVER) no se definió al compilar, el firmware pregunta en la red local
+// por estos servicios y usa el primero que responda:
+//
+//   _mqtt._tcp          broker MQTT (1883)
+//   _secure-mqtt._tcp   broker MQTT con TLS (8883)
+//   _esp32iot._tcp      servidor Rust (API REST)
+//
+// Así mover el broker a otra máquina de la LAN no obliga a reflashear. Si
+// nadie responde no hay a dónde conectar: el firmware nunca recurre a un
+// broker público, vuelve a preguntar en el siguiente intento.
+
+use std::net::Ipv4Addr;
+
+pub const MQTT_SERVICE: &str = "_mqtt";
+pub const MQTTS_SERVICE: &str = "_secure-mqtt";
+pub const API_SERVICE: &str = "_esp32iot";
+pub const PROTO: &str = "_tcp";
+
+// Tiempo de espera de cada consulta y respuestas que se leen
+pub const QUERY_TIMEOUT_MS: u64 = 3000;
+pub const MAX_RESULTS: usize = 4;
+
+// Host y, opcionalmente, puerto ("192.168.1.100" o "broker.lan:1884")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl Endpoint {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (host, port) = match value.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse::<u16>().ok().filter(|p| *p > 0)?)),
+            None => (value, None),
+        };
+        if host.is_empty() || host.contains(['/', ' ']) {
+            return None;
+        }
+        Some(Endpoint { host: host.to_string(), port })
+    }
+
+    // "mqtt://host:1883"; `default_port` si no se indicó puerto
+    pub fn url(&self, scheme: &str, default_port: u16) -> String {
+        format!("{}://{}:{}", scheme, self.host, self.port.unwrap_or(default_port))
+    }
+}
+
+impl core::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}:{}", self.host, port),
+            None => f.write_str(&self.host),
+        }
+    }
+}
+
+// Una respuesta a la consulta PTR, ya sin los tipos de esp-idf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub hostname: Option<String>,
+    pub addr: Option<Ipv4Addr>,
+    pub port: u16,
+}
+
+impl Service {
+    // Se prefiere la IP: el resolver del ESP-IDF no siempre resuelve ".local"
+    fn endpoint(&self) -> Option<Endpoint> {
+        let host = match (self.addr, &self.hostname) {
+            (Some(addr), _) => addr.to_string(),
+            (None, Some(hostname)) if !hostname.is_empty() => format!("{}.local", hostname),
+            _ => return None,
+        };
+        Some(Endpoint { host, port: Some(self.port) })
+    }
+}
+
+// Decide a qué dirección se conecta cada sesión. La dirección configurada
+// manda siempre; si no hay, se consulta mDNS y se recuerda el resultado hasta
+// que una sesión falle antes de conectar, que es cuando se vuelve a preguntar.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    configured: Option<Endpoint>,
+    discovered: Option<Endpoint>,
+}
+
+impl Resolver {
+    pub fn new(configured: Option<Endpoint>) -> Self {
+        Resolver { configured, discovered: None }
+    }
+
+    // Hay que lanzar una consulta antes de la próxima sesión
+    pub fn needs_query(&self) -> bool {
+        self.configured.is_none() && self.discovered.is_none()
+    }
+
+    // Resultado de la consulta: el primer servicio con dirección utilizable
+    pub fn discovered(&mut self, services: &[Service]) -> Option<&Endpoint> {
+        self.discovered = services.iter().find_map(Service::endpoint);
+        self.discovered.as_ref()
+    }
+
+    // La sesión no llegó a conectar: quizá el servicio cambió de máquina
+    pub fn failed(&mut self) {
+        self.discovered = None;
+    }
+
+    pub fn current(&self) -> Option<&Endpoint> {
+        self.configured.as_ref().or(self.discovered.as_ref())
+    }
+
+    pub fn is_discovered(&self) -> bool {
+        self.configured.is_none() && self.discovered.is_some()
+    }
+}
@@ -0,0 +1,32 @@
+use embedded_hal::digital::InputPin;
+
+use crate::button::Debouncer;
+use crate::clock::Clock;
+
+// Grupo de botones con pull-up (activos en bajo) y debouncing compartido
+pub struct ButtonBank<P: InputPin, C: Clock, const N: usize> {
+    buttons: [P; N],
+    clock: C,
+    debouncer: Debouncer<N>,
+}
+
+impl<P: InputPin, C: Clock, const N: usize> ButtonBank<P, C, N> {
+    pub fn new(buttons: [P; N], clock: C) -> Self {
+        ButtonBank {
+            buttons,
+            clock,
+            debouncer: Debouncer::new(),
+        }
+    }
+
+    // Devuelve el id (1..=N) del botón recién presionado
+    pub fn check_buttons(&mut self) -> Option<u8> {
+        let mut states = [false; N];
+        for (state, button) in states.iter_mut().zip(self.buttons.iter_mut()) {
+            // Un error de lectura cuenta como botón suelto
+            *state = button.is_low().unwrap_or(false);
+        }
+
+        self.debouncer.update(states, self.clock.now_ms())
+    }
+}
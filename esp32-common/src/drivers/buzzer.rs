@@ -0,0 +1,168 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::clock::Clock;
+use crate::command::{CommandError, ErrorCode};
+
+// Salida PWM a la que además se le puede cambiar la frecuencia
+// (embedded-hal solo cubre el duty cycle)
+pub trait Tone: SetDutyCycle {
+    fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuzzerError {
+    FrequencyOutOfRange,
+    DurationTooLong,
+    RateLimited,
+    PwmFrequency,
+    PwmDuty,
+}
+
+impl core::fmt::Display for BuzzerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            BuzzerError::FrequencyOutOfRange => "Frequency out of range (100-5000 Hz)",
+            BuzzerError::DurationTooLong => "Duration too long (max 5000ms)",
+            BuzzerError::RateLimited => "Buzzer rate limit exceeded",
+            BuzzerError::PwmFrequency => "Error setting PWM frequency",
+            BuzzerError::PwmDuty => "Error setting PWM duty",
+        })
+    }
+}
+
+impl CommandError for BuzzerError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            BuzzerError::FrequencyOutOfRange | BuzzerError::DurationTooLong => ErrorCode::InvalidParameter,
+            BuzzerError::RateLimited => ErrorCode::RateLimited,
+            BuzzerError::PwmFrequency | BuzzerError::PwmDuty => ErrorCode::HardwareError,
+        }
+    }
+}
+
+// Protecciones del buzzer usadas por la variante segura. La cuota diaria no
+// vive aquí sino en esp32_common::quota, que la guarda en la NVS
+#[derive(Debug, Clone, Copy)]
+pub struct BuzzerLimits {
+    pub min_interval_ms: u64,
+}
+
+impl Default for BuzzerLimits {
+    fn default() -> Self {
+        BuzzerLimits {
+            min_interval_ms: 2000, // No más de un beep cada 2 segundos
+        }
+    }
+}
+
+// Estructura para buzzer
+pub struct BuzzerController<P: Tone, D: DelayNs, C: Clock> {
+    pwm: P,
+    delay: D,
+    clock: C,
+    limits: Option<BuzzerLimits>,
+    last_beep_time: Option<u64>,
+    // Tonos que han sonado desde el arranque (la cuota diaria cobra estos)
+    tones: u32,
+}
+
+impl<P: Tone, D: DelayNs, C: Clock> BuzzerController<P, D, C> {
+    pub fn new(pwm: P, delay: D, clock: C) -> Self {
+        BuzzerController {
+            pwm,
+            delay,
+            clock,
+            limits: None,
+            last_beep_time: None,
+            tones: 0,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: BuzzerLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn beep(&mut self, frequency: u32, duration_ms: u64) -> Result<(), BuzzerError> {
+        let now_ms = self.clock.now_ms();
+        self.check_limits(now_ms)?;
+
+        // Validar parámetros
+        if !(100..=5000).contains(&frequency) {
+            return Err(BuzzerError::FrequencyOutOfRange);
+        }
+
+        if duration_ms > 5000 {
+            return Err(BuzzerError::DurationTooLong);
+        }
+
+        self.tone(frequency, duration_ms as u32)?;
+        self.record_beep(now_ms);
+        Ok(())
+    }
+
+    pub fn triple_beep(&mut self) -> Result<(), BuzzerError> {
+        // Los tres tonos cuentan como un solo beep para el intervalo mínimo
+        let now_ms = self.clock.now_ms();
+        self.check_limits(now_ms)?;
+        for _ in 0..3 {
+            self.tone(1000, 200)?;
+            self.delay.delay_ms(150);
+        }
+        self.record_beep(now_ms);
+        Ok(())
+    }
+
+    pub fn startup_sound(&mut self) -> Result<(), BuzzerError> {
+        self.tone(500, 100)?;
+        self.delay.delay_ms(50);
+        self.tone(750, 100)?;
+        self.delay.delay_ms(50);
+        self.tone(1000, 200)
+    }
+
+    pub fn emergency_beep(&mut self) -> Result<(), BuzzerError> {
+        // Beep de emergencia sin rate limiting
+        self.tone(1500, 200)
+    }
+
+    // Cada tono cuenta: un triple beep son tres
+    pub fn tones(&self) -> u32 {
+        self.tones
+    }
+
+    fn check_limits(&self, now_ms: u64) -> Result<(), BuzzerError> {
+        let Some(limits) = self.limits else {
+            return Ok(());
+        };
+
+        if let Some(last) = self.last_beep_time {
+            if now_ms.saturating_sub(last) < limits.min_interval_ms {
+                return Err(BuzzerError::RateLimited);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_beep(&mut self, now_ms: u64) {
+        self.last_beep_time = Some(now_ms);
+    }
+
+    fn tone(&mut self, frequency: u32, duration_ms: u32) -> Result<(), BuzzerError> {
+        if self.pwm.set_frequency(frequency).is_err() {
+            return Err(BuzzerError::PwmFrequency);
+        }
+        if self.pwm.set_duty_cycle_percent(50).is_err() {
+            return Err(BuzzerError::PwmDuty);
+        }
+        self.tones += 1;
+
+        self.delay.delay_ms(duration_ms);
+
+        // Apagar buzzer
+        let _ = self.pwm.set_duty_cycle_fully_off();
+        Ok(())
+    }
+}
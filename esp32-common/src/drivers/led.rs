@@ -0,0 +1,133 @@
+use embedded_hal::digital::OutputPin;
+
+use crate::clock::Clock;
+use crate::command::{CommandError, ErrorCode};
+
+pub const LED_COUNT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedError {
+    InvalidId,
+    RateLimited,
+    Hardware,
+}
+
+impl core::fmt::Display for LedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            LedError::InvalidId => "LED ID must be between 1 and 3",
+            LedError::RateLimited => "LED change rate limit exceeded",
+            LedError::Hardware => "Hardware error setting LED",
+        })
+    }
+}
+
+impl CommandError for LedError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            LedError::InvalidId => ErrorCode::InvalidParameter,
+            LedError::RateLimited => ErrorCode::RateLimited,
+            LedError::Hardware => ErrorCode::HardwareError,
+        }
+    }
+}
+
+// Estructura para LEDs controlables
+pub struct LedController<P: OutputPin, C: Clock> {
+    leds: [P; LED_COUNT],
+    clock: C,
+    states: [bool; LED_COUNT],
+    // Tiempo mínimo entre cambios de un mismo LED (None = sin límite)
+    min_change_interval_ms: Option<u64>,
+    last_change_time: [Option<u64>; LED_COUNT],
+}
+
+impl<P: OutputPin, C: Clock> LedController<P, C> {
+    pub fn new(leds: [P; LED_COUNT], clock: C) -> Self {
+        LedController {
+            leds,
+            clock,
+            states: [false; LED_COUNT],
+            min_change_interval_ms: None,
+            last_change_time: [None; LED_COUNT],
+        }
+    }
+
+    // Rate limiting: no más de un cambio cada `interval_ms` por LED
+    pub fn with_rate_limit(mut self, interval_ms: u64) -> Self {
+        self.min_change_interval_ms = Some(interval_ms);
+        self
+    }
+
+    pub fn set_led(&mut self, led_id: u8, state: bool) -> Result<(), LedError> {
+        let index = led_index(led_id)?;
+        let now_ms = self.clock.now_ms();
+
+        if let (Some(interval), Some(last)) =
+            (self.min_change_interval_ms, self.last_change_time[index])
+        {
+            if now_ms.saturating_sub(last) < interval {
+                return Err(LedError::RateLimited);
+            }
+        }
+
+        let result = if state {
+            self.leds[index].set_high()
+        } else {
+            self.leds[index].set_low()
+        };
+        if result.is_err() {
+            return Err(LedError::Hardware);
+        }
+
+        self.states[index] = state;
+        self.last_change_time[index] = Some(now_ms);
+        Ok(())
+    }
+
+    pub fn toggle_led(&mut self, led_id: u8) -> Result<bool, LedError> {
+        let index = led_index(led_id)?;
+        let new_state = !self.states[index];
+        self.set_led(led_id, new_state)?;
+        Ok(new_state)
+    }
+
+    pub fn get_state(&self, led_id: u8) -> bool {
+        match led_index(led_id) {
+            Ok(index) => self.states[index],
+            Err(_) => false,
+        }
+    }
+
+    pub fn states(&self) -> [bool; LED_COUNT] {
+        self.states
+    }
+
+    pub fn turn_off_all(&mut self) {
+        for i in 1..=LED_COUNT as u8 {
+            let _ = self.set_led(i, false);
+        }
+    }
+
+    pub fn turn_on_all(&mut self) {
+        for i in 1..=LED_COUNT as u8 {
+            let _ = self.set_led(i, true);
+        }
+    }
+
+    pub fn emergency_shutdown(&mut self) {
+        // Apagar todos los LEDs sin rate limiting en emergencia
+        for (led, state) in self.leds.iter_mut().zip(self.states.iter_mut()) {
+            let _ = led.set_low();
+            *state = false;
+        }
+        self.last_change_time = [None; LED_COUNT]; // Reset rate limiting
+    }
+}
+
+fn led_index(led_id: u8) -> Result<usize, LedError> {
+    if !(1..=LED_COUNT as u8).contains(&led_id) {
+        return Err(LedError::InvalidId);
+    }
+    Ok((led_id - 1) as usize)
+}
@@ -0,0 +1,169 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+
+// Comandos básicos del RC522
+pub const PCD_IDLE: u8 = 0x00;
+pub const PCD_TRANSCEIVE: u8 = 0x0C;
+pub const PCD_SOFT_RESET: u8 = 0x0F;
+pub const PICC_REQIDL: u8 = 0x26;
+pub const PICC_ANTICOLL: u8 = 0x93;
+pub const PICC_HALT: u8 = 0x50;
+
+// Registros del RC522
+pub const COMMAND_REG: u8 = 0x01;
+pub const COM_IRQ_REG: u8 = 0x04;
+pub const DIV_IRQ_REG: u8 = 0x05;
+pub const ERROR_REG: u8 = 0x06;
+pub const FIFO_DATA_REG: u8 = 0x09;
+pub const FIFO_LEVEL_REG: u8 = 0x0A;
+pub const BIT_FRAMING_REG: u8 = 0x0D;
+pub const MODE_REG: u8 = 0x11;
+pub const TX_CONTROL_REG: u8 = 0x14;
+pub const TX_AUTO_REG: u8 = 0x15;
+pub const T_MODE_REG: u8 = 0x2A;
+pub const T_PRESCALER_REG: u8 = 0x2B;
+pub const T_RELOAD_REG_H: u8 = 0x2C;
+pub const T_RELOAD_REG_L: u8 = 0x2D;
+
+// Iteraciones de sondeo antes de dar la transceive por perdida
+const TRANSCEIVE_TIMEOUT: u32 = 2000;
+
+// Estructura para RFID RC522
+pub struct Mfrc522<SPI: SpiDevice, RST: OutputPin, D: DelayNs> {
+    spi: SPI,
+    rst: RST,
+    delay: D,
+}
+
+impl<SPI: SpiDevice, RST: OutputPin, D: DelayNs> Mfrc522<SPI, RST, D> {
+    pub fn new(spi: SPI, rst: RST, delay: D) -> Self {
+        Mfrc522 { spi, rst, delay }
+    }
+
+    pub fn init(&mut self) {
+        // Hard reset
+        self.rst.set_low().ok();
+        self.delay.delay_ms(50);
+        self.rst.set_high().ok();
+        self.delay.delay_ms(50);
+
+        // Soft reset
+        self.write_register(COMMAND_REG, PCD_SOFT_RESET);
+        self.delay.delay_ms(50);
+
+        // Timer configuration
+        self.write_register(T_MODE_REG, 0x8D);
+        self.write_register(T_PRESCALER_REG, 0x3E);
+        self.write_register(T_RELOAD_REG_L, 30);
+        self.write_register(T_RELOAD_REG_H, 0);
+
+        // Force 100% ASK modulation
+        self.write_register(TX_AUTO_REG, 0x40);
+
+        // CRC preset value 0x6363
+        self.write_register(MODE_REG, 0x3D);
+
+        // Turn on antenna
+        self.antenna_on();
+    }
+
+    pub fn write_register(&mut self, reg: u8, value: u8) {
+        let addr = (reg << 1) & 0x7E;
+        let _ = self.spi.write(&[addr, value]);
+    }
+
+    pub fn read_register(&mut self, reg: u8) -> u8 {
+        let addr = ((reg << 1) & 0x7E) | 0x80;
+        let mut rx = [0u8; 2];
+        let tx = [addr, 0x00];
+
+        let _ = self.spi.transfer(&mut rx, &tx);
+        rx[1]
+    }
+
+    fn antenna_on(&mut self) {
+        let value = self.read_register(TX_CONTROL_REG);
+        if (value & 0x03) != 0x03 {
+            self.write_register(TX_CONTROL_REG, value | 0x03);
+        }
+    }
+
+    // Busca una tarjeta en el campo; devuelve el ATQA
+    pub fn request(&mut self) -> Option<[u8; 2]> {
+        self.write_register(BIT_FRAMING_REG, 0x07);
+        if !self.transceive(&[PICC_REQIDL], 2) {
+            return None;
+        }
+
+        let atqa1 = self.read_register(FIFO_DATA_REG);
+        let atqa2 = self.read_register(FIFO_DATA_REG);
+        Some([atqa1, atqa2])
+    }
+
+    // Anticolisión: devuelve los 4 bytes del UID más el BCC verificado
+    pub fn anticoll(&mut self) -> Option<[u8; 5]> {
+        self.write_register(BIT_FRAMING_REG, 0x00);
+        if !self.transceive(&[PICC_ANTICOLL, 0x20], 5) {
+            return None;
+        }
+
+        let mut uid = [0u8; 5];
+        for byte in uid.iter_mut() {
+            *byte = self.read_register(FIFO_DATA_REG);
+        }
+
+        let bcc = uid[0] ^ uid[1] ^ uid[2] ^ uid[3];
+        if bcc == uid[4] {
+            Some(uid)
+        } else {
+            None
+        }
+    }
+
+    pub fn halt(&mut self) {
+        self.write_register(FIFO_LEVEL_REG, 0x80);
+        self.write_register(FIFO_DATA_REG, PICC_HALT);
+        self.write_register(FIFO_DATA_REG, 0x00);
+        self.write_register(COMMAND_REG, PCD_TRANSCEIVE);
+        self.delay.delay_ms(10);
+    }
+
+    // Envía `data` a la tarjeta y espera a tener `expected` bytes en la FIFO.
+    // BIT_FRAMING_REG debe estar configurado antes de llamar.
+    fn transceive(&mut self, data: &[u8], expected: u8) -> bool {
+        self.write_register(COM_IRQ_REG, 0x7F);
+        self.write_register(DIV_IRQ_REG, 0x7F);
+        self.write_register(FIFO_LEVEL_REG, 0x80);
+
+        for &byte in data {
+            self.write_register(FIFO_DATA_REG, byte);
+        }
+        self.write_register(COMMAND_REG, PCD_TRANSCEIVE);
+
+        // StartSend
+        let val = self.read_register(BIT_FRAMING_REG);
+        self.write_register(BIT_FRAMING_REG, val | 0x80);
+
+        let mut timeout = TRANSCEIVE_TIMEOUT;
+        let fifo_level = loop {
+            let irq = self.read_register(COM_IRQ_REG);
+            let fifo_level = self.read_register(FIFO_LEVEL_REG);
+
+            timeout -= 1;
+            if timeout == 0 || (irq & 0x01) != 0 || (irq & 0x20) != 0 || fifo_level >= expected {
+                break fifo_level;
+            }
+        };
+
+        let val = self.read_register(BIT_FRAMING_REG);
+        self.write_register(BIT_FRAMING_REG, val & !0x80);
+
+        if timeout == 0 || fifo_level < expected {
+            return false;
+        }
+
+        let error = self.read_register(ERROR_REG);
+        (error & 0x1B) == 0x00
+    }
+}
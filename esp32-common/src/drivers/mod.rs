@@ -0,0 +1,12 @@
+// Drivers de los periféricos, genéricos sobre los traits de embedded-hal 1.0
+// para poder probar su lógica en el host con pines y buses simulados.
+
+pub mod button;
+pub mod buzzer;
+pub mod led;
+pub mod mfrc522;
+
+pub use button::ButtonBank;
+pub use buzzer::{BuzzerController, BuzzerError, BuzzerLimits, Tone};
+pub use led::{LedController, LedError};
+pub use mfrc522::Mfrc522;
@@ -0,0 +1,25 @@
+// Identificador de arranque: un contador en la NVS que sube en cada arranque.
+// Los timestamps de la telemetría son ms desde el arranque (esp_timer) y un
+// registro del outbox puede reenviarse después de un reinicio, así que va
+// acompañado del id del arranque en el que se produjo (message::Presence::boot)
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+
+const NAMESPACE: &str = "system";
+const KEY: &str = "boot_id";
+
+// None si la NVS no está disponible: la telemetría sale sin id de arranque
+pub fn next_boot_id(partition: EspDefaultNvsPartition) -> Option<u32> {
+    let next = EspNvs::new(partition, NAMESPACE, true).and_then(|mut nvs| {
+        let id = nvs.get_u32(KEY)?.unwrap_or(0).wrapping_add(1);
+        nvs.set_u32(KEY, id)?;
+        Ok(id)
+    });
+    match next {
+        Ok(id) => Some(id),
+        Err(e) => {
+            println!("⚠️ No se pudo guardar el id de arranque: {:?}", e);
+            None
+        }
+    }
+}
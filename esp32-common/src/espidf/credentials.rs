@@ -0,0 +1,106 @@
+// Credenciales en la partición NVS "creds" y su aprovisionamiento por la
+// consola serie (esp32_common::credentials)
+
+use esp_idf_svc::hal::delay::{FreeRtos, BLOCK};
+use esp_idf_svc::hal::uart::UartDriver;
+use esp_idf_svc::nvs::{EspNvs, EspNvsPartition};
+#[cfg(esp_idf_nvs_encryption)]
+use esp_idf_svc::nvs::{EspEncryptedNvsPartition, NvsEncrypted};
+#[cfg(not(esp_idf_nvs_encryption))]
+use esp_idf_svc::nvs::{EspCustomNvsPartition, NvsCustom};
+use esp_idf_svc::sys::EspError;
+
+use crate::credentials::{self, Provisioning, Reply, Requirements, SecretStore};
+
+// Partición "creds": cifrada con las claves de "nvs_keys" si el sdkconfig
+// activa CONFIG_NVS_ENCRYPTION (sdkconfig.production)
+#[cfg(esp_idf_nvs_encryption)]
+pub type CredentialPartition = NvsEncrypted;
+#[cfg(not(esp_idf_nvs_encryption))]
+pub type CredentialPartition = NvsCustom;
+
+pub type CredentialNvs = EspNvsPartition<CredentialPartition>;
+
+pub struct NvsSecrets(EspNvs<CredentialPartition>);
+
+impl SecretStore for NvsSecrets {
+    type Error = EspError;
+
+    fn get(&self, key: &str) -> Result<Option<String>, EspError> {
+        // +1 para el terminador nulo que guarda la NVS
+        let mut buf = [0u8; credentials::MAX_VALUE_LEN + 1];
+        Ok(self.0.get_str(key, &mut buf)?.map(str::to_string))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), EspError> {
+        self.0.set_str(key, value)
+    }
+}
+
+// Solo se puede tomar una vez; el handle se clona para abrir otros espacios
+// de nombres en la misma partición (la política de ESP32 #2)
+pub fn take_credential_partition() -> Result<CredentialNvs, EspError> {
+    #[cfg(esp_idf_nvs_encryption)]
+    let partition = EspEncryptedNvsPartition::take(credentials::PARTITION, Some(credentials::KEYS_PARTITION))?;
+    #[cfg(not(esp_idf_nvs_encryption))]
+    let partition = {
+        println!("⚠️ NVS sin cifrar: las credenciales quedan legibles en la flash (usa sdkconfig.production)");
+        EspCustomNvsPartition::take(credentials::PARTITION)?
+    };
+    Ok(partition)
+}
+
+pub fn open_credentials(partition: CredentialNvs) -> Result<NvsSecrets, EspError> {
+    Ok(NvsSecrets(EspNvs::new(partition, credentials::NAMESPACE, true)?))
+}
+
+// Sin credenciales la red no se arranca: se piden por la consola serie y el
+// dispositivo se reinicia en cuanto se guardan. Los valores no se repiten en
+// pantalla para no dejar contraseñas en el log
+pub fn provision(store: &mut NvsSecrets, requirements: Requirements, example_id: &str, uart: UartDriver<'_>) -> ! {
+    println!("🛠️  Dispositivo sin aprovisionar. Escribe clave=valor (una por línea) y después save:");
+    println!("    {}={}", credentials::DEVICE_ID, example_id);
+    println!("    Obligatorias: {}", requirements.required().join(", "));
+    println!("    Opcionales: {}", requirements.optional().join(", "));
+    if requirements.command_keys {
+        println!("    cmd_keys debe incluir la clave de este device_id");
+    }
+
+    let mut provisioning = Provisioning::new(requirements);
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        if uart.read(&mut byte, BLOCK).unwrap_or(0) == 0 {
+            continue;
+        }
+        if byte[0] != b'\n' && byte[0] != b'\r' {
+            // Una línea más larga que cualquier valor válido se descarta al validarla
+            if line.len() <= credentials::MAX_VALUE_LEN + 16 {
+                line.push(byte[0]);
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&line).into_owned();
+        line.clear();
+        match provisioning.line(&text, store) {
+            Ok(Reply::Set(key)) => {
+                let missing = provisioning.missing();
+                if missing.is_empty() {
+                    println!("✅ {} guardada; escribe save para terminar", key);
+                } else {
+                    println!("✅ {} guardada; faltan: {}", key, missing.join(", "));
+                }
+            },
+            Ok(Reply::Saved(credentials)) => {
+                println!("💾 Credenciales guardadas para {}; reiniciando...", credentials.device_id);
+                FreeRtos::delay_ms(500);
+                unsafe { esp_idf_svc::sys::esp_restart() }
+            },
+            Err(e) => println!("❌ {}", e),
+        }
+    }
+}
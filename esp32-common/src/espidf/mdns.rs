@@ -0,0 +1,69 @@
+// Descubrimiento por mDNS con el componente espressif/mdns: cada dispositivo
+// se anuncia como {device_id}.local y pregunta por el broker y el servidor
+// (esp32_common::discovery)
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use esp_idf_svc::mdns::{EspMdns, QueryResult};
+
+use crate::discovery::{self, Endpoint, Resolver, Service};
+
+// Arranca el responder con el nombre del dispositivo; sin mDNS solo sirven
+// las direcciones configuradas al compilar
+pub fn start_responder(hostname: &str) -> Option<EspMdns> {
+    match EspMdns::take() {
+        Ok(mut mdns) => {
+            if let Err(e) = mdns.set_hostname(hostname) {
+                println!("⚠️ No se pudo fijar el nombre mDNS: {:?}", e);
+            }
+            Some(mdns)
+        },
+        Err(e) => {
+            println!("⚠️ mDNS no disponible: {:?}", e);
+            None
+        },
+    }
+}
+
+// Pregunta por un servicio en la red local; sin mDNS o sin respuestas
+// devuelve una lista vacía
+pub fn query_services(mdns: Option<&EspMdns>, service: &str) -> Vec<Service> {
+    let Some(mdns) = mdns else {
+        return Vec::new();
+    };
+
+    let mut results = vec![QueryResult::default(); discovery::MAX_RESULTS];
+    let timeout = Duration::from_millis(discovery::QUERY_TIMEOUT_MS);
+    match mdns.query_ptr(service, discovery::PROTO, timeout, discovery::MAX_RESULTS, &mut results) {
+        Ok(count) => results
+            .into_iter()
+            .take(count)
+            .map(|result| Service {
+                addr: result.addr.iter().find_map(|addr| match addr {
+                    IpAddr::V4(v4) => Some(*v4),
+                    IpAddr::V6(_) => None,
+                }),
+                hostname: result.hostname,
+                port: result.port,
+            })
+            .collect(),
+        Err(e) => {
+            println!("⚠️ Consulta mDNS {}.{} fallida: {:?}", service, discovery::PROTO, e);
+            Vec::new()
+        },
+    }
+}
+
+// Pregunta por el servicio si el resolver no tiene dirección y devuelve la que
+// toca usar. None si no se configuró ninguna y nadie responde: no hay broker
+// público de reserva, así que la sesión espera al siguiente intento
+pub fn resolve(mdns: Option<&EspMdns>, resolver: &mut Resolver, service: &str) -> Option<Endpoint> {
+    if resolver.needs_query() {
+        match resolver.discovered(&query_services(mdns, service)) {
+            Some(endpoint) => println!("🔎 {}.{} encontrado por mDNS: {}", service, discovery::PROTO, endpoint),
+            None => println!("⚠️ Nadie responde por mDNS a {}.{}", service, discovery::PROTO),
+        }
+    }
+    resolver.current().cloned()
+}
@@ -0,0 +1,12 @@
+// Código de integración con ESP-IDF que comparten los dos firmwares: FFI de
+// particiones y de esp-mqtt, la red y la NVS. Solo se compila con la feature
+// "espidf" (el target del ESP32); la lógica que se prueba en el host vive en
+// el resto del crate y aquí solo se conecta con el hardware.
+
+pub mod boot;
+pub mod credentials;
+pub mod mdns;
+pub mod mqtt;
+pub mod network;
+pub mod outbox;
+pub mod signing;
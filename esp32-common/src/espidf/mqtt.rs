@@ -0,0 +1,216 @@
+// Publicación y recepción con propiedades MQTT 5 a través de la API C de
+// esp-mqtt, que esp-idf-svc no expone
+
+use std::ffi::{c_void, CStr, CString};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use esp_idf_svc::handle::RawHandle;
+use esp_idf_svc::mqtt::client::{EspMqttClient, QoS};
+use esp_idf_svc::sys::{
+    esp, esp_event_base_t, esp_mqtt5_client_delete_user_property, esp_mqtt5_client_get_user_property,
+    esp_mqtt5_client_get_user_property_count, esp_mqtt5_client_set_publish_property, esp_mqtt5_client_set_user_property,
+    esp_mqtt5_event_property_t, esp_mqtt5_publish_property_config_t, esp_mqtt5_user_property_item_t, esp_mqtt_client_register_event,
+    esp_mqtt_error_type_t_MQTT_ERROR_TYPE_CONNECTION_REFUSED, esp_mqtt_event_handle_t, esp_mqtt_event_id_t_MQTT_EVENT_DATA,
+    esp_mqtt_event_id_t_MQTT_EVENT_ERROR, free, mqtt5_user_property_handle_t, EspError, ESP_OK,
+};
+
+use crate::message::Encoding;
+use crate::event::Publication;
+use crate::link::Link;
+use crate::mqtt5::{Properties, ProtocolVersion};
+
+// Cliente MQTT de la sesión actual; None mientras no hay conexión
+pub type SharedClient = Arc<Mutex<Option<EspMqttClient<'static>>>>;
+
+// El broker olvida las suscripciones al cerrar la sesión: se repiten en cada conexión
+pub fn subscribe_all(client: &SharedClient, subscriptions: &[String]) -> bool {
+    let mut guard = client.lock().unwrap();
+    let Some(mqtt) = guard.as_mut() else {
+        return false;
+    };
+
+    for topic in subscriptions {
+        if let Err(e) = mqtt.subscribe(topic, QoS::AtLeastOnce) {
+            println!("❌ Error suscribiendo a {}: {:?}", topic, e);
+            return false;
+        }
+        println!("✅ Suscrito a {}", topic);
+    }
+    true
+}
+
+// Publica con la sesión actual; false si no hay sesión o el cliente lo rechaza.
+// Con 3.1.1 las propiedades se ignoran: el payload ya lleva from y request_id
+// y los consumidores reconocen la codificación por el primer byte
+pub fn send(client: &SharedClient, link: &Link, publication: &Publication) -> bool {
+    let mut guard = client.lock().unwrap();
+    let Some(mqtt) = guard.as_mut() else {
+        return false;
+    };
+
+    let with_properties = !publication.properties.is_empty() || publication.encoding != Encoding::Json;
+    let result = if link.protocol() == ProtocolVersion::V5 && with_properties {
+        publish_with_properties(mqtt, publication)
+    } else {
+        mqtt.publish(&publication.topic, QoS::AtLeastOnce, publication.retain, &publication.payload)
+    };
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("❌ Error publicando en {}: {:?}", publication.topic, e);
+            false
+        }
+    }
+}
+
+// Publica con propiedades MQTT 5. esp-mqtt copia solo los punteros de la
+// configuración, así que todo debe vivir hasta que termina el publish; después
+// se limpia para que el siguiente mensaje no las herede
+pub fn publish_with_properties(mqtt: &mut EspMqttClient<'static>, publication: &Publication) -> Result<u32, EspError> {
+    let properties = &publication.properties;
+    let response_topic = properties.response_topic.as_deref().and_then(|topic| CString::new(topic).ok());
+    let content_type = CString::new(publication.encoding.content_type()).ok();
+    let correlation = properties.correlation_data.as_deref().unwrap_or(&[]);
+    let pairs: Vec<(CString, CString)> = properties
+        .user_properties
+        .iter()
+        .filter_map(|(key, value)| Some((CString::new(key.as_str()).ok()?, CString::new(value.as_str()).ok()?)))
+        .collect();
+    let mut items: Vec<esp_mqtt5_user_property_item_t> = pairs
+        .iter()
+        .map(|(key, value)| esp_mqtt5_user_property_item_t { key: key.as_ptr(), value: value.as_ptr() })
+        .collect();
+
+    let mut user_property: mqtt5_user_property_handle_t = ptr::null_mut();
+    if !items.is_empty() {
+        esp!(unsafe { esp_mqtt5_client_set_user_property(&mut user_property, items.as_mut_ptr(), items.len() as u8) })?;
+    }
+
+    let config = esp_mqtt5_publish_property_config_t {
+        message_expiry_interval: properties.message_expiry_secs.unwrap_or(0),
+        response_topic: response_topic.as_ref().map_or(ptr::null(), |topic| topic.as_ptr()),
+        correlation_data: if correlation.is_empty() { ptr::null() } else { correlation.as_ptr() as *const _ },
+        correlation_data_len: correlation.len() as u16,
+        content_type: content_type.as_ref().map_or(ptr::null(), |content_type| content_type.as_ptr()),
+        user_property,
+        ..Default::default()
+    };
+
+    let result = esp!(unsafe { esp_mqtt5_client_set_publish_property(mqtt.handle(), &config) })
+        .and_then(|_| mqtt.publish(&publication.topic, QoS::AtLeastOnce, publication.retain, &publication.payload));
+
+    let cleared = esp_mqtt5_publish_property_config_t::default();
+    unsafe {
+        esp_mqtt5_client_set_publish_property(mqtt.handle(), &cleared);
+        if !user_property.is_null() {
+            esp_mqtt5_client_delete_user_property(user_property);
+        }
+    }
+    result
+}
+
+// Destino de los mensajes recibidos, con sus propiedades si la sesión es MQTT 5
+pub trait Receiver: Sync {
+    fn receive(&self, topic: Option<&str>, data: &[u8], properties: Properties);
+}
+
+// esp-idf-svc no expone las propiedades MQTT 5 de los mensajes recibidos, así
+// que los datos se leen con un handler propio sobre el evento de esp-mqtt.
+//
+// Safety: `receiver` tiene que vivir más que el cliente
+pub unsafe fn register_receiver<R: Receiver>(mqtt: &EspMqttClient<'static>, receiver: &R) -> Result<(), EspError> {
+    esp!(esp_mqtt_client_register_event(
+        mqtt.handle(),
+        esp_mqtt_event_id_t_MQTT_EVENT_DATA,
+        Some(on_mqtt_data::<R>),
+        receiver as *const R as *mut c_void,
+    ))
+}
+
+unsafe extern "C" fn on_mqtt_data<R: Receiver>(arg: *mut c_void, _base: esp_event_base_t, _id: i32, data: *mut c_void) {
+    let receiver = &*(arg as *const R);
+    let event = &*(data as esp_mqtt_event_handle_t);
+
+    // Un comando cabe siempre en un solo fragmento
+    if event.current_data_offset != 0 || event.data_len != event.total_data_len {
+        println!("⚠️ Mensaje fragmentado ignorado ({} bytes)", event.total_data_len);
+        return;
+    }
+
+    let topic = raw_bytes(event.topic as *const u8, event.topic_len).and_then(|t| core::str::from_utf8(t).ok());
+    let payload = raw_bytes(event.data as *const u8, event.data_len).unwrap_or(&[]);
+    receiver.receive(topic, payload, read_properties(event.property));
+}
+
+// Código del CONNACK con el que el broker rechazó la conexión: EventPayload::Error
+// de esp-idf-svc no lo trae, así que también se lee con un handler propio
+#[derive(Default)]
+pub struct ConnackRefusal(Mutex<Option<u8>>);
+
+impl ConnackRefusal {
+    // Lo devuelve y lo borra para la siguiente sesión
+    pub fn take(&self) -> Option<u8> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+// Safety: `refusal` tiene que vivir más que el cliente
+pub unsafe fn register_refusal(mqtt: &EspMqttClient<'static>, refusal: &ConnackRefusal) -> Result<(), EspError> {
+    esp!(esp_mqtt_client_register_event(
+        mqtt.handle(),
+        esp_mqtt_event_id_t_MQTT_EVENT_ERROR,
+        Some(on_mqtt_error),
+        refusal as *const ConnackRefusal as *mut c_void,
+    ))
+}
+
+unsafe extern "C" fn on_mqtt_error(arg: *mut c_void, _base: esp_event_base_t, _id: i32, data: *mut c_void) {
+    let refusal = &*(arg as *const ConnackRefusal);
+    let event = &*(data as esp_mqtt_event_handle_t);
+
+    let Some(error) = event.error_handle.as_ref() else {
+        return;
+    };
+    if error.error_type == esp_mqtt_error_type_t_MQTT_ERROR_TYPE_CONNECTION_REFUSED {
+        *refusal.0.lock().unwrap() = Some(error.connect_return_code as u8);
+    }
+}
+
+unsafe fn raw_bytes<'a>(ptr: *const u8, len: i32) -> Option<&'a [u8]> {
+    (!ptr.is_null() && len > 0).then(|| core::slice::from_raw_parts(ptr, len as usize))
+}
+
+unsafe fn read_properties(raw: *const esp_mqtt5_event_property_t) -> Properties {
+    let mut properties = Properties::default();
+    let Some(raw) = raw.as_ref() else {
+        return properties;
+    };
+
+    properties.response_topic = raw_bytes(raw.response_topic as *const u8, raw.response_topic_len)
+        .and_then(|topic| core::str::from_utf8(topic).ok())
+        .map(str::to_string);
+    properties.correlation_data =
+        raw_bytes(raw.correlation_data as *const u8, raw.correlation_data_len as i32).map(<[u8]>::to_vec);
+
+    if raw.user_property.is_null() {
+        return properties;
+    }
+    let mut count = esp_mqtt5_client_get_user_property_count(raw.user_property);
+    if count == 0 {
+        return properties;
+    }
+    // esp-mqtt devuelve copias de cada clave y valor que hay que liberar
+    let mut items = vec![esp_mqtt5_user_property_item_t::default(); count as usize];
+    if esp_mqtt5_client_get_user_property(raw.user_property, items.as_mut_ptr(), &mut count) == ESP_OK {
+        for item in &items[..count as usize] {
+            properties.user_properties.push((
+                CStr::from_ptr(item.key).to_string_lossy().into_owned(),
+                CStr::from_ptr(item.value).to_string_lossy().into_owned(),
+            ));
+            free(item.key as *mut c_void);
+            free(item.value as *mut c_void);
+        }
+    }
+    properties
+}
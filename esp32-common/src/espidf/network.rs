@@ -0,0 +1,100 @@
+// Interfaces de red del firmware: WiFi en la placa y OpenCores Ethernet en
+// el QEMU de Espressif (NETWORK, ver esp32_common::link::NetworkBackend)
+
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::hal::mac::MAC;
+use esp_idf_svc::hal::modem::Modem;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys::EspError;
+use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi};
+#[cfg(esp_idf_eth_use_openeth)]
+use esp_idf_svc::eth::{BlockingEth, EspEth, EthDriver, OpenEth};
+
+use crate::credentials::WifiCredentials;
+use crate::link::{Link, LinkState};
+
+// Red sobre la que va MQTT; la tarea supervisora no sabe cuál es
+pub trait Network: Send {
+    fn name(&self) -> &'static str;
+    fn is_connected(&self) -> Result<bool, EspError>;
+    // Arranca el interfaz si hace falta y espera a tener IP
+    fn connect(&mut self) -> Result<(), EspError>;
+}
+
+impl Network for BlockingWifi<EspWifi<'static>> {
+    fn name(&self) -> &'static str {
+        "WiFi"
+    }
+
+    fn is_connected(&self) -> Result<bool, EspError> {
+        BlockingWifi::is_connected(self)
+    }
+
+    fn connect(&mut self) -> Result<(), EspError> {
+        if !self.is_started()? {
+            self.start()?;
+        }
+        BlockingWifi::connect(self)?;
+        self.wait_netif_up()
+    }
+}
+
+// Con Ethernet no hay asociación: basta con arrancar y esperar al DHCP
+#[cfg(esp_idf_eth_use_openeth)]
+impl Network for BlockingEth<EspEth<'static, OpenEth>> {
+    fn name(&self) -> &'static str {
+        "Ethernet"
+    }
+
+    fn is_connected(&self) -> Result<bool, EspError> {
+        BlockingEth::is_connected(self)
+    }
+
+    fn connect(&mut self) -> Result<(), EspError> {
+        if !self.is_started()? {
+            self.start()?;
+        }
+        self.wait_netif_up()
+    }
+}
+
+// Cliente WiFi con las credenciales aprovisionadas; se asocia al conectar
+pub fn open_wifi(
+    modem: Modem,
+    sysloop: EspSystemEventLoop,
+    nvs: EspDefaultNvsPartition,
+    credentials: &WifiCredentials,
+) -> Box<dyn Network> {
+    let mut wifi = BlockingWifi::wrap(EspWifi::new(modem, sysloop.clone(), Some(nvs)).unwrap(), sysloop).unwrap();
+
+    wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+        ssid: credentials.ssid.as_str().try_into().unwrap(),
+        password: credentials.password.as_str().try_into().unwrap(),
+        ..Default::default()
+    })).unwrap();
+    Box::new(wifi)
+}
+
+// OpenCores Ethernet del QEMU de Espressif (CONFIG_ETH_USE_OPENETH)
+#[cfg(esp_idf_eth_use_openeth)]
+pub fn open_ethernet(mac: MAC, sysloop: EspSystemEventLoop) -> Box<dyn Network> {
+    let eth = EspEth::wrap(EthDriver::new_openeth(mac, sysloop.clone()).unwrap()).unwrap();
+    Box::new(BlockingEth::wrap(eth, sysloop).unwrap())
+}
+
+#[cfg(not(esp_idf_eth_use_openeth))]
+pub fn open_ethernet(_mac: MAC, _sysloop: EspSystemEventLoop) -> Box<dyn Network> {
+    panic!("NETWORK=ethernet requiere CONFIG_ETH_USE_OPENETH (compilar con sdkconfig.qemu)");
+}
+
+// Conecta la red si no lo está; cualquier error lo reintenta el supervisor
+pub fn connect_network(network: &mut dyn Network, link: &Link) -> Result<(), EspError> {
+    if network.is_connected()? {
+        return Ok(());
+    }
+
+    link.set(LinkState::WifiConnecting);
+    network.connect()?;
+    println!("✅ {} conectado", network.name());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+// Partición "outbox" de partitions.csv como memoria del outbox de telemetría
+// (esp32_common::outbox)
+
+use esp_idf_svc::sys::{
+    esp, esp_partition_erase_range, esp_partition_find_first, esp_partition_read, esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+    esp_partition_t, esp_partition_type_t_ESP_PARTITION_TYPE_DATA, esp_partition_write, EspError,
+};
+
+use crate::config::SecurityConfig;
+use crate::outbox::{Flash, Outbox};
+
+pub struct OutboxPartition(*const esp_partition_t);
+
+// Solo la usa la tarea de publicación
+unsafe impl Send for OutboxPartition {}
+
+impl OutboxPartition {
+    pub fn find() -> Option<Self> {
+        let partition = unsafe {
+            esp_partition_find_first(
+                esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+                b"outbox\0".as_ptr() as *const _,
+            )
+        };
+        (!partition.is_null()).then_some(OutboxPartition(partition))
+    }
+}
+
+impl Flash for OutboxPartition {
+    type Error = EspError;
+
+    fn size(&self) -> u32 {
+        unsafe { (*self.0).size }
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), EspError> {
+        esp!(unsafe { esp_partition_read(self.0, offset as usize, buf.as_mut_ptr() as *mut _, buf.len()) })
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), EspError> {
+        esp!(unsafe { esp_partition_write(self.0, offset as usize, data.as_ptr() as *const _, data.len()) })
+    }
+
+    fn erase(&mut self, offset: u32, len: u32) -> Result<(), EspError> {
+        esp!(unsafe { esp_partition_erase_range(self.0, offset as usize, len as usize) })
+    }
+}
+
+// Sin partición el firmware sigue funcionando, pero pierde lo que no pueda enviar
+pub fn open_outbox(config: &SecurityConfig) -> Option<Outbox<OutboxPartition>> {
+    let Some(partition) = OutboxPartition::find() else {
+        println!("⚠️ Sin partición outbox: la telemetría producida sin conexión se perderá");
+        return None;
+    };
+
+    match Outbox::open(partition, config.outbox_capacity, config.outbox_policy) {
+        Ok(outbox) => {
+            println!(
+                "💾 Outbox: {} de {} registros pendientes ({})",
+                outbox.len(),
+                outbox.capacity(),
+                outbox.policy().name()
+            );
+            Some(outbox)
+        },
+        Err(e) => {
+            println!("❌ Outbox no disponible: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,42 @@
+// Firma de los comandos que envía el dispositivo (esp32_common::signature)
+// con su propia clave de cmd_keys, la hora de SNTP y un nonce del RNG
+// hardware
+
+use crate::clock::wall_clock_ms;
+use crate::event::Publication;
+use crate::message::{decode_command, CommandMessage, Encoding};
+use crate::signature::{self, Fields};
+use crate::topics;
+
+// Nonce de 64 bits para la firma de un comando
+pub fn new_nonce() -> String {
+    let (high, low) = unsafe { (esp_idf_svc::sys::esp_random(), esp_idf_svc::sys::esp_random()) };
+    format!("{:08x}{:08x}", high, low)
+}
+
+// Codifica un comando firmado con timestamp y nonce nuevos. Sin hora
+// sincronizada no se firma ni se envía: el receptor lo rechazaría
+pub fn encode_signed(message: CommandMessage<'_>, key: &[u8], encoding: Encoding) -> Result<Publication, String> {
+    let nonce = new_nonce();
+    let mut message = message;
+    message.timestamp = Some(wall_clock_ms().ok_or("Clock not synchronized")?);
+    message.nonce = Some(&nonce);
+    let sig = signature::sign(key, &Fields::from(&message));
+    message.sig = Some(&sig);
+    Publication::encode_as(&topics::command(message.to), &message, encoding).map_err(|e| e.to_string())
+}
+
+// Un reintento es un envío nuevo para el receptor: mismo request_id, pero
+// nonce y timestamp nuevos, o lo rechazaría como repetido
+pub fn resign(publication: &Publication, key: &[u8]) -> Result<Publication, String> {
+    let command = decode_command(&publication.payload).map_err(|e| e.to_string())?;
+    let message = CommandMessage {
+        led_id: command.led_id,
+        duration: command.duration,
+        emergency: command.emergency,
+        security: command.security.as_deref(),
+        request_id: command.request_id.as_deref(),
+        ..CommandMessage::new(&command.from, &command.to, &command.command)
+    };
+    Ok(encode_signed(message, key, publication.encoding)?.with_properties(publication.properties.clone()))
+}
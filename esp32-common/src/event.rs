@@ -0,0 +1,183 @@
+// Eventos que intercambian las tareas del firmware. Cada subsistema (entrada,
+// sensado, RFID, actuación, publicación MQTT) corre en su propia tarea
+// FreeRTOS y solo se comunica con las demás a través de colas acotadas, así
+// un beep largo o una lectura del RC522 nunca bloquea a los botones.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::command::{Command, CommandError, ErrorCode};
+use crate::message::{self, CommandResponse, EncodeError, Encoding, MAX_PAYLOAD_LEN};
+use crate::mqtt5::Properties;
+use crate::shadow::DesiredUpdate;
+use crate::signature::SignatureError;
+
+// Capacidad por defecto de las colas entre tareas
+pub const EVENT_QUEUE_CAPACITY: usize = 16;
+pub const PUBLISH_QUEUE_CAPACITY: usize = 16;
+
+// Eventos que las tareas productoras envían a la tarea principal
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // Tarea de entrada: botón local presionado (ya filtrado por el debounce)
+    ButtonPressed { button_id: u8, timestamp_ms: u64 },
+    // Tarea de sensado: lectura válida del LM35 en °C
+    Temperature { celsius: f32, timestamp_ms: u64 },
+    // Tarea RFID: tarjeta detectada, con el UID devuelto por el anticollision
+    CardDetected { uid: [u8; 4], timestamp_ms: u64 },
+    // Tarea MQTT: comando dirigido a este dispositivo con parámetros válidos
+    CommandReceived(Command),
+    // Tarea de actuación: estado de los LEDs después de ejecutar un comando
+    LedStates([bool; 3]),
+    // Cualquier tarea: un comando terminó (ejecutado o rechazado) y hay que
+    // responder al emisor
+    CommandDone(CommandOutcome),
+    // Tarea MQTT: respuesta a un comando que envió este dispositivo
+    ResponseReceived(CommandResponse),
+    // Tarea MQTT: sesión con el broker (re)establecida
+    MqttConnected,
+    // Tarea MQTT: comando descartado al verificar su firma
+    CommandRejected { command: Command, reason: SignatureError },
+    // Tarea MQTT: el mismo mensaje firmado otra vez (redelivery de QoS 1); no
+    // se ejecuta, se contesta con el resultado original si aún se recuerda
+    CommandRedelivered(Command),
+    // Tarea MQTT: desired de la sombra (al suscribirse o cuando lo cambia un
+    // operador), aún sin comprobar la firma
+    DesiredState(Box<DesiredUpdate>),
+    // Tarea MQTT: documento de política tal como llegó; se decodifica y
+    // comprueba en el bucle principal y se guarda así en la NVS si se acepta
+    PolicyReceived(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandOutcome {
+    pub command: Command,
+    pub result: Result<String, (ErrorCode, String)>,
+    // Tonos del buzzer que sonaron al ejecutarlo
+    pub beeps: u32,
+}
+
+impl CommandOutcome {
+    pub fn ok(command: Command, message: String) -> Self {
+        CommandOutcome { command, result: Ok(message), beeps: 0 }
+    }
+
+    pub fn failed(command: Command, error: &impl CommandError) -> Self {
+        CommandOutcome::with_code(command, error.code(), &error.to_string())
+    }
+
+    pub fn with_code(command: Command, code: ErrorCode, error: &str) -> Self {
+        CommandOutcome {
+            command,
+            result: Err((code, error.to_string())),
+            beeps: 0,
+        }
+    }
+}
+
+// Mensaje listo para que la tarea de publicación lo envíe al broker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Publication {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+    pub durable: bool,
+    // Solo se envían si la sesión es MQTT 5
+    pub properties: Properties,
+    // Con MQTT 5 viaja como content-type
+    pub encoding: Encoding,
+}
+
+impl Publication {
+    pub fn new(topic: &str, payload: &[u8]) -> Self {
+        Publication {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            retain: false,
+            durable: false,
+            properties: Properties::default(),
+            encoding: Encoding::Json,
+        }
+    }
+
+    // El broker guarda el último mensaje retenido del tópico y se lo entrega a
+    // cada nuevo suscriptor
+    pub fn retained(mut self) -> Self {
+        self.retain = true;
+        self
+    }
+
+    // Telemetría que no debe perderse: sin conexión se guarda en el outbox
+    // de flash y se reenvía al reconectar
+    pub fn durable(mut self) -> Self {
+        self.durable = true;
+        self
+    }
+
+    pub fn with_properties(mut self, properties: Properties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    pub fn encode<T: Serialize>(topic: &str, message: &T) -> Result<Self, EncodeError> {
+        Publication::encode_as(topic, message, Encoding::Json)
+    }
+
+    pub fn encode_as<T: Serialize>(topic: &str, message: &T, encoding: Encoding) -> Result<Self, EncodeError> {
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        let len = message::encode_as(message, encoding, &mut buf)?;
+        let mut publication = Publication::new(topic, &buf[..len]);
+        publication.encoding = encoding;
+        Ok(publication)
+    }
+}
+
+// Extremo emisor de una cola acotada. Nunca bloquea: si la cola está llena el
+// mensaje se descarta y se cuenta, para que un consumidor lento no frene a la
+// tarea que produce.
+pub struct Sender<T> {
+    tx: SyncSender<T>,
+    dropped: Arc<AtomicU32>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            tx: self.tx.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Devuelve false si el mensaje no se pudo encolar
+    pub fn post(&self, message: T) -> bool {
+        match self.tx.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    // Mensajes descartados por cola llena desde el arranque
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::sync_channel(capacity);
+    (
+        Sender {
+            tx,
+            dropped: Arc::new(AtomicU32::new(0)),
+        },
+        rx,
+    )
+}
@@ -0,0 +1,48 @@
+// Código compartido entre los firmwares de esp32-device-1 y esp32-device-2.
+// Todo lo que define el formato de los mensajes MQTT vive aquí para que los
+// dos dispositivos no puedan divergir.
+
+pub mod actuator;
+pub mod button;
+pub mod cert;
+pub mod clock;
+pub mod command;
+pub mod config;
+pub mod credentials;
+pub mod dedup;
+pub mod discovery;
+pub mod drivers;
+#[cfg(feature = "espidf")]
+pub mod espidf;
+pub mod event;
+pub mod link;
+pub mod message;
+pub mod mqtt5;
+pub mod outbox;
+pub mod pending;
+pub mod policy;
+pub mod quota;
+pub mod ratelimit;
+pub mod shadow;
+pub mod signature;
+pub mod system;
+pub mod task;
+pub mod topics;
+pub mod validator;
+
+pub use actuator::Actuator;
+pub use button::Debouncer;
+pub use clock::{Clock, ManualClock};
+#[cfg(target_os = "espidf")]
+pub use clock::EspTimerClock;
+pub use command::{Command, CommandError, ErrorCode, RequestIds};
+pub use config::SecurityConfig;
+pub use credentials::Credentials;
+pub use dedup::{RecentCommands, Seen};
+pub use event::{CommandOutcome, Event, Publication};
+pub use message::{DecodeError, EncodeError, Encoding, SCHEMA_VERSION};
+pub use pending::{PendingAction, PendingCommands};
+pub use policy::Policy;
+pub use shadow::Shadow;
+pub use task::spawn_task;
+pub use validator::{CommandValidator, Rejection};
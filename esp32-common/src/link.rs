@@ -0,0 +1,188 @@
+// Estado de la conexión red (WiFi o Ethernet) + MQTT. La tarea supervisora es la única que lo
+// cambia; el resto del firmware lo consulta (p. ej. para no intentar publicar
+// sin sesión) y el heartbeat y la presencia reportan los contadores.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::mqtt5::ProtocolVersion;
+
+// Primera espera tras un fallo y espera máxima entre intentos
+pub const BACKOFF_BASE_MS: u64 = 1000;
+pub const BACKOFF_MAX_MS: u64 = 60_000;
+
+// Interfaz de red sobre la que va MQTT (NETWORK=wifi|ethernet)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkBackend {
+    Wifi,
+    // OpenCores Ethernet: la única red que emula el QEMU de Espressif
+    Ethernet,
+}
+
+impl NetworkBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "wifi" => Some(NetworkBackend::Wifi),
+            "ethernet" => Some(NetworkBackend::Ethernet),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NetworkBackend::Wifi => "WiFi",
+            NetworkBackend::Ethernet => "Ethernet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    // Sin red, esperando el siguiente intento
+    Down,
+    // Conectando el WiFi o esperando IP por Ethernet
+    WifiConnecting,
+    // Red arriba, sesión MQTT en curso de establecerse
+    MqttConnecting,
+    // Sesión MQTT activa y suscripciones hechas
+    Connected,
+}
+
+impl LinkState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => LinkState::WifiConnecting,
+            2 => LinkState::MqttConnecting,
+            3 => LinkState::Connected,
+            _ => LinkState::Down,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            LinkState::Down => 0,
+            LinkState::WifiConnecting => 1,
+            LinkState::MqttConnecting => 2,
+            LinkState::Connected => 3,
+        }
+    }
+}
+
+// Compartido entre tareas; clonar da otra vista del mismo estado
+#[derive(Debug, Clone, Default)]
+pub struct Link {
+    state: Arc<AtomicU8>,
+    sessions: Arc<AtomicU32>,
+    failures: Arc<AtomicU32>,
+    v311: Arc<AtomicBool>,
+}
+
+impl Link {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> LinkState {
+        LinkState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, state: LinkState) {
+        self.state.store(state.as_u8(), Ordering::Relaxed);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == LinkState::Connected
+    }
+
+    // Sesión MQTT establecida
+    pub fn connected(&self) {
+        self.sessions.fetch_add(1, Ordering::Relaxed);
+        self.set(LinkState::Connected);
+    }
+
+    // Intento fallido o sesión perdida
+    pub fn failed(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        self.set(LinkState::Down);
+    }
+
+    // Sesiones establecidas después de la primera
+    pub fn reconnects(&self) -> u32 {
+        self.sessions.load(Ordering::Relaxed).saturating_sub(1)
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    // Versión de MQTT de la sesión actual (o de la próxima, si no hay sesión)
+    pub fn protocol(&self) -> ProtocolVersion {
+        if self.v311.load(Ordering::Relaxed) {
+            ProtocolVersion::V311
+        } else {
+            ProtocolVersion::V5
+        }
+    }
+
+    pub fn set_protocol(&self, protocol: ProtocolVersion) {
+        self.v311.store(protocol == ProtocolVersion::V311, Ordering::Relaxed);
+    }
+}
+
+// Espera exponencial con jitter: tras el intento n la espera es un valor al
+// azar entre la mitad y el total de min(base * 2^n, max). El jitter evita que
+// todos los dispositivos reconecten a la vez cuando vuelve el broker.
+pub struct Backoff {
+    base_ms: u64,
+    max_ms: u64,
+    attempt: u32,
+    rng: u32,
+}
+
+impl Backoff {
+    // `seed` debe variar entre dispositivos (esp_random() en el firmware)
+    pub fn new(seed: u32) -> Self {
+        Backoff {
+            base_ms: BACKOFF_BASE_MS,
+            max_ms: BACKOFF_MAX_MS,
+            attempt: 0,
+            rng: seed.max(1),
+        }
+    }
+
+    pub fn with_limits(mut self, base_ms: u64, max_ms: u64) -> Self {
+        self.base_ms = base_ms.max(1);
+        self.max_ms = max_ms.max(self.base_ms);
+        self
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    // Conexión lograda: el próximo fallo vuelve a empezar desde base
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn next_delay_ms(&mut self) -> u64 {
+        let ceiling = self
+            .base_ms
+            .saturating_mul(1u64 << self.attempt.min(32))
+            .min(self.max_ms);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = ceiling / 2;
+        half + self.random() as u64 % (ceiling - half + 1)
+    }
+
+    // xorshift32: suficiente para repartir esperas, no para criptografía
+    fn random(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+}
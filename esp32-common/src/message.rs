@@ -0,0 +1,675 @@
+// Esquema de los mensajes MQTT. Todos los payloads llevan "v" con la versión
+// del esquema; los consumidores ignoran los campos que no conocen, así que
+// añadir un campo opcional no requiere subir la versión.
+//
+// Los payloads van en JSON o, si el dispositivo se configura así, en CBOR con
+// los mismos campos. Los decodificadores aceptan los dos sin que nadie se lo
+// diga: un objeto JSON empieza por '{' y un mapa CBOR por un byte 0xA0-0xBF.
+
+use core::fmt;
+
+use heapless::String as HString;
+use serde::{Deserialize, Serialize};
+
+use crate::command::{Command, ErrorCode};
+use crate::drivers::led::LED_COUNT;
+use crate::event::CommandOutcome;
+use crate::shadow::{DesiredUpdate, LedMask};
+use crate::policy::{Policy, PolicyUpdate, Rule, MAX_COMMAND_LEN, MAX_POLICY_LEN, MAX_RULES, MAX_RULE_COMMANDS};
+use crate::signature::{Signed, SIGNATURE_HEX_LEN};
+
+pub const SCHEMA_VERSION: u8 = 1;
+
+// Tamaño máximo de un payload codificado: cabe un comando firmado con todos
+// los campos y cada identificador de MAX_ID_LEN (536 bytes en JSON)
+pub const MAX_PAYLOAD_LEN: usize = 576;
+// Longitud máxima de un identificador de dispositivo o de origen
+pub const MAX_ID_LEN: usize = 48;
+// Longitud máxima del texto de un resultado
+pub const MAX_MESSAGE_LEN: usize = 96;
+// Buffer para desescapar strings al decodificar
+const UNESCAPE_BUFFER_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    NotUtf8,
+    Json(serde_json_core::de::Error),
+    Cbor(serde_cbor::error::Category),
+    // Ni objeto JSON ni mapa CBOR
+    UnknownEncoding,
+    UnsupportedVersion(u8),
+    // Más largo de lo que admite ese tipo de mensaje
+    TooLarge,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::NotUtf8 => write!(f, "Payload is not valid UTF-8"),
+            DecodeError::Json(e) => write!(f, "Invalid JSON payload: {}", e),
+            DecodeError::Cbor(category) => write!(f, "Invalid CBOR payload ({:?})", category),
+            DecodeError::UnknownEncoding => write!(f, "Payload is neither a JSON object nor a CBOR map"),
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported schema version {} (max {})", v, SCHEMA_VERSION)
+            },
+            DecodeError::TooLarge => write!(f, "Payload too large"),
+        }
+    }
+}
+
+impl From<serde_json_core::de::Error> for DecodeError {
+    fn from(e: serde_json_core::de::Error) -> Self {
+        DecodeError::Json(e)
+    }
+}
+
+impl From<serde_cbor::Error> for DecodeError {
+    fn from(e: serde_cbor::Error) -> Self {
+        DecodeError::Cbor(e.classify())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodeError {
+    BufferFull,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::BufferFull => write!(f, "Encoded message exceeds buffer size"),
+        }
+    }
+}
+
+// PAYLOAD_ENCODING=json|cbor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    // Números en binario y sin comillas ni separadores: bastante menos
+    // airtime y menos sitio en los buffers y en el outbox
+    Cbor,
+}
+
+impl Encoding {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(Encoding::Json),
+            "cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Cbor => "cbor",
+        }
+    }
+
+    // Propiedad content-type de MQTT 5
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn detect(payload: &[u8]) -> Option<Self> {
+        match payload.iter().find(|b| !b.is_ascii_whitespace())? {
+            b'{' => Some(Encoding::Json),
+            0xA0..=0xBF => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode<T: Serialize>(message: &T, buf: &mut [u8]) -> Result<usize, EncodeError> {
+    encode_as(message, Encoding::Json, buf)
+}
+
+pub fn encode_as<T: Serialize>(message: &T, encoding: Encoding, buf: &mut [u8]) -> Result<usize, EncodeError> {
+    match encoding {
+        Encoding::Json => serde_json_core::to_slice(message, buf).map_err(|_| EncodeError::BufferFull),
+        Encoding::Cbor => {
+            let mut serializer = serde_cbor::Serializer::new(serde_cbor::ser::SliceWrite::new(buf));
+            message.serialize(&mut serializer).map_err(|_| EncodeError::BufferFull)?;
+            Ok(serializer.into_inner().bytes_written())
+        }
+    }
+}
+
+// Decodifica un payload en cualquiera de las dos codificaciones. `scratch`
+// sirve para desescapar strings JSON o reunir strings CBOR por trozos
+fn decode_with<'a, T: Deserialize<'a>>(payload: &'a [u8], scratch: &mut [u8]) -> Result<T, DecodeError> {
+    match Encoding::detect(payload) {
+        Some(Encoding::Json) => {
+            let json = core::str::from_utf8(payload).map_err(|_| DecodeError::NotUtf8)?;
+            Ok(serde_json_core::from_str_escaped::<T>(json, scratch)?.0)
+        },
+        Some(Encoding::Cbor) => Ok(serde_cbor::de::from_slice_with_scratch(payload, scratch)?),
+        None if core::str::from_utf8(payload).is_err() => Err(DecodeError::NotUtf8),
+        None => Err(DecodeError::UnknownEncoding),
+    }
+}
+
+// Para los consumidores (servidor, herramientas): cualquier mensaje del
+// esquema en JSON o CBOR. Los strings se toman prestados del payload
+pub fn decode<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<T, DecodeError> {
+    let mut scratch = [0u8; UNESCAPE_BUFFER_LEN];
+    decode_with(payload, &mut scratch)
+}
+
+// Los productores anteriores al esquema versionado no envían "v"; se tratan
+// como versión 1
+fn check_version(v: Option<u8>) -> Result<(), DecodeError> {
+    match v {
+        Some(v) if v > SCHEMA_VERSION => Err(DecodeError::UnsupportedVersion(v)),
+        _ => Ok(()),
+    }
+}
+
+// esp32/{device_id}/telemetry/button
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonEvent<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub button_id: u8,
+    pub action: &'a str,
+    // Ver Presence::boot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<u32>,
+    pub timestamp: u64,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub security: Option<&'a str>,
+}
+
+// esp32/{device_id}/telemetry/temperature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureReading<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub temp: f32,
+    pub hum: f32,
+    // Ver Presence::boot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<u32>,
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validated: Option<bool>,
+}
+
+// esp32/{device_id}/telemetry/rfid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RfidEvent<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub uid: &'a str,
+    pub count: u32,
+    // Ver Presence::boot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<u32>,
+    pub timestamp: u64,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub security: Option<&'a str>,
+}
+
+// UID en hexadecimal tal como lo espera el servidor ("A1B2C3D4")
+pub fn uid_hex(uid: &[u8; 4]) -> HString<8> {
+    use core::fmt::Write;
+
+    let mut hex = HString::new();
+    for byte in uid {
+        // 4 bytes siempre caben en 8 caracteres
+        let _ = write!(hex, "{:02X}", byte);
+    }
+    hex
+}
+
+// esp32/{device_id}/presence: retenido. "online" lo publica el dispositivo al
+// conectar; "offline" lo publica el broker como Last Will si la conexión se
+// pierde sin desconectar limpiamente
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Presence<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub status: &'a str,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<&'a str>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub boot_reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconnects: Option<u32>,
+    // Contador de arranques guardado en la NVS (esp32_common::espidf::boot).
+    // Los timestamps de la telemetría son ms desde el arranque, así que la
+    // que se reenvía desde el outbox tras un reinicio lleva el id del
+    // arranque en el que se produjo para no confundirse con la del actual
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<u32>,
+}
+
+impl<'a> Presence<'a> {
+    pub fn online(device: &'a str, firmware: &'a str, boot_reason: &'a str) -> Self {
+        Presence {
+            v: SCHEMA_VERSION,
+            device,
+            status: "online",
+            firmware: Some(firmware),
+            boot_reason: Some(boot_reason),
+            reconnects: None,
+            boot: None,
+        }
+    }
+
+    pub fn offline(device: &'a str) -> Self {
+        Presence {
+            v: SCHEMA_VERSION,
+            device,
+            status: "offline",
+            firmware: None,
+            boot_reason: None,
+            reconnects: None,
+            boot: None,
+        }
+    }
+}
+
+// esp32/{device_id}/heartbeat: informe de salud periódico; la presencia la da
+// esp32/{device_id}/presence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub status: &'a str,
+    pub uptime: u64,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub security: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commands_processed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_events: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconnects: Option<u32>,
+    // Telemetría guardada en flash esperando conexión, perdida y política
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outbox_pending: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outbox_dropped: Option<u32>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub outbox_policy: Option<&'a str>,
+    // Versión de la política de autorización activa (0: la de fábrica)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_version: Option<u32>,
+}
+
+// esp32/{device_id}/state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedStatus<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub led1: bool,
+    pub led2: bool,
+    pub led3: bool,
+    pub timestamp: u64,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub security: Option<&'a str>,
+}
+
+// esp32/{device_id}/cmd (lo que publican los dispositivos)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandMessage<'a> {
+    pub v: u8,
+    pub from: &'a str,
+    pub to: &'a str,
+    pub command: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub led_id: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emergency: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub security: Option<&'a str>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<&'a str>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<&'a str>,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<&'a str>,
+}
+
+impl<'a> CommandMessage<'a> {
+    pub fn new(from: &'a str, to: &'a str, command: &'a str) -> Self {
+        CommandMessage {
+            v: SCHEMA_VERSION,
+            from,
+            to,
+            command,
+            led_id: None,
+            duration: None,
+            emergency: None,
+            timestamp: None,
+            security: None,
+            request_id: None,
+            nonce: None,
+            sig: None,
+        }
+    }
+}
+
+// esp32/{emisor}/responses: resultado de cada comando, dirigido a quien lo envió
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandResult<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub to: &'a str,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<&'a str>,
+    pub command: &'a str,
+    pub status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    pub message: &'a str,
+    pub leds: [bool; 3],
+    pub timestamp: u64,
+}
+
+impl<'a> CommandResult<'a> {
+    pub fn new(
+        device: &'a str,
+        outcome: &'a CommandOutcome,
+        leds: [bool; 3],
+        timestamp: u64,
+    ) -> Self {
+        let command = &outcome.command;
+        let (status, code, message) = match &outcome.result {
+            Ok(message) => ("ok", None, message.as_str()),
+            Err((code, message)) => ("error", Some(*code), message.as_str()),
+        };
+
+        CommandResult {
+            v: SCHEMA_VERSION,
+            device,
+            to: &command.from,
+            request_id: command.request_id.as_deref(),
+            command: &command.command,
+            status,
+            code,
+            message,
+            leds,
+            timestamp,
+        }
+    }
+}
+
+// esp32/{device_id}/responses visto desde quien envió el comando
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandResponse {
+    pub device: String,
+    pub to: String,
+    pub request_id: Option<String>,
+    pub command: String,
+    pub ok: bool,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct ResponseWire {
+    v: Option<u8>,
+    device: HString<MAX_ID_LEN>,
+    to: HString<MAX_ID_LEN>,
+    request_id: Option<HString<MAX_ID_LEN>>,
+    command: HString<MAX_ID_LEN>,
+    status: HString<8>,
+    message: HString<MAX_MESSAGE_LEN>,
+}
+
+pub fn decode_response(payload: &[u8]) -> Result<CommandResponse, DecodeError> {
+    let mut scratch = [0u8; UNESCAPE_BUFFER_LEN];
+    let wire: ResponseWire = decode_with(payload, &mut scratch)?;
+    check_version(wire.v)?;
+
+    Ok(CommandResponse {
+        device: wire.device.as_str().into(),
+        to: wire.to.as_str().into(),
+        request_id: wire.request_id.map(|s| s.as_str().into()),
+        command: wire.command.as_str().into(),
+        ok: wire.status == "ok",
+        message: wire.message.as_str().into(),
+    })
+}
+
+// esp32/{device_id}/shadow/desired: lo publican los operadores, retenido y
+// firmado (ver esp32_common::shadow). null en un LED es "sin preferencia"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShadowDesired<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<u8>,
+    pub version: u32,
+    pub from: &'a str,
+    pub sig: &'a str,
+    pub leds: LedMask,
+}
+
+#[derive(Deserialize)]
+struct DesiredWire {
+    v: Option<u8>,
+    version: u32,
+    from: HString<MAX_ID_LEN>,
+    sig: HString<SIGNATURE_HEX_LEN>,
+    leds: LedMask,
+}
+
+// None si el payload está vacío: se borró el retenido, pero el desired
+// aplicado se mantiene hasta que llegue otro documento firmado
+pub fn decode_desired(payload: &[u8]) -> Result<Option<DesiredUpdate>, DecodeError> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    let mut scratch = [0u8; UNESCAPE_BUFFER_LEN];
+    let wire: DesiredWire = decode_with(payload, &mut scratch)?;
+    check_version(wire.v)?;
+    Ok(Some(DesiredUpdate {
+        version: wire.version,
+        from: wire.from.as_str().into(),
+        signature: wire.sig.as_str().into(),
+        leds: wire.leds,
+    }))
+}
+
+// esp32/{device_id}/shadow/reported: estado real, retenido
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShadowReported<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub leds: [bool; LED_COUNT],
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/shadow/delta: valor deseado de los LEDs que no coinciden
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShadowDelta<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub leds: LedMask,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/events/delivery_failed: un comando no obtuvo respuesta tras todos los intentos
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryFailed<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub to: &'a str,
+    pub request_id: &'a str,
+    pub command: &'a str,
+    pub attempts: u8,
+    // Ver Presence::boot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<u32>,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/events/command_rejected: comando descartado por su firma,
+// su timestamp o un nonce repetido. No se responde al emisor: su `from` no
+// está verificado.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRejected<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub from: &'a str,
+    pub command: &'a str,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<&'a str>,
+    pub reason: &'a str,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/events/rate_limited: comando rechazado por falta de
+// tokens, con lo que queda de cada presupuesto y cuándo habrá uno nuevo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitExceeded<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub from: &'a str,
+    pub command: &'a str,
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<&'a str>,
+    // "source" o la clase del comando: led, buzzer, acknowledge, emergency
+    pub limit: &'a str,
+    pub class: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_remaining: Option<u32>,
+    pub class_remaining: u32,
+    pub retry_after_ms: u64,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/quota: lo que queda de las cuotas diarias, retenido. Se
+// publica al pedirlo con QUOTA_STATUS y al empezar un día nuevo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaReport<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub buzzer_remaining: u32,
+    pub buzzer_limit: u32,
+    pub commands_remaining: u32,
+    pub commands_limit: u32,
+    // Ausente mientras el reloj no esté sincronizado
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resets_in_ms: Option<u64>,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/events/policy_rejected: documento de política descartado
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRejected<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub from: &'a str,
+    pub version: u32,
+    pub reason: &'a str,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/events/desired_rejected: estado deseado descartado
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DesiredRejected<'a> {
+    pub v: u8,
+    pub device: &'a str,
+    pub from: &'a str,
+    pub version: u32,
+    pub reason: &'a str,
+    pub timestamp: u64,
+}
+
+// esp32/{device_id}/config/policy (ver esp32_common::policy)
+#[derive(Deserialize)]
+struct RuleWire {
+    source: HString<MAX_ID_LEN>,
+    commands: heapless::Vec<HString<MAX_COMMAND_LEN>, MAX_RULE_COMMANDS>,
+    leds: Option<heapless::Vec<u8, LED_COUNT>>,
+    max_duration: Option<u64>,
+    rate: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct PolicyWire {
+    v: Option<u8>,
+    version: u32,
+    from: HString<MAX_ID_LEN>,
+    sig: HString<SIGNATURE_HEX_LEN>,
+    rules: heapless::Vec<RuleWire, MAX_RULES>,
+}
+
+pub fn decode_policy(payload: &[u8]) -> Result<PolicyUpdate, DecodeError> {
+    if payload.len() > MAX_POLICY_LEN {
+        return Err(DecodeError::TooLarge);
+    }
+    let mut scratch = [0u8; UNESCAPE_BUFFER_LEN];
+    let wire: PolicyWire = decode_with(payload, &mut scratch)?;
+    check_version(wire.v)?;
+
+    let rules = wire
+        .rules
+        .iter()
+        .map(|rule| Rule {
+            source: rule.source.as_str().into(),
+            commands: rule.commands.iter().map(|c| c.as_str().into()).collect(),
+            leds: rule.leds.as_ref().map(|leds| leds.to_vec()),
+            max_duration: rule.max_duration,
+            rate: rule.rate,
+        })
+        .collect();
+    Ok(PolicyUpdate {
+        from: wire.from.as_str().into(),
+        signature: wire.sig.as_str().into(),
+        policy: Policy { version: wire.version, rules },
+    })
+}
+
+// esp32/{device_id}/cmd (lo que reciben los dispositivos)
+#[derive(Deserialize)]
+struct CommandWire {
+    v: Option<u8>,
+    from: HString<MAX_ID_LEN>,
+    to: HString<MAX_ID_LEN>,
+    command: HString<MAX_ID_LEN>,
+    led_id: Option<u8>,
+    duration: Option<u64>,
+    emergency: Option<bool>,
+    security: Option<HString<MAX_ID_LEN>>,
+    request_id: Option<HString<MAX_ID_LEN>>,
+    timestamp: Option<u64>,
+    nonce: Option<HString<MAX_ID_LEN>>,
+    sig: Option<HString<SIGNATURE_HEX_LEN>>,
+}
+
+pub fn decode_command(payload: &[u8]) -> Result<Command, DecodeError> {
+    let mut scratch = [0u8; UNESCAPE_BUFFER_LEN];
+    let wire: CommandWire = decode_with(payload, &mut scratch)?;
+    check_version(wire.v)?;
+
+    Ok(Command {
+        from: wire.from.as_str().into(),
+        to: wire.to.as_str().into(),
+        command: wire.command.as_str().into(),
+        led_id: wire.led_id,
+        duration: wire.duration,
+        emergency: wire.emergency,
+        security: wire.security.map(|s| s.as_str().into()),
+        request_id: wire.request_id.map(|s| s.as_str().into()),
+        signed: match (wire.timestamp, wire.nonce, wire.sig) {
+            (Some(timestamp), Some(nonce), Some(sig)) => Some(Box::new(Signed {
+                timestamp,
+                nonce: nonce.as_str().into(),
+                signature: sig.as_str().into(),
+            })),
+            _ => None,
+        },
+        reply_to: None,
+        quota: None,
+    })
+}
@@ -0,0 +1,197 @@
+// Semántica de petición/respuesta de MQTT 5 para los comandos:
+//
+//   - response topic + correlation data: quien envía un comando dice dónde
+//     quiere la respuesta y con qué dato la reconocerá
+//   - message expiry: el broker descarta el comando si nadie lo recoge a
+//     tiempo, así un BUZZER encolado hace una hora no suena al reconectar
+//   - user property "sender": identidad del emisor fuera del payload
+//
+// Si el broker solo habla 3.1.1 el firmware cae a ese protocolo y todo vuelve
+// a ir dentro del JSON (`from`, `request_id`, esp32/{from}/responses).
+
+use crate::topics;
+
+// Vida de un comando en el broker si el emisor no indica otra
+pub const COMMAND_EXPIRY_SECS: u32 = 60;
+
+// User property con el id del emisor
+pub const SENDER_PROPERTY: &str = "sender";
+
+// Conexiones rechazadas con MQTT 5 antes de probar con 3.1.1
+pub const V5_ATTEMPTS_BEFORE_FALLBACK: u32 = 2;
+
+// Códigos de CONNACK con los que el broker dice que no habla MQTT 5: 0x84
+// (Unsupported Protocol Version) en un broker MQTT 5 y 0x01 (unacceptable
+// protocol version) en uno 3.1.1 que contesta con su propio CONNACK
+pub const UNSUPPORTED_PROTOCOL_CODES: [u8; 2] = [0x84, 0x01];
+
+// Límite del firmware para la correlation data recibida
+const MAX_CORRELATION_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    V5,
+    V311,
+}
+
+impl ProtocolVersion {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProtocolVersion::V5 => "5",
+            ProtocolVersion::V311 => "3.1.1",
+        }
+    }
+}
+
+// MQTT_PROTOCOL=auto|5|3.1.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolPreference {
+    // MQTT 5 y, si el broker lo rechaza, 3.1.1
+    Auto,
+    Only(ProtocolVersion),
+}
+
+impl ProtocolPreference {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ProtocolPreference::Auto),
+            "5" => Some(ProtocolPreference::Only(ProtocolVersion::V5)),
+            "3.1.1" => Some(ProtocolPreference::Only(ProtocolVersion::V311)),
+            _ => None,
+        }
+    }
+}
+
+// Decide con qué versión se abre cada sesión. En modo auto se empieza con
+// MQTT 5; si el broker la rechaza varias veces seguidas con un CONNACK de
+// versión no soportada se pasa a 3.1.1 hasta el próximo arranque. Un broker
+// caído o un fallo de DNS, TCP o TLS no dicen nada del protocolo y no cuentan,
+// y cualquier sesión establecida pone el contador a cero.
+#[derive(Debug, Clone)]
+pub struct Negotiation {
+    preference: ProtocolPreference,
+    current: ProtocolVersion,
+    refused: u32,
+}
+
+impl Negotiation {
+    pub fn new(preference: ProtocolPreference) -> Self {
+        let current = match preference {
+            ProtocolPreference::Auto => ProtocolVersion::V5,
+            ProtocolPreference::Only(version) => version,
+        };
+        Negotiation { preference, current, refused: 0 }
+    }
+
+    pub fn current(&self) -> ProtocolVersion {
+        self.current
+    }
+
+    pub fn connected(&mut self) {
+        self.refused = 0;
+    }
+
+    // La sesión se cerró sin llegar a conectar; `connack_code` es el código
+    // del CONNACK si el broker llegó a rechazarla. Devuelve true si a partir
+    // de ahora se usa 3.1.1.
+    pub fn refused(&mut self, connack_code: Option<u8>) -> bool {
+        if self.preference != ProtocolPreference::Auto || self.current != ProtocolVersion::V5 {
+            return false;
+        }
+        if !connack_code.is_some_and(|code| UNSUPPORTED_PROTOCOL_CODES.contains(&code)) {
+            return false;
+        }
+
+        self.refused += 1;
+        if self.refused < V5_ATTEMPTS_BEFORE_FALLBACK {
+            return false;
+        }
+        self.current = ProtocolVersion::V311;
+        self.refused = 0;
+        true
+    }
+}
+
+// Adónde responder a un comando recibido por MQTT 5
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyTo {
+    pub topic: String,
+    pub correlation: Vec<u8>,
+}
+
+// Propiedades MQTT 5 de un mensaje; vacías con 3.1.1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub message_expiry_secs: Option<u32>,
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl Properties {
+    // Comando enviado por `sender`: la respuesta va a su buzón y se reconoce
+    // por el request_id
+    pub fn command(sender: &str, request_id: &str, expiry_secs: u32) -> Self {
+        Properties {
+            response_topic: Some(topics::responses(sender)),
+            correlation_data: Some(request_id.as_bytes().to_vec()),
+            message_expiry_secs: Some(expiry_secs),
+            user_properties: vec![(SENDER_PROPERTY.to_string(), sender.to_string())],
+        }
+    }
+
+    // Respuesta a un comando: devuelve la correlation data tal cual llegó
+    pub fn reply(reply_to: &ReplyTo, sender: &str) -> Self {
+        Properties {
+            correlation_data: Some(reply_to.correlation.clone()),
+            user_properties: vec![(SENDER_PROPERTY.to_string(), sender.to_string())],
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Properties::default()
+    }
+
+    pub fn user_property(&self, key: &str) -> Option<&str> {
+        self.user_properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        self.user_property(SENDER_PROPERTY)
+    }
+
+    // La firma cubre el payload pero no las propiedades, así que el response
+    // topic solo se acepta si es el buzón del propio emisor: nadie puede
+    // desviar las respuestas de `from` a otro tópico. Sin él (o con otro) se
+    // responde en ese mismo buzón como con 3.1.1, sin correlation data
+    pub fn reply_to(&self, from: &str) -> Option<ReplyTo> {
+        let topic = self.response_topic.as_deref()?;
+        let correlation = self.correlation_data.clone().unwrap_or_default();
+        if topic != topics::responses(from) || correlation.len() > MAX_CORRELATION_LEN {
+            return None;
+        }
+
+        Some(ReplyTo { topic: topic.to_string(), correlation })
+    }
+
+    // Con propiedades MQTT 5 la user property es obligatoria y tiene que
+    // coincidir con el `from` del payload; sin ninguna (cliente 3.1.1) solo
+    // queda el `from`
+    pub fn check_sender(&self, from: &str) -> Result<(), String> {
+        match self.sender() {
+            Some(sender) if sender != from => Err(format!(
+                "Untrusted command source: sender property '{}' does not match '{}'",
+                sender, from
+            )),
+            None if !self.is_empty() => Err(format!(
+                "Untrusted command source: MQTT 5 command from '{}' without sender property",
+                from
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,408 @@
+// Telemetría pendiente de enviar, guardada en una partición de flash. Mientras
+// no hay conexión las publicaciones "durables" se escriben aquí y, al volver
+// la conexión, se reenvían en el orden en que se produjeron con su payload
+// original (y por tanto con su timestamp original). Ese timestamp es desde
+// el arranque, así que la telemetría lleva también el id del arranque en el
+// que se produjo (message::Presence::boot) para seguir teniendo sentido si se
+// reenvía después de un reinicio.
+//
+// La partición se divide en ranuras de SLOT_SIZE bytes. Cada registro se
+// escribe una sola vez en una ranura borrada y al enviarse se marca como
+// consumido poniendo a 0 su primer byte (en NOR flash escribir solo puede
+// pasar bits de 1 a 0). El borrado es por sectores, así que al llegar al
+// inicio de un sector se borra entero; si aún tenía registros sin enviar se
+// aplica la política de desbordamiento. Tras un reinicio el estado se
+// reconstruye leyendo las cabeceras.
+
+use core::fmt;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::event::Publication;
+use crate::message::Encoding;
+
+// Unidad de borrado de la flash del ESP32
+pub const SECTOR_SIZE: u32 = 4096;
+pub const SLOT_SIZE: u32 = 512;
+// Registros por defecto (16 sectores, 64 KB)
+pub const DEFAULT_CAPACITY: usize = 128;
+
+const SLOTS_PER_SECTOR: usize = (SECTOR_SIZE / SLOT_SIZE) as usize;
+const HEADER_LEN: usize = 16;
+const MAX_RECORD_DATA: usize = SLOT_SIZE as usize - HEADER_LEN;
+
+const STATE_PENDING: u8 = 0xFF;
+const STATE_CONSUMED: u8 = 0x00;
+const MAGIC: u8 = 0xA5;
+const FLAG_RETAIN: u8 = 0x01;
+const FLAG_CBOR: u8 = 0x02;
+
+// Acceso mínimo a una partición de flash NOR
+pub trait Flash {
+    type Error: fmt::Debug;
+
+    fn size(&self) -> u32;
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+    // Solo puede pasar bits de 1 a 0
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+    // Deja el rango en 0xFF; offset y len múltiplos de SECTOR_SIZE
+    fn erase(&mut self, offset: u32, len: u32) -> Result<(), Self::Error>;
+}
+
+// Qué hacer cuando no queda sitio para un registro nuevo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Se pierde el sector más antiguo (lo reciente suele importar más)
+    DropOldest,
+    // Se descarta el registro nuevo y se conserva lo ya guardado
+    DropNewest,
+}
+
+impl OverflowPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "drop_oldest" => Some(OverflowPolicy::DropOldest),
+            "drop_newest" => Some(OverflowPolicy::DropNewest),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::DropNewest => "drop_newest",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutboxError<E> {
+    Flash(E),
+    // La partición no llega a dos sectores
+    PartitionTooSmall,
+    // Tópico + payload no caben en una ranura
+    TooLarge,
+    // Sin sitio y la política es DropNewest
+    Full,
+}
+
+impl<E: fmt::Debug> fmt::Display for OutboxError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboxError::Flash(e) => write!(f, "Flash error: {:?}", e),
+            OutboxError::PartitionTooSmall => write!(f, "Outbox partition must hold at least two sectors"),
+            OutboxError::TooLarge => write!(f, "Publication too large for an outbox slot"),
+            OutboxError::Full => write!(f, "Outbox full"),
+        }
+    }
+}
+
+// Contadores compartidos con la tarea que publica el heartbeat
+#[derive(Debug, Clone, Default)]
+pub struct OutboxStats {
+    pending: Arc<AtomicU32>,
+    dropped: Arc<AtomicU32>,
+}
+
+impl OutboxStats {
+    pub fn pending(&self) -> u32 {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    // Registros perdidos por desbordamiento, corrupción o tamaño
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Outbox<F: Flash> {
+    flash: F,
+    slots: usize,
+    policy: OverflowPolicy,
+    // (ranura, secuencia) de los registros sin enviar, del más antiguo al más nuevo
+    pending: VecDeque<(usize, u32)>,
+    write_slot: usize,
+    next_seq: u32,
+    stats: OutboxStats,
+}
+
+impl<F: Flash> Outbox<F> {
+    // `capacity` en registros; se redondea a sectores completos y se limita
+    // al tamaño de la partición
+    pub fn open(mut flash: F, capacity: usize, policy: OverflowPolicy) -> Result<Self, OutboxError<F::Error>> {
+        let available = (flash.size() / SECTOR_SIZE) as usize;
+        let sectors = capacity.div_ceil(SLOTS_PER_SECTOR).max(2).min(available);
+        if sectors < 2 {
+            return Err(OutboxError::PartitionTooSmall);
+        }
+        let slots = sectors * SLOTS_PER_SECTOR;
+
+        // Reconstruir el estado desde las cabeceras
+        let mut records = Vec::new();
+        let mut last: Option<(usize, u32)> = None;
+        for slot in 0..slots {
+            let mut header = [0u8; HEADER_LEN];
+            flash.read(slot_offset(slot), &mut header).map_err(OutboxError::Flash)?;
+            let Some(header) = Header::parse(&header) else {
+                continue;
+            };
+            if last.map_or(true, |(_, seq)| header.seq > seq) {
+                last = Some((slot, header.seq));
+            }
+            if header.state == STATE_PENDING {
+                records.push((slot, header.seq));
+            }
+        }
+        records.sort_by_key(|&(_, seq)| seq);
+
+        let (write_slot, next_seq) = match last {
+            Some((slot, seq)) => ((slot + 1) % slots, seq.wrapping_add(1)),
+            None => (0, 1),
+        };
+
+        let outbox = Outbox {
+            flash,
+            slots,
+            policy,
+            pending: records.into_iter().collect(),
+            write_slot,
+            next_seq,
+            stats: OutboxStats::default(),
+        };
+        outbox.update_pending();
+        Ok(outbox)
+    }
+
+    pub fn stats(&self) -> OutboxStats {
+        self.stats.clone()
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn update_pending(&self) {
+        self.stats.pending.store(self.pending.len() as u32, Ordering::Relaxed);
+    }
+
+    fn count_dropped(&self, count: usize) {
+        self.stats.dropped.fetch_add(count as u32, Ordering::Relaxed);
+    }
+
+    // Guarda una publicación para enviarla cuando vuelva la conexión
+    pub fn push(&mut self, publication: &Publication) -> Result<(), OutboxError<F::Error>> {
+        let topic = publication.topic.as_bytes();
+        let payload = &publication.payload;
+        if topic.len() > u8::MAX as usize || topic.len() + payload.len() > MAX_RECORD_DATA {
+            self.count_dropped(1);
+            return Err(OutboxError::TooLarge);
+        }
+
+        // Una ranura sucia (escritura cortada por un reinicio) se salta
+        for _ in 0..self.slots {
+            let slot = self.write_slot;
+            if slot % SLOTS_PER_SECTOR == 0 {
+                self.prepare_sector(slot / SLOTS_PER_SECTOR)?;
+            } else if !self.is_blank(slot)? {
+                self.write_slot = (slot + 1) % self.slots;
+                continue;
+            }
+
+            let mut record = [0xFFu8; SLOT_SIZE as usize];
+            let len = HEADER_LEN + topic.len() + payload.len();
+            record[HEADER_LEN..HEADER_LEN + topic.len()].copy_from_slice(topic);
+            record[HEADER_LEN + topic.len()..len].copy_from_slice(payload);
+            Header {
+                state: STATE_PENDING,
+                flags: if publication.retain { FLAG_RETAIN } else { 0 }
+                    | if publication.encoding == Encoding::Cbor { FLAG_CBOR } else { 0 },
+                topic_len: topic.len() as u8,
+                seq: self.next_seq,
+                payload_len: payload.len() as u16,
+            }
+            .write(&mut record[..len]);
+
+            self.flash.write(slot_offset(slot), &record[..len]).map_err(OutboxError::Flash)?;
+            self.pending.push_back((slot, self.next_seq));
+            self.next_seq = self.next_seq.wrapping_add(1);
+            self.write_slot = (slot + 1) % self.slots;
+            self.update_pending();
+            return Ok(());
+        }
+
+        self.count_dropped(1);
+        Err(OutboxError::Full)
+    }
+
+    // Borra el sector antes de escribir su primera ranura
+    fn prepare_sector(&mut self, sector: usize) -> Result<(), OutboxError<F::Error>> {
+        let in_sector = |slot: usize| slot / SLOTS_PER_SECTOR == sector;
+        let lost = self.pending.iter().filter(|&&(slot, _)| in_sector(slot)).count();
+
+        if lost > 0 && self.policy == OverflowPolicy::DropNewest {
+            self.count_dropped(1);
+            return Err(OutboxError::Full);
+        }
+
+        self.flash
+            .erase(sector as u32 * SECTOR_SIZE, SECTOR_SIZE)
+            .map_err(OutboxError::Flash)?;
+        if lost > 0 {
+            self.pending.retain(|&(slot, _)| !in_sector(slot));
+            self.count_dropped(lost);
+            self.update_pending();
+        }
+        Ok(())
+    }
+
+    fn is_blank(&mut self, slot: usize) -> Result<bool, OutboxError<F::Error>> {
+        let mut header = [0u8; HEADER_LEN];
+        self.flash.read(slot_offset(slot), &mut header).map_err(OutboxError::Flash)?;
+        Ok(header.iter().all(|&b| b == 0xFF))
+    }
+
+    // El registro más antiguo sin enviar. Los registros corruptos se descartan.
+    pub fn front(&mut self) -> Result<Option<Publication>, OutboxError<F::Error>> {
+        while let Some(&(slot, seq)) = self.pending.front() {
+            let mut record = [0u8; SLOT_SIZE as usize];
+            self.flash.read(slot_offset(slot), &mut record).map_err(OutboxError::Flash)?;
+
+            match Header::parse(&record[..HEADER_LEN]) {
+                Some(header) if header.seq == seq && header.checksum_matches(&record) => {
+                    let topic_end = HEADER_LEN + header.topic_len as usize;
+                    let payload_end = topic_end + header.payload_len as usize;
+                    let topic = String::from_utf8_lossy(&record[HEADER_LEN..topic_end]);
+                    let mut publication = Publication::new(&topic, &record[topic_end..payload_end]);
+                    publication.retain = header.flags & FLAG_RETAIN != 0;
+                    if header.flags & FLAG_CBOR != 0 {
+                        publication.encoding = Encoding::Cbor;
+                    }
+                    publication.durable = true;
+                    return Ok(Some(publication));
+                }
+                _ => {
+                    self.pending.pop_front();
+                    self.count_dropped(1);
+                    self.update_pending();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Marca como enviado el registro devuelto por front()
+    pub fn pop_front(&mut self) -> Result<(), OutboxError<F::Error>> {
+        if let Some(&(slot, _)) = self.pending.front() {
+            self.flash
+                .write(slot_offset(slot), &[STATE_CONSUMED])
+                .map_err(OutboxError::Flash)?;
+            self.pending.pop_front();
+            self.update_pending();
+        }
+        Ok(())
+    }
+
+    // Reenvía hasta `max` registros en orden; para en el primero que `send`
+    // no consiga enviar. Devuelve cuántos salieron.
+    pub fn replay(
+        &mut self,
+        max: usize,
+        mut send: impl FnMut(&Publication) -> bool,
+    ) -> Result<usize, OutboxError<F::Error>> {
+        let mut sent = 0;
+        while sent < max {
+            let Some(publication) = self.front()? else {
+                break;
+            };
+            if !send(&publication) {
+                break;
+            }
+            self.pop_front()?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
+
+fn slot_offset(slot: usize) -> u32 {
+    slot as u32 * SLOT_SIZE
+}
+
+// Cabecera de 16 bytes:
+// [0] estado  [1] magic  [2] flags  [3] longitud del tópico
+// [4..8] secuencia  [8..10] longitud del payload  [10..12] reservado
+// [12..16] CRC-32 de los bytes 1..12, el tópico y el payload
+struct Header {
+    state: u8,
+    flags: u8,
+    topic_len: u8,
+    seq: u32,
+    payload_len: u16,
+}
+
+impl Header {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes[1] != MAGIC {
+            return None;
+        }
+        let header = Header {
+            state: bytes[0],
+            flags: bytes[2],
+            topic_len: bytes[3],
+            seq: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            payload_len: u16::from_le_bytes([bytes[8], bytes[9]]),
+        };
+        let valid_state = header.state == STATE_PENDING || header.state == STATE_CONSUMED;
+        let fits = header.topic_len as usize + header.payload_len as usize <= MAX_RECORD_DATA;
+        (valid_state && fits).then_some(header)
+    }
+
+    // Escribe la cabecera y el CRC; `record` ya contiene tópico y payload
+    fn write(&self, record: &mut [u8]) {
+        record[0] = self.state;
+        record[1] = MAGIC;
+        record[2] = self.flags;
+        record[3] = self.topic_len;
+        record[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        record[8..10].copy_from_slice(&self.payload_len.to_le_bytes());
+        record[10..12].copy_from_slice(&[0xFF, 0xFF]);
+        let crc = self.checksum(record);
+        record[12..16].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    fn checksum(&self, record: &[u8]) -> u32 {
+        let end = HEADER_LEN + self.topic_len as usize + self.payload_len as usize;
+        crc32(&[&record[1..12], &record[HEADER_LEN..end]])
+    }
+
+    fn checksum_matches(&self, record: &[u8]) -> bool {
+        let stored = u32::from_le_bytes([record[12], record[13], record[14], record[15]]);
+        stored == self.checksum(record)
+    }
+}
+
+// CRC-32 (IEEE) bit a bit: los registros son pequeños y no hace falta tabla
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
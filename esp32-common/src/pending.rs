@@ -0,0 +1,115 @@
+// Comandos enviados a otro dispositivo que esperan respuesta. Si la respuesta
+// con el mismo request_id no llega a tiempo se reenvía el mismo payload (el
+// receptor lo reconoce por el id) hasta agotar los intentos.
+
+use crate::clock::Clock;
+use crate::event::Publication;
+
+// Comandos en vuelo como máximo
+pub const MAX_PENDING: usize = 8;
+// Tiempo de espera de la respuesta antes de reenviar
+pub const ACK_TIMEOUT_MS: u64 = 3000;
+// Envíos totales (el primero más los reintentos)
+pub const MAX_ATTEMPTS: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingCommand {
+    pub request_id: String,
+    pub command: String,
+    pub to: String,
+    pub publication: Publication,
+    pub attempts: u8,
+    last_sent_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingAction {
+    // Volver a publicar el comando
+    Resend(Publication),
+    // Sin respuesta tras todos los intentos
+    Failed(PendingCommand),
+}
+
+pub struct PendingCommands<C: Clock> {
+    clock: C,
+    entries: Vec<PendingCommand>,
+    timeout_ms: u64,
+    max_attempts: u8,
+}
+
+impl<C: Clock> PendingCommands<C> {
+    pub fn new(clock: C) -> Self {
+        PendingCommands {
+            clock,
+            entries: Vec::with_capacity(MAX_PENDING),
+            timeout_ms: ACK_TIMEOUT_MS,
+            max_attempts: MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_retry(mut self, timeout_ms: u64, max_attempts: u8) -> Self {
+        self.timeout_ms = timeout_ms;
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Registra un comando recién publicado
+    pub fn track(
+        &mut self,
+        request_id: &str,
+        command: &str,
+        to: &str,
+        publication: Publication,
+    ) -> Result<(), &'static str> {
+        if self.entries.len() >= MAX_PENDING {
+            return Err("Too many pending commands");
+        }
+
+        self.entries.push(PendingCommand {
+            request_id: request_id.to_string(),
+            command: command.to_string(),
+            to: to.to_string(),
+            publication,
+            attempts: 1,
+            last_sent_ms: self.clock.now_ms(),
+        });
+        Ok(())
+    }
+
+    // Llega la respuesta: deja de esperar ese comando
+    pub fn acknowledge(&mut self, request_id: &str) -> Option<PendingCommand> {
+        let index = self.entries.iter().position(|p| p.request_id == request_id)?;
+        Some(self.entries.remove(index))
+    }
+
+    // Revisa los plazos; llamar periódicamente
+    pub fn poll(&mut self) -> Vec<PendingAction> {
+        let now = self.clock.now_ms();
+        let mut actions = Vec::new();
+
+        let mut i = 0;
+        while i < self.entries.len() {
+            let entry = &mut self.entries[i];
+            if now.saturating_sub(entry.last_sent_ms) < self.timeout_ms {
+                i += 1;
+            } else if entry.attempts >= self.max_attempts {
+                actions.push(PendingAction::Failed(self.entries.remove(i)));
+            } else {
+                entry.attempts += 1;
+                entry.last_sent_ms = now;
+                actions.push(PendingAction::Resend(entry.publication.clone()));
+                i += 1;
+            }
+        }
+
+        actions
+    }
+}
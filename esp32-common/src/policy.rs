@@ -0,0 +1,270 @@
+// Política de autorización de comandos: qué emisor puede enviar qué comandos,
+// sobre qué LEDs, con qué duración máxima y a qué ritmo. Se aplica con
+// denegación por defecto: un emisor sin regla, o un comando que su regla no
+// nombra, se rechaza. Las reglas se recorren en orden y gana la primera cuyo
+// `source` coincide (id exacto o prefijo terminado en '*').
+//
+// La política activa se guarda en la NVS y se sustituye publicando en
+// esp32/{device_id}/config/policy un documento firmado con HMAC-SHA256 (la
+// clave de `from` en cmd_keys) con una versión mayor que la última aceptada:
+//
+//   {"v":1,"version":3,"from":"node-red-dashboard","sig":"<hex>","rules":[
+//     {"source":"node-red-dashboard","commands":["*","POLICY_UPDATE"]},
+//     {"source":"telegram-bot","commands":["*"],"rate":10},
+//     {"source":"esp32-sensor*","commands":["BUZZER","ACKNOWLEDGE"],"max_duration":2000},
+//     {"source":"node-red*","commands":["LED_ON","LED_OFF"],"leds":[1,2]}]}
+//
+// Solo la aceptan los emisores a los que la política actual concede
+// POLICY_UPDATE. La firma cubre esta codificación canónica, una regla por línea:
+//
+//   esp32-policy-v1\n{version}\n{from}\n{source};{commands};{leds};{max_duration};{rate}\n...
+
+use crate::command::{Command, ALLOWED_COMMANDS};
+use crate::signature::{self, Keyring};
+
+pub const CANONICAL_PREFIX: &str = "esp32-policy-v1";
+
+// Permiso para sustituir la política; "*" no lo incluye
+pub const POLICY_UPDATE: &str = "POLICY_UPDATE";
+// En `commands`: cualquier comando de ALLOWED_COMMANDS
+pub const ANY_COMMAND: &str = "*";
+
+// Límites del documento (se decodifica en la pila del bucle principal)
+pub const MAX_RULES: usize = 8;
+pub const MAX_RULE_COMMANDS: usize = 10;
+pub const MAX_COMMAND_LEN: usize = 16;
+pub const MAX_POLICY_LEN: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub source: String,
+    pub commands: Vec<String>,
+    pub leds: Option<Vec<u8>>,     // None: cualquier LED
+    pub max_duration: Option<u64>, // ms; None: el límite del hardware
+    pub rate: Option<u32>,         // Comandos por minuto de este emisor
+}
+
+impl Rule {
+    pub fn new(source: &str, commands: &[&str]) -> Self {
+        Rule {
+            source: source.to_string(),
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            leds: None,
+            max_duration: None,
+            rate: None,
+        }
+    }
+
+    pub fn matches(&self, source: &str) -> bool {
+        match self.source.strip_suffix('*') {
+            Some(prefix) => source.starts_with(prefix),
+            None => self.source == source,
+        }
+    }
+
+    pub fn allows(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| {
+            c == command || (c == ANY_COMMAND && ALLOWED_COMMANDS.contains(&command))
+        })
+    }
+
+    fn canonical(&self) -> String {
+        fn opt<T: ToString>(value: Option<T>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_default()
+        }
+
+        let leds = self
+            .leds
+            .as_ref()
+            .map(|leds| leds.iter().map(u8::to_string).collect::<Vec<_>>().join(","));
+        format!(
+            "{};{};{};{};{}",
+            self.source,
+            self.commands.join(","),
+            opt(leds),
+            opt(self.max_duration),
+            opt(self.rate)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub version: u32,
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Policy {
+    // Versión 0: las reglas que antes estaban fijas en el validador. Cualquier
+    // política publicada la sustituye
+    fn default() -> Self {
+        let no_buzzer = ["LED_ON", "LED_OFF", "LED_TOGGLE", "LED_ALL_ON", "LED_ALL_OFF", "ACKNOWLEDGE", "QUOTA_STATUS"];
+        let mut dashboard = Rule::new("node-red-dashboard", &no_buzzer);
+        dashboard.commands.push(POLICY_UPDATE.to_string());
+
+        Policy {
+            version: 0,
+            rules: vec![
+                Rule::new("telegram-bot*", &[ANY_COMMAND]),
+                Rule::new("esp32-sensor*", &[ANY_COMMAND]),
+                Rule::new("esp32-*", &no_buzzer),
+                dashboard,
+                Rule::new("node-red*", &no_buzzer),
+            ],
+        }
+    }
+}
+
+impl Policy {
+    // Primera regla que corresponde al emisor
+    pub fn rule(&self, source: &str) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(source))
+    }
+
+    // Emisor, comando y parámetros; el ritmo lo controla el validador
+    pub fn authorize(&self, command: &Command) -> Result<&Rule, String> {
+        let source = &command.from;
+        let rule = self
+            .rule(source)
+            .ok_or_else(|| format!("Untrusted command source: {}", source))?;
+        if !rule.allows(&command.command) {
+            return Err(format!("Command '{}' not allowed for {}", command.command, source));
+        }
+
+        if let (Some(leds), Some(led_id)) = (&rule.leds, command.led_id) {
+            if !leds.contains(&led_id) {
+                return Err(format!("Command '{}' not allowed on LED {} for {}", command.command, led_id, source));
+            }
+        }
+        if let (Some(max), Some(duration)) = (rule.max_duration, command.duration) {
+            if duration > max {
+                return Err(format!("Command '{}' limited to {}ms for {}", command.command, max, source));
+            }
+        }
+        Ok(rule)
+    }
+
+    pub fn canonical(&self, from: &str) -> String {
+        let mut lines = vec![CANONICAL_PREFIX.to_string(), self.version.to_string(), from.to_string()];
+        lines.extend(self.rules.iter().map(Rule::canonical));
+        lines.join("\n")
+    }
+
+    // Comprueba un documento recibido antes de que sustituya a esta política.
+    // `floor`: versión de la última aceptada, guardada en la NVS; sigue
+    // valiendo como mínimo aunque la activa sea la de fábrica porque la
+    // guardada ya no se pudo restaurar
+    pub fn accept(&self, update: &PolicyUpdate, keys: &Keyring, floor: u32) -> Result<(), PolicyError> {
+        update.verify(keys)?;
+
+        if !self.rule(&update.from).is_some_and(|rule| rule.allows(POLICY_UPDATE)) {
+            return Err(PolicyError::NotAllowed);
+        }
+        // Versión estrictamente mayor: un documento antiguo retenido o repetido
+        // no puede devolver el dispositivo a una política anterior
+        if update.policy.version <= self.version.max(floor) {
+            return Err(PolicyError::StaleVersion);
+        }
+        Ok(())
+    }
+
+    // Política guardada en la NVS al arrancar. POLICY_UPDATE se comprobó al
+    // aceptarla frente a la política de entonces, que no tiene por qué ser la
+    // de fábrica; aquí basta con que sea el documento registrado como aceptado
+    // y con que su firma siga valiendo con las claves actuales, así que un
+    // documento manipulado en la flash o firmado con una clave retirada de
+    // cmd_keys no sobrevive al reinicio
+    pub fn restore(update: PolicyUpdate, keys: &Keyring, accepted: &Accepted) -> Result<Policy, PolicyError> {
+        update.verify(keys)?;
+        if update.accepted() != *accepted {
+            return Err(PolicyError::NotAccepted);
+        }
+        Ok(update.policy)
+    }
+}
+
+// Lo que se guarda en la NVS junto al documento aceptado: su versión, mínimo
+// para el siguiente, y el emisor que lo autorizó
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accepted {
+    pub version: u32,
+    pub from: String,
+}
+
+// Documento recibido por esp32/{device_id}/config/policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyUpdate {
+    pub from: String,
+    pub signature: String,
+    pub policy: Policy,
+}
+
+impl PolicyUpdate {
+    pub fn accepted(&self) -> Accepted {
+        Accepted {
+            version: self.policy.version,
+            from: self.from.clone(),
+        }
+    }
+
+    // Solo la firma, con la clave de `from` en cmd_keys
+    pub fn verify(&self, keys: &Keyring) -> Result<(), PolicyError> {
+        let key = keys.key(&self.from).ok_or(PolicyError::UnknownSender)?;
+        let signature = signature::from_hex(&self.signature).ok_or(PolicyError::InvalidSignature)?;
+        if !self.is_canonical() || !signature::verify(key, &self.policy.canonical(&self.from), &signature) {
+            return Err(PolicyError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    // ';', ',' o un salto de línea dentro de un campo harían ambigua la firma
+    fn is_canonical(&self) -> bool {
+        !self.from.contains('\n')
+            && self.policy.rules.iter().all(|rule| {
+                core::iter::once(&rule.source)
+                    .chain(&rule.commands)
+                    .all(|field| !field.contains(['\n', ';', ',']))
+            })
+    }
+}
+
+// Firma en hexadecimal para el campo `sig`
+pub fn sign(key: &[u8], from: &str, policy: &Policy) -> String {
+    signature::to_hex(&signature::hmac(key, &policy.canonical(from)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyError {
+    UnknownSender,
+    InvalidSignature,
+    // El emisor no tiene POLICY_UPDATE en la política actual
+    NotAllowed,
+    StaleVersion,
+    // Documento guardado distinto del registrado como aceptado (al restaurar)
+    NotAccepted,
+}
+
+impl PolicyError {
+    // Motivo estable para el evento policy_rejected
+    pub fn code(self) -> &'static str {
+        match self {
+            PolicyError::UnknownSender => "unknown_sender",
+            PolicyError::InvalidSignature => "invalid_signature",
+            PolicyError::NotAllowed => "not_allowed",
+            PolicyError::StaleVersion => "stale_version",
+            PolicyError::NotAccepted => "not_accepted",
+        }
+    }
+}
+
+impl core::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            PolicyError::UnknownSender => "No signing key for policy source",
+            PolicyError::InvalidSignature => "Invalid policy signature",
+            PolicyError::NotAllowed => "Policy source is not allowed to update the policy",
+            PolicyError::StaleVersion => "Policy version is not newer than the active one",
+            PolicyError::NotAccepted => "Stored policy does not match the accepted version and source",
+        })
+    }
+}
@@ -0,0 +1,284 @@
+// Cuotas diarias de buzzer y de comandos. Los token buckets (ratelimit)
+// limitan ráfagas; estas limitan el total de un día natural. Se guardan en la
+// NVS para que un reinicio no las ponga a cero y se reinician a medianoche
+// según la hora real (SNTP), en la zona horaria configurada. Hasta que el
+// reloj se sincroniza se sigue contando sobre el día guardado.
+//
+// Las paradas de emergencia y QUOTA_STATUS no gastan cuota: un operador
+// tiene que poder consultarla y parar los LEDs aunque esté agotada. Los
+// comandos de los botones locales no gastan cuota de comandos, pero sus
+// tonos sí cuentan para la del buzzer.
+//
+// La cuota se reserva al aceptar el comando (así varios en cola no pasan del
+// límite) y se ajusta cuando el actuador termina: un comando que no llegó a
+// ejecutarse se devuelve, y el buzzer cobra los tonos que sonaron de verdad
+// (tres en BUZZER_TRIPLE, uno en el beep de ACKNOWLEDGE). La reserva viaja
+// con el comando: si entre medias pasó la medianoche, el ajuste no toca los
+// contadores del día nuevo.
+
+use core::fmt;
+
+use crate::command::{Command, CommandError, ErrorCode};
+use crate::event::CommandOutcome;
+use crate::ratelimit::CommandClass;
+
+pub const DEFAULT_BUZZER_PER_DAY: u32 = 1000;
+pub const DEFAULT_COMMANDS_PER_DAY: u32 = 5000;
+
+// Comando que responde con lo que queda de cada cuota
+pub const STATUS_COMMAND: &str = "QUOTA_STATUS";
+
+// Tamaño del estado guardado en la NVS
+pub const STATE_LEN: usize = 12;
+
+const DAY_MS: i64 = 86_400_000;
+// Día desconocido en el estado guardado
+const NO_DAY: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaLimits {
+    pub buzzer_per_day: u32,
+    pub commands_per_day: u32,
+    // Desplazamiento de la hora local respecto a UTC (Colombia: -300)
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for QuotaLimits {
+    fn default() -> Self {
+        QuotaLimits {
+            buzzer_per_day: DEFAULT_BUZZER_PER_DAY,
+            commands_per_day: DEFAULT_COMMANDS_PER_DAY,
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+fn local_ms(wall_ms: u64, utc_offset_minutes: i32) -> i64 {
+    wall_ms as i64 + utc_offset_minutes as i64 * 60_000
+}
+
+// Día local (días desde el 1 de enero de 1970) de una hora Unix en ms
+pub fn local_day(wall_ms: u64, utc_offset_minutes: i32) -> u32 {
+    local_ms(wall_ms, utc_offset_minutes).div_euclid(DAY_MS) as u32
+}
+
+// Milisegundos hasta la próxima medianoche local
+pub fn until_midnight_ms(wall_ms: u64, utc_offset_minutes: i32) -> u64 {
+    (DAY_MS - local_ms(wall_ms, utc_offset_minutes).rem_euclid(DAY_MS)) as u64
+}
+
+// Tonos que se reservan de la cuota de buzzer antes de ejecutar el comando
+pub fn expected_beeps(command: &Command) -> u32 {
+    match command.command.as_str() {
+        "BUZZER" | "ACKNOWLEDGE" => 1,
+        "BUZZER_TRIPLE" => 3,
+        _ => 0,
+    }
+}
+
+fn is_exempt(command: &Command) -> bool {
+    matches!(CommandClass::of(command), CommandClass::Status | CommandClass::Emergency)
+}
+
+// Cuota que no alcanza para un comando
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaError {
+    Buzzer,
+    Commands,
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QuotaError::Buzzer => "Daily buzzer limit exceeded",
+            QuotaError::Commands => "Daily command limit exceeded",
+        })
+    }
+}
+
+impl CommandError for QuotaError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::QuotaExceeded
+    }
+}
+
+// Lo que consume() reservó para un comando, hasta que settle() lo ajusta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reservation {
+    // Reinicios de las cuotas desde el arranque cuando se reservó
+    period: u32,
+    // false en los comandos locales, que solo reservan tonos
+    command: bool,
+}
+
+// Lo que se guarda en la NVS
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaState {
+    pub day: Option<u32>,
+    pub buzzer: u32,
+    pub commands: u32,
+}
+
+impl QuotaState {
+    // Día y contadores en little endian
+    pub fn to_bytes(&self) -> [u8; STATE_LEN] {
+        let mut bytes = [0u8; STATE_LEN];
+        bytes[0..4].copy_from_slice(&self.day.unwrap_or(NO_DAY).to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.buzzer.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.commands.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; STATE_LEN] = bytes.try_into().ok()?;
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(QuotaState {
+            day: Some(word(0)).filter(|day| *day != NO_DAY),
+            buzzer: word(4),
+            commands: word(8),
+        })
+    }
+}
+
+// Respuesta a QUOTA_STATUS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub buzzer_remaining: u32,
+    pub buzzer_limit: u32,
+    pub commands_remaining: u32,
+    pub commands_limit: u32,
+    // None hasta que el reloj se sincroniza
+    pub resets_in_ms: Option<u64>,
+}
+
+impl fmt::Display for QuotaStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buzzer {}/{}, commands {}/{} left",
+            self.buzzer_remaining, self.buzzer_limit, self.commands_remaining, self.commands_limit
+        )?;
+        match self.resets_in_ms {
+            Some(ms) => write!(f, ", reset in {}s", ms.div_ceil(1000)),
+            None => f.write_str(", clock not synchronized"),
+        }
+    }
+}
+
+pub struct Quotas {
+    limits: QuotaLimits,
+    state: QuotaState,
+    dirty: bool,
+    // Veces que se reiniciaron los contadores desde el arranque
+    period: u32,
+}
+
+impl Quotas {
+    pub fn new(limits: QuotaLimits, state: QuotaState) -> Self {
+        Quotas { limits, state, dirty: false, period: 0 }
+    }
+
+    pub fn limits(&self) -> QuotaLimits {
+        self.limits
+    }
+
+    pub fn state(&self) -> QuotaState {
+        self.state
+    }
+
+    // Hay cambios sin guardar en la NVS (y se dan por guardados)
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::replace(&mut self.dirty, false)
+    }
+
+    // Pone el día en hora; true si empezó un día nuevo y se reiniciaron las cuotas
+    pub fn roll(&mut self, wall_ms: Option<u64>) -> bool {
+        let Some(wall_ms) = wall_ms else {
+            return false;
+        };
+        let today = local_day(wall_ms, self.limits.utc_offset_minutes);
+        match self.state.day {
+            Some(day) if day == today => false,
+            Some(day) if day < today => {
+                self.state = QuotaState { day: Some(today), buzzer: 0, commands: 0 };
+                self.dirty = true;
+                self.period += 1;
+                true
+            },
+            // Lo contado antes de sincronizar, o con el reloj por delante, se
+            // queda en el día de hoy: un reloj que retrocede no regala cuota
+            _ => {
+                self.state.day = Some(today);
+                self.dirty = true;
+                false
+            },
+        }
+    }
+
+    // Reserva la cuota de un comando aceptado o devuelve el error para
+    // responder. La reserva queda en el comando y se ajusta con settle() al
+    // terminar
+    pub fn consume(&mut self, command: &mut Command, wall_ms: Option<u64>) -> Result<(), QuotaError> {
+        self.reserve(command, wall_ms, true)
+    }
+
+    // Comando de un botón local: solo reserva sus tonos
+    pub fn consume_local(&mut self, command: &mut Command, wall_ms: Option<u64>) -> Result<(), QuotaError> {
+        self.reserve(command, wall_ms, false)
+    }
+
+    fn reserve(&mut self, command: &mut Command, wall_ms: Option<u64>, counted: bool) -> Result<(), QuotaError> {
+        self.roll(wall_ms);
+        if is_exempt(command) {
+            return Ok(());
+        }
+
+        if counted && self.state.commands >= self.limits.commands_per_day {
+            return Err(QuotaError::Commands);
+        }
+        let beeps = expected_beeps(command);
+        if beeps > 0 && self.state.buzzer + beeps > self.limits.buzzer_per_day {
+            return Err(QuotaError::Buzzer);
+        }
+        self.state.buzzer += beeps;
+        self.state.commands += counted as u32;
+        self.dirty = true;
+        command.quota = Some(Reservation { period: self.period, command: counted });
+        Ok(())
+    }
+
+    // Resultado de un comando que pasó por consume(): si falló no cuenta como
+    // comando, y el buzzer cobra los tonos que sonaron en vez de los reservados.
+    // Lo reservado antes de la medianoche se quedó en el día anterior
+    pub fn settle(&mut self, outcome: &CommandOutcome) {
+        let Some(reservation) = outcome.command.quota else {
+            return;
+        };
+        if reservation.period != self.period {
+            return;
+        }
+
+        let buzzer = (self.state.buzzer + outcome.beeps).saturating_sub(expected_beeps(&outcome.command));
+        let commands = if reservation.command && outcome.result.is_err() {
+            self.state.commands.saturating_sub(1)
+        } else {
+            self.state.commands
+        };
+        if (buzzer, commands) != (self.state.buzzer, self.state.commands) {
+            self.state.buzzer = buzzer;
+            self.state.commands = commands;
+            self.dirty = true;
+        }
+    }
+
+    pub fn status(&mut self, wall_ms: Option<u64>) -> QuotaStatus {
+        self.roll(wall_ms);
+        QuotaStatus {
+            buzzer_remaining: self.limits.buzzer_per_day.saturating_sub(self.state.buzzer),
+            buzzer_limit: self.limits.buzzer_per_day,
+            commands_remaining: self.limits.commands_per_day.saturating_sub(self.state.commands),
+            commands_limit: self.limits.commands_per_day,
+            resets_in_ms: wall_ms.map(|wall| until_midnight_ms(wall, self.limits.utc_offset_minutes)),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Rate limiting con token buckets. Cada bucket admite una ráfaga de `burst`
+// comandos y se recarga de forma continua a `per_minute` tokens por minuto,
+// así que no hay frontera de ventana en la que se pueda gastar el doble.
+//
+// El validador consume un token del bucket del emisor y otro del de la clase
+// del comando. Los LED_ALL_OFF de emergencia no tocan esos buckets: gastan
+// del presupuesto de emergencia compartido y de una asignación de emergencia
+// propia de cada emisor, así que ni el resto del tráfico ni un solo emisor
+// pueden agotar las paradas de los demás.
+
+use crate::command::Command;
+use crate::quota;
+
+// Un token en unidades internas: recargar `per_minute` tokens por minuto es
+// sumar `per_minute` unidades por ms, sin decimales
+const UNITS_PER_TOKEN: u64 = 60_000;
+
+// Emisores distintos con bucket propio; al llenarse se olvida el menos reciente
+pub const MAX_SOURCES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Budget {
+    pub const fn new(burst: u32, per_minute: u32) -> Self {
+        Budget { burst, per_minute }
+    }
+
+    // Presupuesto por emisor a partir de un límite en comandos por minuto
+    pub fn per_minute(rate: u32) -> Self {
+        Budget::new(rate.clamp(1, SOURCE_BURST), rate)
+    }
+}
+
+// Ráfaga máxima de un emisor, sea cual sea su límite por minuto
+pub const SOURCE_BURST: u32 = 10;
+
+// Presupuestos por clase de comando, compartidos por todos los emisores
+pub const LED_BUDGET: Budget = Budget::new(10, 60);
+pub const BUZZER_BUDGET: Budget = Budget::new(3, 10);
+pub const ACKNOWLEDGE_BUDGET: Budget = Budget::new(5, 30);
+pub const EMERGENCY_BUDGET: Budget = Budget::new(3, 6);
+pub const STATUS_BUDGET: Budget = Budget::new(5, 30);
+
+// Parte del presupuesto de emergencia que puede gastar cada emisor: menos que
+// la ráfaga y la recarga compartidas, así que siempre queda para otro
+pub const SOURCE_EMERGENCY_BUDGET: Budget = Budget::new(2, 3);
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    budget: Budget,
+    units: u64,
+    last_ms: u64,
+}
+
+impl TokenBucket {
+    // Empieza lleno
+    pub fn new(budget: Budget, now_ms: u64) -> Self {
+        TokenBucket {
+            budget,
+            units: budget.burst as u64 * UNITS_PER_TOKEN,
+            last_ms: now_ms,
+        }
+    }
+
+    pub fn budget(&self) -> Budget {
+        self.budget
+    }
+
+    fn refill(&mut self, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.last_ms);
+        let capacity = self.budget.burst as u64 * UNITS_PER_TOKEN;
+        self.units = (self.units + elapsed * self.budget.per_minute as u64).min(capacity);
+        self.last_ms = self.last_ms.max(now_ms);
+    }
+
+    // Tokens enteros disponibles
+    pub fn remaining(&mut self, now_ms: u64) -> u32 {
+        self.refill(now_ms);
+        (self.units / UNITS_PER_TOKEN) as u32
+    }
+
+    // Espera hasta el próximo token; 0 si ya hay uno. u64::MAX si no se recarga
+    pub fn retry_after_ms(&mut self, now_ms: u64) -> u64 {
+        self.refill(now_ms);
+        let missing = UNITS_PER_TOKEN.saturating_sub(self.units);
+        match (missing, self.budget.per_minute as u64) {
+            (0, _) => 0,
+            (_, 0) => u64::MAX,
+            (missing, rate) => missing.div_ceil(rate),
+        }
+    }
+
+    pub fn try_take(&mut self, now_ms: u64) -> bool {
+        if self.remaining(now_ms) == 0 {
+            return false;
+        }
+        self.units -= UNITS_PER_TOKEN;
+        true
+    }
+}
+
+// Clase de un comando a efectos de presupuesto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandClass {
+    Led,
+    Buzzer,
+    Acknowledge,
+    // LED_ALL_OFF con "emergency":true
+    Emergency,
+    // QUOTA_STATUS: consulta, no toca el hardware
+    Status,
+}
+
+impl CommandClass {
+    pub fn of(command: &Command) -> Self {
+        match command.command.as_str() {
+            "LED_ALL_OFF" if command.emergency == Some(true) => CommandClass::Emergency,
+            "BUZZER" | "BUZZER_TRIPLE" => CommandClass::Buzzer,
+            "ACKNOWLEDGE" => CommandClass::Acknowledge,
+            quota::STATUS_COMMAND => CommandClass::Status,
+            _ => CommandClass::Led,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandClass::Led => "led",
+            CommandClass::Buzzer => "buzzer",
+            CommandClass::Acknowledge => "acknowledge",
+            CommandClass::Emergency => "emergency",
+            CommandClass::Status => "status",
+        }
+    }
+
+    pub fn budget(self) -> Budget {
+        match self {
+            CommandClass::Led => LED_BUDGET,
+            CommandClass::Buzzer => BUZZER_BUDGET,
+            CommandClass::Acknowledge => ACKNOWLEDGE_BUDGET,
+            CommandClass::Emergency => EMERGENCY_BUDGET,
+            CommandClass::Status => STATUS_BUDGET,
+        }
+    }
+}
+
+// Qué bucket se quedó sin tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Source,
+    Class(CommandClass),
+}
+
+impl Limit {
+    pub fn name(self) -> &'static str {
+        match self {
+            Limit::Source => "source",
+            Limit::Class(class) => class.name(),
+        }
+    }
+}
+
+// Comando rechazado por falta de tokens, con lo que queda de cada presupuesto
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub limit: Limit,
+    pub class: CommandClass,
+    // En las emergencias, lo que queda de la asignación de emergencia del emisor
+    pub source_remaining: Option<u32>,
+    pub class_remaining: u32,
+    pub retry_after_ms: u64,
+}
+
+impl core::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.limit {
+            Limit::Source => f.write_str("Source rate limit exceeded"),
+            Limit::Class(class) => write!(f, "Command rate limit exceeded ({})", class.name()),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// Sombra del estado de los LEDs del actuador:
+//
+//   esp32/{device_id}/shadow/desired    lo que quieren los operadores (retenido)
+//   esp32/{device_id}/shadow/reported   lo que hay, en cada cambio (retenido)
+//   esp32/{device_id}/shadow/delta      los LEDs en los que no coinciden
+//
+// Al ser retenido, el desired llega en cuanto el actuador se suscribe, así que
+// tras un reinicio o una reconexión converge a él sin que nadie lo reenvíe.
+// Un cambio local (un botón) no toca el desired: queda como delta hasta que un
+// operador lo actualice o el actuador vuelva a reconciliar.
+//
+// Cualquiera que escriba en el tópico movería los LEDs, así que el desired va
+// firmado y versionado como la política (esp32_common::policy):
+//
+//   {"v":1,"version":4,"from":"node-red-dashboard","sig":"<hex>","leds":[true,null,false]}
+//
+// con HMAC-SHA256 (la clave de `from` en cmd_keys) sobre
+//
+//   esp32-desired-v2\n{device_id}\n{version}\n{from}\n{leds}      leds: "1,,0" para el ejemplo
+//
+// y una versión mayor que la del último desired aceptado. El id del
+// dispositivo va en la firma para que un documento firmado para un actuador
+// no valga para otro, y la versión aceptada se guarda en la NVS para que un
+// documento antiguo retenido o repetido no vuelva a aplicarse tras un reinicio. Los comandos de la
+// reconciliación van a nombre de `from`, así que pasan por la política, el
+// rate limiting y las cuotas como si ese emisor los hubiera enviado.
+
+use crate::command::Command;
+use crate::drivers::led::LED_COUNT;
+use crate::signature::{self, Keyring};
+
+pub const CANONICAL_PREFIX: &str = "esp32-desired-v2";
+
+// None: sin preferencia para ese LED (en el desired) o sin diferencia (en el delta)
+pub type LedMask = [Option<bool>; LED_COUNT];
+
+// Documento recibido por esp32/{device_id}/shadow/desired
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesiredUpdate {
+    pub version: u32,
+    pub from: String,
+    pub signature: String,
+    pub leds: LedMask,
+}
+
+impl DesiredUpdate {
+    // `device_id`: el actuador al que va dirigido, el del tópico
+    pub fn canonical(&self, device_id: &str) -> String {
+        canonical(device_id, self.version, &self.from, &self.leds)
+    }
+
+    // Solo la firma, con la clave de `from` en cmd_keys
+    pub fn verify(&self, keys: &Keyring, device_id: &str) -> Result<(), DesiredError> {
+        let key = keys.key(&self.from).ok_or(DesiredError::UnknownSender)?;
+        let signature = signature::from_hex(&self.signature).ok_or(DesiredError::InvalidSignature)?;
+        // Un salto de línea en `from` haría ambigua la firma
+        if self.from.contains('\n') || !signature::verify(key, &self.canonical(device_id), &signature) {
+            return Err(DesiredError::InvalidSignature);
+        }
+        Ok(())
+    }
+}
+
+fn canonical(device_id: &str, version: u32, from: &str, leds: &LedMask) -> String {
+    let leds = leds
+        .iter()
+        .map(|led| match led {
+            Some(true) => "1",
+            Some(false) => "0",
+            None => "",
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{}\n{}\n{}\n{}\n{}", CANONICAL_PREFIX, device_id, version, from, leds)
+}
+
+// Firma en hexadecimal para el campo `sig` del desired de `device_id`
+pub fn sign(key: &[u8], device_id: &str, from: &str, version: u32, leds: &LedMask) -> String {
+    signature::to_hex(&signature::hmac(key, &canonical(device_id, version, from, leds)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesiredError {
+    UnknownSender,
+    InvalidSignature,
+    StaleVersion,
+    // Documento guardado distinto del registrado como aceptado (al restaurar)
+    NotAccepted,
+}
+
+impl DesiredError {
+    // Motivo estable para el evento desired_rejected
+    pub fn code(self) -> &'static str {
+        match self {
+            DesiredError::UnknownSender => "unknown_sender",
+            DesiredError::InvalidSignature => "invalid_signature",
+            DesiredError::StaleVersion => "stale_version",
+            DesiredError::NotAccepted => "not_accepted",
+        }
+    }
+}
+
+impl core::fmt::Display for DesiredError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            DesiredError::UnknownSender => "No signing key for desired state source",
+            DesiredError::InvalidSignature => "Invalid desired state signature",
+            DesiredError::StaleVersion => "Desired state version is not newer than the active one",
+            DesiredError::NotAccepted => "Stored desired state does not match the accepted version",
+        })
+    }
+}
+
+pub struct Shadow {
+    // Último documento aceptado; None hasta recibir uno
+    desired: Option<DesiredUpdate>,
+    // Versión del último documento aceptado, guardada en la NVS: sigue
+    // valiendo como mínimo aunque el documento no se haya podido restaurar
+    floor: u32,
+    reported: [bool; LED_COUNT],
+    // Último delta publicado, para no repetirlo en cada cambio intermedio
+    published_delta: LedMask,
+}
+
+impl Shadow {
+    // `reported`: estado real de los LEDs al crear la sombra
+    pub fn new(reported: [bool; LED_COUNT]) -> Self {
+        Shadow {
+            desired: None,
+            floor: 0,
+            reported,
+            published_delta: [None; LED_COUNT],
+        }
+    }
+
+    pub fn desired(&self) -> LedMask {
+        self.desired.as_ref().map_or([None; LED_COUNT], |desired| desired.leds)
+    }
+
+    // `floor`: versión aceptada antes del reinicio
+    pub fn with_floor(mut self, floor: u32) -> Self {
+        self.floor = floor;
+        self
+    }
+
+    // 0 mientras no se haya aceptado ningún documento
+    pub fn desired_version(&self) -> u32 {
+        self.desired.as_ref().map_or(0, |desired| desired.version)
+    }
+
+    // Mínimo para el siguiente documento: el aplicado o el de antes del reinicio
+    pub fn floor(&self) -> u32 {
+        self.desired_version().max(self.floor)
+    }
+
+    pub fn reported(&self) -> [bool; LED_COUNT] {
+        self.reported
+    }
+
+    // El documento retenido vuelve en cada suscripción: si es el aplicado no
+    // hace falta comprobarlo otra vez, solo reconciliar
+    pub fn is_current(&self, update: &DesiredUpdate) -> bool {
+        self.desired.as_ref() == Some(update)
+    }
+
+    // Comprueba un documento recibido antes de que sustituya al desired
+    pub fn accept(&self, update: &DesiredUpdate, keys: &Keyring, device_id: &str) -> Result<(), DesiredError> {
+        update.verify(keys, device_id)?;
+        // Como la política: un documento antiguo no devuelve los LEDs a un
+        // estado anterior, tampoco después de un reinicio
+        if update.version <= self.floor() {
+            return Err(DesiredError::StaleVersion);
+        }
+        Ok(())
+    }
+
+    // Desired guardado en la NVS al arrancar: tiene que ser el de la versión
+    // registrada como aceptada y su firma seguir valiendo con las claves
+    // actuales. Así el retenido que llega al suscribirse es el aplicado y
+    // basta con reconciliar.
+    pub fn restore(&mut self, update: DesiredUpdate, keys: &Keyring, device_id: &str) -> Result<(), DesiredError> {
+        update.verify(keys, device_id)?;
+        if update.version != self.floor {
+            return Err(DesiredError::NotAccepted);
+        }
+        self.desired = Some(update);
+        Ok(())
+    }
+
+    pub fn set_desired(&mut self, update: DesiredUpdate) {
+        self.desired = Some(update);
+    }
+
+    // Devuelve true si el estado cambió y hay que publicar un reported nuevo
+    pub fn report(&mut self, leds: [bool; LED_COUNT]) -> bool {
+        let changed = self.reported != leds;
+        self.reported = leds;
+        changed
+    }
+
+    pub fn delta(&self) -> LedMask {
+        let mut delta = [None; LED_COUNT];
+        for (i, wanted) in self.desired().iter().enumerate() {
+            if let Some(on) = *wanted {
+                if self.reported[i] != on {
+                    delta[i] = Some(on);
+                }
+            }
+        }
+        delta
+    }
+
+    pub fn in_sync(&self) -> bool {
+        self.delta().iter().all(Option::is_none)
+    }
+
+    // Delta que hay que publicar: hay divergencia y no es la misma que ya se
+    // publicó. Volver a estar en sincronía rearma la publicación.
+    pub fn take_delta(&mut self) -> Option<LedMask> {
+        let delta = self.delta();
+        if delta == self.published_delta {
+            return None;
+        }
+        self.published_delta = delta;
+        (!self.in_sync()).then_some(delta)
+    }
+
+    // Comandos que llevan los LEDs al desired, a nombre de quien firmó el
+    // documento: el actuador los valida como cualquier comando recibido
+    pub fn reconcile(&self, device_id: &str) -> Vec<Command> {
+        let Some(desired) = &self.desired else {
+            return Vec::new();
+        };
+        self.delta()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, wanted)| {
+                let name = if (*wanted)? { "LED_ON" } else { "LED_OFF" };
+                let mut command = Command::new(&desired.from, device_id, name);
+                command.led_id = Some(i as u8 + 1);
+                Some(command)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,345 @@
+// Firma de los comandos con HMAC-SHA256. Cada emisor (telegram-bot,
+// node-red-dashboard, esp32-sensor-01...) tiene su propia clave; el actuador
+// conoce las de todos (cmd_keys) y antes de validar parámetros comprueba:
+//
+//   - que el comando venga firmado con la clave de su `from`
+//   - que su `to` sea el destino del tópico por el que llegó
+//     (topics::Target::is_addressed_by): uno firmado para otro dispositivo,
+//     grupo o el broadcast no vale republicado en este buzón
+//   - que el `timestamp` (ms Unix) esté a menos de MAX_CLOCK_SKEW_MS de su reloj
+//   - que el `nonce` no se haya usado ya en otro comando
+//
+// La firma cubre esta codificación canónica, un campo por línea y vacío si
+// el campo no viene:
+//
+//   esp32-cmd-v1\n{from}\n{to}\n{command}\n{led_id}\n{duration}\n{emergency}\n{request_id}\n{timestamp}\n{nonce}
+//
+// Un reintento del emisor lleva nonce y timestamp nuevos (y el mismo
+// request_id). Un nonce repetido con el mismo emisor y request_id es el mismo
+// mensaje otra vez: una redelivery de QoS 1 del broker o una repetición de
+// alguien que lo capturó. En los dos casos no se ejecuta; se devuelve
+// Delivery::Redelivered para que el actuador conteste con el resultado
+// original (esp32_common::dedup) si aún lo recuerda y lo descarte si no. Sin
+// request_id no hay resultado que reenviar y es ReplayedNonce.
+
+use std::collections::VecDeque;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::command::Command;
+use crate::message::CommandMessage;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const CANONICAL_PREFIX: &str = "esp32-cmd-v1";
+
+// Diferencia máxima entre el timestamp del comando y el reloj del actuador
+pub const MAX_CLOCK_SKEW_MS: u64 = 30_000;
+
+// Antes de esta fecha (nov. 2023) el reloj no se ha sincronizado por SNTP
+pub const MIN_WALL_CLOCK_MS: u64 = 1_700_000_000_000;
+
+// Nonces recordados como máximo
+pub const MAX_NONCES: usize = 64;
+
+// Claves más cortas no se aceptan en cmd_keys
+pub const MIN_KEY_LEN: usize = 16;
+
+// HMAC-SHA256 en hexadecimal
+pub const SIGNATURE_HEX_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    Unsigned,
+    UnknownSender,
+    InvalidSignature,
+    // El actuador aún no tiene hora: no puede juzgar el timestamp
+    ClockNotSynchronized,
+    StaleTimestamp,
+    ReplayedNonce,
+    // Llegó por el tópico de otro destino que el `to` firmado
+    WrongTarget,
+}
+
+impl SignatureError {
+    // Motivo estable para el evento command_rejected
+    pub fn code(self) -> &'static str {
+        match self {
+            SignatureError::Unsigned => "unsigned",
+            SignatureError::UnknownSender => "unknown_sender",
+            SignatureError::InvalidSignature => "invalid_signature",
+            SignatureError::ClockNotSynchronized => "clock_not_synchronized",
+            SignatureError::StaleTimestamp => "stale_timestamp",
+            SignatureError::ReplayedNonce => "replayed_nonce",
+            SignatureError::WrongTarget => "wrong_target",
+        }
+    }
+}
+
+impl core::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            SignatureError::Unsigned => "Command is not signed",
+            SignatureError::UnknownSender => "No signing key for command source",
+            SignatureError::InvalidSignature => "Invalid command signature",
+            SignatureError::ClockNotSynchronized => "Clock not synchronized, cannot check command timestamp",
+            SignatureError::StaleTimestamp => "Command timestamp outside the allowed window",
+            SignatureError::ReplayedNonce => "Command nonce already used",
+            SignatureError::WrongTarget => "Command addressed to another target than its topic",
+        })
+    }
+}
+
+// Comando con firma válida
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    // Nonce nuevo: se procesa
+    Fresh,
+    // Mismo emisor, request_id y nonce que uno ya verificado
+    Redelivered,
+}
+
+// Lo que añade el emisor al firmar: ms Unix, nonce y HMAC en hexadecimal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signed {
+    pub timestamp: u64,
+    pub nonce: String,
+    pub signature: String,
+}
+
+// Campos firmados, tomados de un comando recibido o de uno por enviar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fields<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub command: &'a str,
+    pub led_id: Option<u8>,
+    pub duration: Option<u64>,
+    pub emergency: Option<bool>,
+    pub request_id: Option<&'a str>,
+    pub timestamp: Option<u64>,
+    pub nonce: Option<&'a str>,
+}
+
+impl<'a> From<&'a Command> for Fields<'a> {
+    fn from(command: &'a Command) -> Self {
+        Fields {
+            from: &command.from,
+            to: &command.to,
+            command: &command.command,
+            led_id: command.led_id,
+            duration: command.duration,
+            emergency: command.emergency,
+            request_id: command.request_id.as_deref(),
+            timestamp: command.signed.as_ref().map(|signed| signed.timestamp),
+            nonce: command.signed.as_ref().map(|signed| signed.nonce.as_str()),
+        }
+    }
+}
+
+impl<'a> From<&CommandMessage<'a>> for Fields<'a> {
+    fn from(message: &CommandMessage<'a>) -> Self {
+        Fields {
+            from: message.from,
+            to: message.to,
+            command: message.command,
+            led_id: message.led_id,
+            duration: message.duration,
+            emergency: message.emergency,
+            request_id: message.request_id,
+            timestamp: message.timestamp,
+            nonce: message.nonce,
+        }
+    }
+}
+
+impl Fields<'_> {
+    pub fn canonical(&self) -> String {
+        fn opt<T: ToString>(value: Option<T>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_default()
+        }
+
+        [
+            CANONICAL_PREFIX.to_string(),
+            self.from.to_string(),
+            self.to.to_string(),
+            self.command.to_string(),
+            opt(self.led_id),
+            opt(self.duration),
+            opt(self.emergency),
+            opt(self.request_id),
+            opt(self.timestamp),
+            opt(self.nonce),
+        ]
+        .join("\n")
+    }
+
+    // Un salto de línea dentro de un campo haría ambigua la codificación
+    fn is_canonical(&self) -> bool {
+        [self.from, self.to, self.command, self.request_id.unwrap_or(""), self.nonce.unwrap_or("")]
+            .iter()
+            .all(|field| !field.contains('\n'))
+    }
+}
+
+fn mac(key: &[u8], message: &str) -> HmacSha256 {
+    // HMAC acepta claves de cualquier longitud
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC key of any length");
+    mac.update(message.as_bytes());
+    mac
+}
+
+// HMAC-SHA256 de una codificación canónica (comandos, política)
+pub fn hmac(key: &[u8], message: &str) -> Vec<u8> {
+    mac(key, message).finalize().into_bytes().to_vec()
+}
+
+// Comparación en tiempo constante (verify_slice)
+pub fn verify(key: &[u8], message: &str, signature: &[u8]) -> bool {
+    mac(key, message).verify_slice(signature).is_ok()
+}
+
+// Firma en hexadecimal para el campo `sig`
+pub fn sign(key: &[u8], fields: &Fields) -> String {
+    to_hex(&hmac(key, &fields.canonical()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Claves por emisor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyring {
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+impl Keyring {
+    // cmd_keys=telegram-bot=<hex>,esp32-sensor-01=<hex>
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut keyring = Keyring::default();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (sender, hex) = entry.split_once('=')?;
+            let key = from_hex(hex.trim()).filter(|key| key.len() >= MIN_KEY_LEN)?;
+            keyring.insert(sender.trim(), key);
+        }
+        Some(keyring)
+    }
+
+    pub fn insert(&mut self, sender: &str, key: Vec<u8>) {
+        self.keys.retain(|(s, _)| s != sender);
+        self.keys.push((sender.to_string(), key));
+    }
+
+    pub fn key(&self, sender: &str) -> Option<&[u8]> {
+        self.keys
+            .iter()
+            .find(|(s, _)| s == sender)
+            .map(|(_, key)| key.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn senders(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|(s, _)| s.as_str())
+    }
+}
+
+struct SeenNonce {
+    from: String,
+    nonce: String,
+    request_id: Option<String>,
+    timestamp: u64,
+}
+
+// Comprueba firma, frescura y nonce de los comandos recibidos
+pub struct Verifier {
+    keys: Keyring,
+    max_skew_ms: u64,
+    seen: VecDeque<SeenNonce>,
+    capacity: usize,
+    // Timestamp del nonce más reciente que se tuvo que olvidar: lo que no sea
+    // posterior ya no se puede comprobar y se trata como caducado
+    floor_ms: u64,
+}
+
+impl Verifier {
+    pub fn new(keys: Keyring) -> Self {
+        Verifier {
+            keys,
+            max_skew_ms: MAX_CLOCK_SKEW_MS,
+            seen: VecDeque::with_capacity(MAX_NONCES),
+            capacity: MAX_NONCES,
+            floor_ms: 0,
+        }
+    }
+
+    pub fn with_limits(mut self, max_skew_ms: u64, capacity: usize) -> Self {
+        self.max_skew_ms = max_skew_ms;
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    // `now_ms`: hora Unix del actuador, None si aún no está sincronizado
+    pub fn verify(&mut self, command: &Command, now_ms: Option<u64>) -> Result<Delivery, SignatureError> {
+        let signed = command.signed.as_deref().ok_or(SignatureError::Unsigned)?;
+        let (timestamp, nonce) = (signed.timestamp, signed.nonce.as_str());
+        let fields = Fields::from(command);
+        let key = self.keys.key(fields.from).ok_or(SignatureError::UnknownSender)?;
+
+        let signature = from_hex(&signed.signature).ok_or(SignatureError::InvalidSignature)?;
+        if !fields.is_canonical() || !verify(key, &fields.canonical(), &signature) {
+            return Err(SignatureError::InvalidSignature);
+        }
+
+        let now_ms = now_ms
+            .filter(|now| *now >= MIN_WALL_CLOCK_MS)
+            .ok_or(SignatureError::ClockNotSynchronized)?;
+        if now_ms.abs_diff(timestamp) > self.max_skew_ms || timestamp <= self.floor_ms {
+            return Err(SignatureError::StaleTimestamp);
+        }
+
+        // Los nonces fuera de la ventana ya no hacen falta: su timestamp los rechaza
+        let oldest = now_ms.saturating_sub(self.max_skew_ms);
+        while self.seen.front().is_some_and(|seen| seen.timestamp < oldest) {
+            self.seen.pop_front();
+        }
+        if let Some(seen) = self.seen.iter().find(|seen| seen.from == fields.from && seen.nonce == nonce) {
+            return match (&seen.request_id, fields.request_id) {
+                (Some(seen), Some(request_id)) if seen == request_id => Ok(Delivery::Redelivered),
+                _ => Err(SignatureError::ReplayedNonce),
+            };
+        }
+
+        if self.seen.len() >= self.capacity {
+            if let Some(evicted) = self.seen.pop_front() {
+                self.floor_ms = self.floor_ms.max(evicted.timestamp);
+            }
+        }
+        // Ordenados por timestamp para poder podar por el frente
+        let position = self.seen.partition_point(|seen| seen.timestamp <= timestamp);
+        self.seen.insert(
+            position,
+            SeenNonce {
+                from: fields.from.to_string(),
+                nonce: nonce.to_string(),
+                request_id: fields.request_id.map(str::to_string),
+                timestamp,
+            },
+        );
+        Ok(Delivery::Fresh)
+    }
+}
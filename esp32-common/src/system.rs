@@ -0,0 +1,29 @@
+// Información del sistema que se reporta en la presencia del dispositivo
+
+// Nombre estable para cada valor de esp_reset_reason_t
+pub fn reset_reason_name(code: u32) -> &'static str {
+    match code {
+        1 => "power_on",
+        2 => "external",
+        3 => "software",
+        4 => "panic",
+        5 => "interrupt_watchdog",
+        6 => "task_watchdog",
+        7 => "watchdog",
+        8 => "deep_sleep",
+        9 => "brownout",
+        10 => "sdio",
+        _ => "unknown",
+    }
+}
+
+#[cfg(target_os = "espidf")]
+extern "C" {
+    fn esp_reset_reason() -> u32;
+}
+
+// Motivo del último reinicio según ESP-IDF
+#[cfg(target_os = "espidf")]
+pub fn reset_reason() -> &'static str {
+    reset_reason_name(unsafe { esp_reset_reason() })
+}
@@ -0,0 +1,20 @@
+// En ESP-IDF cada std::thread es una tarea FreeRTOS; el nombre aparece en los
+// logs del sistema y el stack se reserva al crearla.
+
+use std::thread::{self, JoinHandle};
+
+// Stack por defecto para tareas que solo leen periféricos
+pub const SMALL_STACK_SIZE: usize = 4096;
+// Stack para tareas que formatean JSON o hablan con el cliente MQTT
+pub const LARGE_STACK_SIZE: usize = 8192;
+
+pub fn spawn_task<F>(name: &str, stack_size: usize, f: F) -> JoinHandle<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    thread::Builder::new()
+        .name(name.to_string())
+        .stack_size(stack_size)
+        .spawn(f)
+        .unwrap_or_else(|e| panic!("No se pudo crear la tarea {}: {}", name, e))
+}
@@ -0,0 +1,144 @@
+// Espacio de tópicos MQTT por dispositivo:
+//
+//   esp32/{device_id}/cmd                 comandos para un dispositivo
+//   esp32/group/{group}/cmd               comandos para un grupo
+//   esp32/all/cmd                         comandos para todos
+//   esp32/{device_id}/responses           resultados de los comandos que envió {device_id}
+//   esp32/{device_id}/state               estado de los actuadores
+//   esp32/{device_id}/presence            online/offline (retenido, Last Will)
+//   esp32/{device_id}/heartbeat           heartbeat
+//   esp32/{device_id}/telemetry/{kind}    temperature, button, rfid
+//   esp32/{device_id}/events/{kind}       delivery_failed, ...
+//   esp32/{device_id}/shadow/{doc}        desired, reported, delta (sombra de los LEDs)
+//   esp32/{device_id}/config/policy       política de autorización firmada (retenida)
+//   esp32/{device_id}/quota               cuotas diarias restantes (retenido)
+//
+// Un dispositivo solo se suscribe a su buzón, a sus grupos y al broadcast, así
+// que ya no recibe los comandos de los demás.
+
+use crate::message::MAX_ID_LEN;
+
+pub const ROOT: &str = "esp32";
+pub const BROADCAST_COMMANDS: &str = "esp32/all/cmd";
+
+// Niveles reservados que no pueden usarse como device_id
+const RESERVED: &[&str] = &["all", "group"];
+
+// Un id válido ocupa exactamente un nivel del tópico y no es un comodín
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && !id.contains(['/', '+', '#'])
+        && !RESERVED.contains(&id)
+}
+
+pub fn command(device_id: &str) -> String {
+    format!("{}/{}/cmd", ROOT, device_id)
+}
+
+pub fn group_command(group: &str) -> String {
+    format!("{}/group/{}/cmd", ROOT, group)
+}
+
+pub fn responses(device_id: &str) -> String {
+    format!("{}/{}/responses", ROOT, device_id)
+}
+
+pub fn state(device_id: &str) -> String {
+    format!("{}/{}/state", ROOT, device_id)
+}
+
+pub fn presence(device_id: &str) -> String {
+    format!("{}/{}/presence", ROOT, device_id)
+}
+
+pub fn heartbeat(device_id: &str) -> String {
+    format!("{}/{}/heartbeat", ROOT, device_id)
+}
+
+pub fn telemetry(device_id: &str, kind: &str) -> String {
+    format!("{}/{}/telemetry/{}", ROOT, device_id, kind)
+}
+
+pub fn event(device_id: &str, kind: &str) -> String {
+    format!("{}/{}/events/{}", ROOT, device_id, kind)
+}
+
+pub fn shadow(device_id: &str, doc: &str) -> String {
+    format!("{}/{}/shadow/{}", ROOT, device_id, doc)
+}
+
+pub fn policy(device_id: &str) -> String {
+    format!("{}/{}/config/policy", ROOT, device_id)
+}
+
+pub fn quota(device_id: &str) -> String {
+    format!("{}/{}/quota", ROOT, device_id)
+}
+
+// Tópicos de comandos a los que debe suscribirse un dispositivo
+pub fn command_subscriptions(device_id: &str, groups: &[String]) -> Vec<String> {
+    let mut topics = vec![command(device_id), BROADCAST_COMMANDS.to_string()];
+    topics.extend(groups.iter().map(|g| group_command(g)));
+    topics
+}
+
+// A quién va dirigido un comando según el tópico por el que llegó
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target<'a> {
+    Device(&'a str),
+    Group(&'a str),
+    Broadcast,
+}
+
+impl Target<'_> {
+    pub fn includes(&self, device_id: &str, groups: &[String]) -> bool {
+        match self {
+            Target::Device(id) => *id == device_id,
+            Target::Group(group) => groups.iter().any(|g| g == group),
+            Target::Broadcast => true,
+        }
+    }
+
+    // El `to` (firmado) de un comando es el nivel del tópico al que se envía
+    // (topics::command(to)): el id del dispositivo, "group/{grupo}" o "all".
+    // Un id no contiene '/' ni es "all", así que ninguno se confunde con otro
+    pub fn is_addressed_by(&self, to: &str) -> bool {
+        match self {
+            Target::Device(id) => to == *id,
+            Target::Group(group) => to.strip_prefix("group/") == Some(*group),
+            Target::Broadcast => to == "all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route<'a> {
+    Command(Target<'a>),
+    Responses(&'a str),
+    ShadowDesired(&'a str),
+    Policy(&'a str),
+}
+
+// Clasifica un tópico entrante; None si no es de comandos, respuestas, desired ni política
+pub fn parse(topic: &str) -> Option<Route<'_>> {
+    let mut levels = topic.split('/');
+    if levels.next()? != ROOT {
+        return None;
+    }
+
+    let route = match (levels.next()?, levels.next()?, levels.next()) {
+        ("all", "cmd", None) => Route::Command(Target::Broadcast),
+        ("group", group, Some("cmd")) => Route::Command(Target::Group(group)),
+        (id, "cmd", None) => Route::Command(Target::Device(id)),
+        (id, "responses", None) => Route::Responses(id),
+        (id, "shadow", Some("desired")) => Route::ShadowDesired(id),
+        (id, "config", Some("policy")) => Route::Policy(id),
+        _ => return None,
+    };
+
+    if levels.next().is_some() {
+        return None;
+    }
+    Some(route)
+}
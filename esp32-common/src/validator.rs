@@ -0,0 +1,195 @@
+use crate::clock::Clock;
+use crate::command::{Command, CommandError, ErrorCode, ALLOWED_COMMANDS};
+use crate::policy::Policy;
+use crate::ratelimit::{Budget, CommandClass, Limit, RateLimited, TokenBucket, MAX_SOURCES, SOURCE_EMERGENCY_BUDGET};
+
+// Por qué no se acepta un comando
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    // Comando desconocido o no autorizado por la política
+    Denied(String),
+    // Sin tokens en el presupuesto del emisor o de la clase del comando
+    RateLimited(RateLimited),
+}
+
+impl core::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Rejection::Denied(reason) => f.write_str(reason),
+            Rejection::RateLimited(limited) => limited.fmt(f),
+        }
+    }
+}
+
+impl CommandError for Rejection {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Rejection::Denied(_) => ErrorCode::Unauthorized,
+            Rejection::RateLimited(_) => ErrorCode::RateLimited,
+        }
+    }
+}
+
+struct SourceBucket {
+    source: String,
+    bucket: TokenBucket,
+    // Solo para las paradas de emergencia: agotar `bucket` no las bloquea
+    emergency: TokenBucket,
+    last_used: u64,
+}
+
+// Validador de comandos mejorado: política de autorización y token buckets
+// por emisor y por clase de comando (ver esp32_common::ratelimit)
+pub struct CommandValidator<C: Clock> {
+    clock: C,
+    allowed_commands: &'static [&'static str],
+    policy: Policy,
+    command_count: u32,
+    // Presupuesto de los emisores cuya regla no fija `rate`
+    source_budget: Budget,
+    sources: Vec<SourceBucket>,
+    led: TokenBucket,
+    buzzer: TokenBucket,
+    acknowledge: TokenBucket,
+    emergency: TokenBucket,
+    status: TokenBucket,
+}
+
+impl<C: Clock> CommandValidator<C> {
+    // `max_commands_per_minute` es por emisor: uno muy activo ya no agota el
+    // presupuesto de los demás
+    pub fn new(max_commands_per_minute: u32, clock: C) -> Self {
+        let now = clock.now_ms();
+        let bucket = |class: CommandClass| TokenBucket::new(class.budget(), now);
+        CommandValidator {
+            clock,
+            allowed_commands: ALLOWED_COMMANDS,
+            policy: Policy::default(),
+            command_count: 0,
+            source_budget: Budget::per_minute(max_commands_per_minute),
+            sources: Vec::new(),
+            led: bucket(CommandClass::Led),
+            buzzer: bucket(CommandClass::Buzzer),
+            acknowledge: bucket(CommandClass::Acknowledge),
+            emergency: bucket(CommandClass::Emergency),
+            status: bucket(CommandClass::Status),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.set_policy(policy);
+        self
+    }
+
+    // Los buckets por emisor se descartan: su `rate` puede haber cambiado
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+        self.sources.clear();
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    // Comandos aceptados desde el arranque
+    pub fn command_count(&self) -> u32 {
+        self.command_count
+    }
+
+    pub fn validate_command(&mut self, command: &Command) -> Result<(), Rejection> {
+        let now = self.clock.now_ms();
+
+        // Verificar comando en whitelist
+        if !self.allowed_commands.contains(&command.command.as_str()) {
+            return Err(Rejection::Denied(format!("Command '{}' not allowed", command.command)));
+        }
+
+        // Emisor, comando y parámetros según la política (denegación por defecto).
+        // Lo que no se autoriza no gasta tokens
+        let rate = self.policy.authorize(command).map_err(Rejection::Denied)?.rate;
+
+        let class = CommandClass::of(command);
+        let budget = rate.map(Budget::per_minute).unwrap_or(self.source_budget);
+        let index = self.source_index(&command.from, budget, now);
+
+        if class == CommandClass::Emergency {
+            // Sin pasar por el bucket del emisor ni por los de otras clases,
+            // pero con la asignación de emergencia del emisor: uno solo no
+            // puede gastar el presupuesto compartido de los demás
+            let source_remaining = self.sources[index].emergency.remaining(now);
+            let class_remaining = self.emergency.remaining(now);
+            if source_remaining == 0 || class_remaining == 0 {
+                let (limit, retry_after_ms) = if source_remaining == 0 {
+                    (Limit::Source, self.sources[index].emergency.retry_after_ms(now))
+                } else {
+                    (Limit::Class(class), self.emergency.retry_after_ms(now))
+                };
+                return Err(Rejection::RateLimited(RateLimited {
+                    limit,
+                    class,
+                    source_remaining: Some(source_remaining),
+                    class_remaining,
+                    retry_after_ms,
+                }));
+            }
+            self.sources[index].emergency.try_take(now);
+            self.emergency.try_take(now);
+            self.command_count += 1;
+            return Ok(());
+        }
+
+        // Se comprueban los dos antes de gastar: un rechazo no consume nada
+        let source_remaining = self.sources[index].bucket.remaining(now);
+        let class_remaining = self.class_bucket(class).remaining(now);
+        if source_remaining == 0 || class_remaining == 0 {
+            let (limit, retry_after_ms) = if source_remaining == 0 {
+                (Limit::Source, self.sources[index].bucket.retry_after_ms(now))
+            } else {
+                (Limit::Class(class), self.class_bucket(class).retry_after_ms(now))
+            };
+            return Err(Rejection::RateLimited(RateLimited {
+                limit,
+                class,
+                source_remaining: Some(source_remaining),
+                class_remaining,
+                retry_after_ms,
+            }));
+        }
+
+        self.sources[index].bucket.try_take(now);
+        self.class_bucket(class).try_take(now);
+        self.command_count += 1;
+        Ok(())
+    }
+
+    fn class_bucket(&mut self, class: CommandClass) -> &mut TokenBucket {
+        match class {
+            CommandClass::Led => &mut self.led,
+            CommandClass::Buzzer => &mut self.buzzer,
+            CommandClass::Acknowledge => &mut self.acknowledge,
+            CommandClass::Emergency => &mut self.emergency,
+            CommandClass::Status => &mut self.status,
+        }
+    }
+
+    fn source_index(&mut self, source: &str, budget: Budget, now: u64) -> usize {
+        if let Some(index) = self.sources.iter().position(|s| s.source == source) {
+            self.sources[index].last_used = now;
+            return index;
+        }
+
+        // Se olvida el emisor que lleva más tiempo sin enviar nada
+        if self.sources.len() >= MAX_SOURCES {
+            if let Some(oldest) = (0..self.sources.len()).min_by_key(|&i| self.sources[i].last_used) {
+                self.sources.swap_remove(oldest);
+            }
+        }
+        self.sources.push(SourceBucket {
+            source: source.to_string(),
+            bucket: TokenBucket::new(budget, now),
+            emergency: TokenBucket::new(SOURCE_EMERGENCY_BUDGET, now),
+            last_used: now,
+        });
+        self.sources.len() - 1
+    }
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{MockDelay, MockPin, MockPwm};
+use esp32_common::drivers::{BuzzerController, BuzzerLimits, LedController};
+use esp32_common::actuator::ActuatorError;
+use esp32_common::{Actuator, Command, ErrorCode, ManualClock};
+
+type TestActuator<'a> = Actuator<MockPin, MockPwm, MockDelay, &'a ManualClock>;
+
+fn actuator(clock: &ManualClock, pwm: MockPwm) -> TestActuator<'_> {
+    let leds = LedController::new([MockPin::new(), MockPin::new(), MockPin::new()], clock)
+        .with_rate_limit(1000);
+    let buzzer = BuzzerController::new(pwm, MockDelay::default(), clock);
+    Actuator::new(leds, buzzer)
+}
+
+fn command(name: &str, led_id: Option<u8>) -> Command {
+    let mut command = Command::new("node-red", "esp32-actuator-01", name);
+    command.led_id = led_id;
+    command
+}
+
+#[test]
+fn led_commands_update_state() {
+    let clock = ManualClock::new(0);
+    let mut actuator = actuator(&clock, MockPwm::default());
+
+    assert_eq!(actuator.execute(&command("LED_ON", Some(2))), Ok("LED 2 encendido".to_string()));
+    assert_eq!(actuator.execute(&command("LED_TOGGLE", Some(3))), Ok("LED 3 encendido".to_string()));
+    assert_eq!(actuator.led_states(), [false, true, true]);
+    assert_eq!(actuator.execute(&command("LED_ON", None)), Err(ActuatorError::MissingLedId));
+    assert_eq!(actuator.execute(&command("REBOOT", None)), Err(ActuatorError::Unsupported));
+}
+
+#[test]
+fn emergency_flag_only_applies_when_enabled() {
+    let clock = ManualClock::new(0);
+    let pwm = MockPwm::default();
+    let mut emergency = command("LED_ALL_OFF", None);
+    emergency.emergency = Some(true);
+
+    let mut plain = actuator(&clock, pwm.clone());
+    plain.execute(&command("LED_ALL_ON", None)).unwrap();
+    assert_eq!(plain.execute(&emergency), Ok("Todos los LEDs apagados".to_string()));
+    assert!(pwm.frequencies().is_empty());
+
+    let mut secure = actuator(&clock, pwm.clone()).with_emergency_stop();
+    secure.execute(&command("LED_ON", Some(1))).unwrap();
+    // La parada de emergencia ignora el rate limiting de los LEDs
+    assert_eq!(secure.execute(&emergency), Ok("Emergency shutdown ejecutado".to_string()));
+    assert_eq!(secure.led_states(), [false; 3]);
+    assert_eq!(pwm.frequencies(), vec![1500]);
+}
+
+#[test]
+fn handle_reports_error_codes() {
+    let clock = ManualClock::new(0);
+    let mut actuator = actuator(&clock, MockPwm::default());
+
+    let outcome = actuator.handle(command("LED_ON", Some(1)));
+    assert_eq!(outcome.result, Ok("LED 1 encendido".to_string()));
+
+    let outcome = actuator.handle(command("LED_OFF", Some(1)));
+    assert_eq!(
+        outcome.result,
+        Err((ErrorCode::RateLimited, "LED change rate limit exceeded".to_string()))
+    );
+    assert_eq!(outcome.command.led_id, Some(1));
+
+    let outcome = actuator.handle(command("REBOOT", None));
+    assert_eq!(outcome.result.unwrap_err().0, ErrorCode::UnsupportedCommand);
+}
+
+#[test]
+fn handle_reports_the_tones_that_sounded() {
+    let clock = ManualClock::new(0);
+    let pwm = MockPwm::default();
+    let leds = LedController::new([MockPin::new(), MockPin::new(), MockPin::new()], &clock);
+    let buzzer = BuzzerController::new(pwm, MockDelay::default(), &clock).with_limits(BuzzerLimits::default());
+    let mut actuator = Actuator::new(leds, buzzer);
+
+    assert_eq!(actuator.handle(command("BUZZER_TRIPLE", None)).beeps, 3);
+    assert_eq!(actuator.handle(command("LED_ON", Some(1))).beeps, 0);
+
+    // Dentro del intervalo mínimo no suena nada, ni el beep de ACKNOWLEDGE
+    let rejected = actuator.handle(command("BUZZER", None));
+    assert!(rejected.result.is_err());
+    assert_eq!(rejected.beeps, 0);
+    assert_eq!(actuator.handle(command("ACKNOWLEDGE", None)).beeps, 0);
+
+    clock.advance(2000);
+    let ack = actuator.handle(command("ACKNOWLEDGE", None));
+    assert!(ack.result.is_ok());
+    assert_eq!(ack.beeps, 1);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::released_button;
+use esp32_common::button::{Debouncer, DEBOUNCE_MS};
+use esp32_common::drivers::ButtonBank;
+use esp32_common::ManualClock;
+
+#[test]
+fn press_is_reported_once_per_edge() {
+    let pins = [released_button(), released_button(), released_button()];
+    let clock = ManualClock::new(0);
+    let mut bank = ButtonBank::new(pins.clone(), &clock);
+
+    assert_eq!(bank.check_buttons(), None);
+
+    pins[1].set_level(false);
+    clock.advance(10);
+    assert_eq!(bank.check_buttons(), Some(2));
+    // Mantener presionado no genera más eventos
+    clock.advance(10);
+    assert_eq!(bank.check_buttons(), None);
+    clock.advance(1_000);
+    assert_eq!(bank.check_buttons(), None);
+}
+
+#[test]
+fn bounce_within_debounce_window_is_ignored() {
+    let pins = [released_button(), released_button()];
+    let clock = ManualClock::new(100);
+    let mut bank = ButtonBank::new(pins.clone(), &clock);
+
+    pins[0].set_level(false);
+    assert_eq!(bank.check_buttons(), Some(1));
+
+    // Rebote: suelta y vuelve a bajar dentro de la ventana
+    pins[0].set_level(true);
+    clock.advance(10);
+    assert_eq!(bank.check_buttons(), None);
+    pins[0].set_level(false);
+    clock.advance(10);
+    assert_eq!(bank.check_buttons(), None);
+
+    // Nueva pulsación real después de la ventana
+    pins[0].set_level(true);
+    clock.set(100 + DEBOUNCE_MS);
+    assert_eq!(bank.check_buttons(), None);
+    pins[0].set_level(false);
+    clock.advance(1);
+    assert_eq!(bank.check_buttons(), Some(1));
+}
+
+#[test]
+fn first_press_right_after_boot_is_not_lost() {
+    let mut debouncer = Debouncer::<1>::new();
+
+    assert_eq!(debouncer.update([true], 0), Some(1));
+}
+
+#[test]
+fn buttons_debounce_independently() {
+    let mut debouncer = Debouncer::<2>::with_debounce(200);
+
+    assert_eq!(debouncer.update([true, false], 0), Some(1));
+    assert_eq!(debouncer.update([true, true], 50), Some(2));
+}
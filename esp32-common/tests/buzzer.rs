@@ -0,0 +1,77 @@
+mod common;
+
+use common::{MockDelay, MockPwm, PwmEvent};
+use esp32_common::drivers::{BuzzerController, BuzzerError, BuzzerLimits};
+use esp32_common::ManualClock;
+
+#[test]
+fn beep_sets_frequency_and_turns_off() {
+    let pwm = MockPwm::default();
+    let delay = MockDelay::default();
+    let clock = ManualClock::new(0);
+    let mut buzzer = BuzzerController::new(pwm.clone(), delay.clone(), &clock);
+
+    buzzer.beep(1000, 300).unwrap();
+
+    assert_eq!(
+        *pwm.events.borrow(),
+        vec![
+            PwmEvent::Frequency(1000),
+            PwmEvent::Duty(511),
+            PwmEvent::Duty(0),
+        ]
+    );
+    assert_eq!(delay.total_ms(), 300);
+}
+
+#[test]
+fn parameters_are_validated() {
+    let pwm = MockPwm::default();
+    let clock = ManualClock::new(0);
+    let mut buzzer = BuzzerController::new(pwm.clone(), MockDelay::default(), &clock);
+
+    assert_eq!(buzzer.beep(50, 100), Err(BuzzerError::FrequencyOutOfRange));
+    assert_eq!(buzzer.beep(1000, 6000), Err(BuzzerError::DurationTooLong));
+    assert!(pwm.events.borrow().is_empty());
+}
+
+#[test]
+fn limits_enforce_the_minimum_interval() {
+    let clock = ManualClock::new(0);
+    let limits = BuzzerLimits { min_interval_ms: 2000 };
+    let mut buzzer =
+        BuzzerController::new(MockPwm::default(), MockDelay::default(), &clock).with_limits(limits);
+
+    buzzer.beep(1000, 100).unwrap();
+    clock.advance(1_999);
+    assert_eq!(buzzer.beep(1000, 100), Err(BuzzerError::RateLimited));
+    clock.advance(1);
+    buzzer.beep(1000, 100).unwrap();
+}
+
+#[test]
+fn triple_beep_counts_as_one_beep() {
+    let pwm = MockPwm::default();
+    let clock = ManualClock::new(0);
+    let mut buzzer = BuzzerController::new(pwm.clone(), MockDelay::default(), &clock)
+        .with_limits(BuzzerLimits::default());
+
+    buzzer.triple_beep().unwrap();
+    assert_eq!(pwm.frequencies(), vec![1000, 1000, 1000]);
+
+    clock.advance(2_000);
+    buzzer.beep(1000, 100).unwrap();
+}
+
+#[test]
+fn emergency_beep_bypasses_limits() {
+    let pwm = MockPwm::default();
+    let clock = ManualClock::new(0);
+    let mut buzzer = BuzzerController::new(pwm.clone(), MockDelay::default(), &clock)
+        .with_limits(BuzzerLimits::default());
+
+    buzzer.beep(1000, 100).unwrap();
+    buzzer.emergency_beep().unwrap();
+
+    assert_eq!(pwm.frequencies(), vec![1000, 1500]);
+}
@@ -0,0 +1,114 @@
+use esp32_common::cert::{self, CertError, ExpiryCheck, Validity, RECHECK_INTERVAL_SECS};
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        out.extend([0x82, (content.len() >> 8) as u8, content.len() as u8]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+// Name con atributos de tipo 2.5.4.x, como el subject de openssl req -subj
+fn name(attributes: &[(&str, &str)]) -> Vec<u8> {
+    let mut sets = Vec::new();
+    for (oid, value) in attributes {
+        let attribute_type: u8 = oid.rsplit('.').next().unwrap().parse().unwrap();
+        let mut attribute = tlv(0x06, &[0x55, 0x04, attribute_type]);
+        attribute.extend(tlv(0x0C, value.as_bytes()));
+        sets.extend(tlv(0x31, &tlv(0x30, &attribute)));
+    }
+    tlv(0x30, &sets)
+}
+
+// Certificado con la misma estructura que genera openssl; firma y claves son
+// relleno porque solo se lee el periodo de validez
+fn certificate(not_before: (u8, &str), not_after: (u8, &str), with_version: bool) -> Vec<u8> {
+    let mut tbs = Vec::new();
+    if with_version {
+        tbs.extend(tlv(0xA0, &tlv(0x02, &[2])));
+    }
+    tbs.extend(tlv(0x02, &[0x01, 0x23]));
+    tbs.extend(tlv(0x30, &tlv(0x06, &[0x2A, 0x86, 0x48])));
+    tbs.extend(tlv(0x30, &[0u8; 150]));
+    let mut validity = tlv(not_before.0, not_before.1.as_bytes());
+    validity.extend(tlv(not_after.0, not_after.1.as_bytes()));
+    tbs.extend(tlv(0x30, &validity));
+    tbs.extend(name(&[("2.5.4.10", "ESP32-IoT"), ("2.5.4.3", "esp32-sensor-01")]));
+    tbs.extend(tlv(0x30, &[0u8; 40]));
+
+    let mut body = tlv(0x30, &tbs);
+    body.extend(tlv(0x30, &tlv(0x06, &[0x2A, 0x86, 0x48])));
+    body.extend(tlv(0x03, &[0u8; 64]));
+    tlv(0x30, &body)
+}
+
+#[test]
+fn reads_utc_and_generalized_times() {
+    let der = certificate((0x17, "240101000000Z"), (0x18, "20341231235959Z"), true);
+    assert_eq!(
+        cert::validity(&der),
+        Ok(Validity {
+            not_before: 1_704_067_200,
+            not_after: 2_051_222_399,
+        })
+    );
+
+    // Certificados v1 sin el campo version
+    let v1 = certificate((0x17, "700101000000Z"), (0x17, "491231235959Z"), false);
+    assert_eq!(cert::validity(&v1).unwrap().not_before, 0);
+}
+
+#[test]
+fn check_reports_expired_and_not_yet_valid() {
+    let validity = Validity {
+        not_before: 1000,
+        not_after: 2000,
+    };
+
+    assert_eq!(validity.check(1500), Ok(()));
+    assert_eq!(validity.check(999), Err(CertError::NotYetValid { not_before: 1000 }));
+    assert_eq!(validity.check(2001), Err(CertError::Expired { not_after: 2000 }));
+    assert_eq!(validity.remaining_secs(1500), 500);
+    assert_eq!(validity.remaining_secs(3000), 0);
+}
+
+#[test]
+fn expiry_is_checked_once_the_clock_is_synchronized_and_then_periodically() {
+    let mut check = ExpiryCheck::new();
+
+    // Sin SNTP no se revisa nada
+    assert_eq!(check.due(None), None);
+
+    let synced = 1_700_000_000;
+    assert_eq!(check.due(Some(synced)), Some(synced));
+    assert_eq!(check.due(Some(synced + 30)), None);
+    assert_eq!(check.due(None), None);
+    assert_eq!(check.due(Some(synced + RECHECK_INTERVAL_SECS)), Some(synced + RECHECK_INTERVAL_SECS));
+    assert_eq!(check.due(Some(synced + RECHECK_INTERVAL_SECS + 1)), None);
+}
+
+#[test]
+fn common_name_is_read_from_the_subject() {
+    let der = certificate((0x17, "240101000000Z"), (0x18, "20341231235959Z"), true);
+    assert_eq!(cert::common_name(&der), Ok("esp32-sensor-01"));
+
+    let v1 = certificate((0x17, "700101000000Z"), (0x17, "491231235959Z"), false);
+    assert_eq!(cert::common_name(&v1), Ok("esp32-sensor-01"));
+    assert_eq!(cert::common_name(&[]), Err(CertError::Malformed));
+}
+
+#[test]
+fn garbage_is_malformed_not_a_panic() {
+    assert_eq!(cert::validity(&[]), Err(CertError::Malformed));
+    assert_eq!(cert::validity(&[0x30, 0x82, 0xFF]), Err(CertError::Malformed));
+
+    // Clave privada en lugar de certificado
+    let key = tlv(0x30, &[0x02, 0x01, 0x00, 0x02, 0x01, 0x05]);
+    assert_eq!(cert::validity(&key), Err(CertError::Malformed));
+
+    let bad_date = certificate((0x17, "241301000000Z"), (0x17, "250101000000Z"), true);
+    assert_eq!(cert::validity(&bad_date), Err(CertError::Malformed));
+}
@@ -0,0 +1,296 @@
+// Implementaciones simuladas de los traits de embedded-hal para los tests
+#![allow(dead_code)]
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, InputPin, OutputPin};
+use embedded_hal::pwm::{self, SetDutyCycle};
+use embedded_hal::spi::{self, Operation, SpiDevice};
+use esp32_common::credentials::SecretStore;
+use esp32_common::drivers::Tone;
+use esp32_common::outbox::{Flash, SECTOR_SIZE};
+
+// Pin compartido: el test conserva un clon para leer/forzar el nivel
+#[derive(Clone, Default)]
+pub struct MockPin {
+    level: Rc<Cell<bool>>,
+    writes: Rc<Cell<u32>>,
+}
+
+impl MockPin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self) -> bool {
+        self.level.get()
+    }
+
+    pub fn set_level(&self, high: bool) {
+        self.level.set(high);
+    }
+
+    pub fn writes(&self) -> u32 {
+        self.writes.get()
+    }
+}
+
+impl digital::ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.level.set(false);
+        self.writes.set(self.writes.get() + 1);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.level.set(true);
+        self.writes.set(self.writes.get() + 1);
+        Ok(())
+    }
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.level.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.level.get())
+    }
+}
+
+// Botón con pull-up: suelto = nivel alto
+pub fn released_button() -> MockPin {
+    let pin = MockPin::new();
+    pin.set_level(true);
+    pin
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmEvent {
+    Frequency(u32),
+    Duty(u16),
+}
+
+#[derive(Clone, Default)]
+pub struct MockPwm {
+    pub events: Rc<RefCell<Vec<PwmEvent>>>,
+}
+
+impl MockPwm {
+    pub fn frequencies(&self) -> Vec<u32> {
+        self.events
+            .borrow()
+            .iter()
+            .filter_map(|e| match e {
+                PwmEvent::Frequency(f) => Some(*f),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl pwm::ErrorType for MockPwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for MockPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        1023
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.events.borrow_mut().push(PwmEvent::Duty(duty));
+        Ok(())
+    }
+}
+
+impl Tone for MockPwm {
+    fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), Infallible> {
+        self.events
+            .borrow_mut()
+            .push(PwmEvent::Frequency(frequency_hz));
+        Ok(())
+    }
+}
+
+// Delay que solo acumula el tiempo pedido
+#[derive(Clone, Default)]
+pub struct MockDelay {
+    pub total_ns: Rc<Cell<u64>>,
+}
+
+impl MockDelay {
+    pub fn total_ms(&self) -> u64 {
+        self.total_ns.get() / 1_000_000
+    }
+}
+
+impl DelayNs for MockDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.total_ns.set(self.total_ns.get() + ns as u64);
+    }
+}
+
+// RC522 simulado a nivel de registros sobre SPI
+#[derive(Default)]
+pub struct Rc522State {
+    pub registers: HashMap<u8, u8>,
+    pub writes: Vec<(u8, u8)>,
+    pub fifo: VecDeque<u8>,
+}
+
+#[derive(Clone, Default)]
+pub struct MockRc522 {
+    pub state: Rc<RefCell<Rc522State>>,
+}
+
+impl MockRc522 {
+    // Simula una tarjeta en el campo que responde con `response`
+    pub fn card_responds(&self, response: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        state.fifo = response.iter().copied().collect();
+        state.registers.insert(0x04, 0x30); // COM_IRQ: RxIRq | IdleIRq
+        state.registers.insert(0x06, 0x00); // ERROR_REG sin errores
+    }
+
+    pub fn writes(&self) -> Vec<(u8, u8)> {
+        self.state.borrow().writes.clone()
+    }
+
+    fn read_register(&self, reg: u8) -> u8 {
+        let mut state = self.state.borrow_mut();
+        match reg {
+            0x09 => state.fifo.pop_front().unwrap_or(0),
+            0x0A => state.fifo.len() as u8,
+            _ => state.registers.get(&reg).copied().unwrap_or(0),
+        }
+    }
+
+    fn write_register(&self, reg: u8, value: u8) {
+        let mut state = self.state.borrow_mut();
+        state.writes.push((reg, value));
+        match reg {
+            // Escribir 0x80 en FIFO_LEVEL vacía la FIFO; en FIFO_DATA los datos
+            // son los enviados a la tarjeta, no la respuesta
+            0x0A | 0x09 => {}
+            _ => {
+                state.registers.insert(reg, value);
+            }
+        }
+    }
+}
+
+impl spi::ErrorType for MockRc522 {
+    type Error = Infallible;
+}
+
+impl SpiDevice for MockRc522 {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        for op in operations {
+            match op {
+                Operation::Write(data) => {
+                    let reg = (data[0] >> 1) & 0x3F;
+                    self.write_register(reg, data[1]);
+                }
+                Operation::Transfer(read, write) => {
+                    let reg = (write[0] >> 1) & 0x3F;
+                    read[1] = self.read_register(reg);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Flash NOR en memoria: escribir solo pasa bits de 1 a 0 y borrar deja 0xFF.
+// Compartida para poder "reiniciar" abriendo otro Outbox sobre los mismos datos.
+#[derive(Clone)]
+pub struct MockFlash {
+    data: Rc<RefCell<Vec<u8>>>,
+    pub erases: Rc<Cell<u32>>,
+}
+
+impl MockFlash {
+    pub fn new(sectors: usize) -> Self {
+        MockFlash {
+            data: Rc::new(RefCell::new(vec![0xFF; sectors * SECTOR_SIZE as usize])),
+            erases: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn corrupt(&self, offset: usize) {
+        self.data.borrow_mut()[offset] ^= 0x55;
+    }
+}
+
+impl Flash for MockFlash {
+    type Error = Infallible;
+
+    fn size(&self) -> u32 {
+        self.data.borrow().len() as u32
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Infallible> {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.data.borrow()[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Infallible> {
+        let mut data = self.data.borrow_mut();
+        for (i, &byte) in bytes.iter().enumerate() {
+            let cell = &mut data[offset as usize + i];
+            assert_eq!(*cell & byte, byte, "write at {} needs an erase first", offset as usize + i);
+            *cell = byte;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, offset: u32, len: u32) -> Result<(), Infallible> {
+        assert_eq!(offset % SECTOR_SIZE, 0);
+        assert_eq!(len % SECTOR_SIZE, 0);
+        self.data.borrow_mut()[offset as usize..(offset + len) as usize].fill(0xFF);
+        self.erases.set(self.erases.get() + 1);
+        Ok(())
+    }
+}
+
+// NVS simulada: un mapa de claves; `writes` cuenta las escrituras
+#[derive(Default)]
+pub struct MemoryStore {
+    values: HashMap<String, String>,
+    pub writes: u32,
+}
+
+impl MemoryStore {
+    pub fn with(values: &[(&str, &str)]) -> Self {
+        MemoryStore {
+            values: values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            writes: 0,
+        }
+    }
+}
+
+impl SecretStore for MemoryStore {
+    type Error = Infallible;
+
+    fn get(&self, key: &str) -> Result<Option<String>, Infallible> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Infallible> {
+        self.values.insert(key.to_string(), value.to_string());
+        self.writes += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::MemoryStore;
+use esp32_common::credentials::{self, CredentialError, Credentials, Provisioning, Reply, Requirements};
+use esp32_common::link::NetworkBackend;
+use esp32_common::SecurityConfig;
+
+const KEYS: &str = "telegram-bot=000102030405060708090a0b0c0d0e0f";
+const SIGNED_KEYS: &str =
+    "telegram-bot=000102030405060708090a0b0c0d0e0f,esp32-actuator-01=101112131415161718191a1b1c1d1e1f";
+
+const WIFI: Requirements = Requirements { wifi: true, command_keys: false };
+
+#[test]
+fn missing_credentials_require_provisioning() {
+    let empty = MemoryStore::default();
+    assert_eq!(Credentials::load(&empty, WIFI), Err(CredentialError::Missing(credentials::DEVICE_ID)));
+
+    let no_password = MemoryStore::with(&[("device_id", "esp32-actuator-01"), ("wifi_ssid", "Lab")]);
+    assert_eq!(Credentials::load(&no_password, WIFI), Err(CredentialError::Missing(credentials::WIFI_PASSWORD)));
+
+    let bad_id = MemoryStore::with(&[("device_id", "esp32/+"), ("wifi_ssid", "Lab"), ("wifi_pass", "x")]);
+    assert_eq!(Credentials::load(&bad_id, WIFI), Err(CredentialError::Invalid(credentials::DEVICE_ID)));
+}
+
+#[test]
+fn requirements_depend_on_the_network_and_the_variant() {
+    let ethernet = Requirements::new(NetworkBackend::Ethernet, false);
+    assert_eq!(ethernet.required(), vec!["device_id"]);
+    let only_id = MemoryStore::with(&[("device_id", "esp32-actuator-01")]);
+    assert_eq!(Credentials::load(&only_id, ethernet).unwrap().wifi, None);
+    assert_eq!(Credentials::load(&only_id, WIFI), Err(CredentialError::Missing(credentials::WIFI_SSID)));
+
+    // La variante segura no arranca sin claves, ni sin la del propio dispositivo
+    let secure = Requirements::new(NetworkBackend::Ethernet, true);
+    assert_eq!(secure.required(), vec!["device_id", "cmd_keys"]);
+    assert!(secure.optional().contains(&"wifi_ssid"));
+    assert_eq!(Credentials::load(&only_id, secure), Err(CredentialError::Missing(credentials::COMMAND_KEYS)));
+    let foreign_keys = MemoryStore::with(&[("device_id", "esp32-actuator-01"), ("cmd_keys", KEYS)]);
+    assert_eq!(Credentials::load(&foreign_keys, secure), Err(CredentialError::Invalid(credentials::COMMAND_KEYS)));
+    let own_key = MemoryStore::with(&[("device_id", "esp32-actuator-01"), ("cmd_keys", SIGNED_KEYS)]);
+    assert!(Credentials::load(&own_key, secure).unwrap().command_keys.key("esp32-actuator-01").is_some());
+}
+
+#[test]
+fn provisioning_stores_everything_only_when_complete() {
+    let mut store = MemoryStore::default();
+    let mut provisioning = Provisioning::new(WIFI);
+
+    assert_eq!(provisioning.line("device_id=esp32-actuator-01\r\n", &mut store), Ok(Reply::Set("device_id")));
+    assert_eq!(provisioning.line("wifi_ssid = Lab ", &mut store), Ok(Reply::Set("wifi_ssid")));
+    assert!(provisioning.line("wifi_ssid=", &mut store).is_err());
+    assert!(provisioning.line("cmd_keys=telegram-bot=abcd", &mut store).is_err());
+    assert!(provisioning.line("colour=blue", &mut store).is_err());
+    assert_eq!(provisioning.line(&format!("cmd_keys={}", KEYS), &mut store), Ok(Reply::Set("cmd_keys")));
+
+    // Sin contraseña no se guarda nada
+    assert_eq!(provisioning.missing(), vec!["wifi_pass"]);
+    assert_eq!(provisioning.line("save", &mut store), Err("Missing credentials: wifi_pass".to_string()));
+    assert_eq!(store.writes, 0);
+
+    provisioning.line("wifi_pass= con espacio", &mut store).unwrap();
+    let Ok(Reply::Saved(saved)) = provisioning.line("save", &mut store) else {
+        panic!("las credenciales deberían guardarse");
+    };
+    let wifi = saved.wifi.as_ref().unwrap();
+    assert_eq!(wifi.ssid, "Lab");
+    assert_eq!(wifi.password, " con espacio");
+    assert_eq!(saved.mqtt_username, None);
+    assert!(saved.command_keys.key("telegram-bot").is_some());
+    assert_eq!(Credentials::load(&store, WIFI), Ok(saved));
+}
+
+#[test]
+fn signed_provisioning_needs_the_device_own_key() {
+    let mut store = MemoryStore::default();
+    let mut provisioning = Provisioning::new(Requirements::new(NetworkBackend::Ethernet, true));
+
+    provisioning.line("device_id=esp32-actuator-01", &mut store).unwrap();
+    assert_eq!(provisioning.missing(), vec!["cmd_keys"]);
+    provisioning.line(&format!("cmd_keys={}", KEYS), &mut store).unwrap();
+
+    // Completas pero inválidas juntas: no se escribe nada
+    assert_eq!(provisioning.line("save", &mut store), Err("Invalid credential: cmd_keys".to_string()));
+    assert_eq!(store.writes, 0);
+
+    provisioning.line(&format!("cmd_keys={}", SIGNED_KEYS), &mut store).unwrap();
+    assert!(matches!(provisioning.line("save", &mut store), Ok(Reply::Saved(_))));
+}
+
+#[test]
+fn security_config_takes_secrets_from_credentials() {
+    let store = MemoryStore::with(&[
+        ("device_id", "esp32-sensor-07"),
+        ("wifi_ssid", "Lab"),
+        ("wifi_pass", "secreto"),
+        ("mqtt_user", "sensor-07"),
+        ("mqtt_pass", ""),
+    ]);
+    let config = SecurityConfig::load(Credentials::load(&store, WIFI).unwrap()).unwrap();
+
+    assert_eq!(config.device_id, "esp32-sensor-07");
+    assert_eq!(config.wifi.unwrap().password, "secreto");
+    assert_eq!(config.mqtt_username.as_deref(), Some("sensor-07"));
+    assert_eq!(config.mqtt_password, None);
+    assert!(config.command_keys.is_empty());
+
+    // Sin NETWORK definido la red es WiFi: sin sus credenciales no hay configuración
+    let ethernet_only = MemoryStore::with(&[("device_id", "esp32-sensor-07")]);
+    let credentials = Credentials::load(&ethernet_only, Requirements::new(NetworkBackend::Ethernet, false)).unwrap();
+    assert!(SecurityConfig::load(credentials).is_err());
+}
@@ -0,0 +1,58 @@
+use esp32_common::{Command, CommandOutcome, ErrorCode, RecentCommands, Seen};
+
+fn command(from: &str, request_id: Option<&str>) -> Command {
+    let mut command = Command::new(from, "esp32-actuator-01", "LED_TOGGLE");
+    command.led_id = Some(1);
+    command.request_id = request_id.map(str::to_string);
+    command
+}
+
+#[test]
+fn redelivered_command_gets_original_result() {
+    let mut recent = RecentCommands::new();
+    let first = command("esp32-sensor-01", Some("esp32-sensor-01-1"));
+
+    assert_eq!(recent.check(&first), Seen::New);
+    assert_eq!(recent.check(&first), Seen::InProgress);
+
+    let outcome = CommandOutcome::ok(first.clone(), "LED 1 encendido".to_string());
+    recent.complete(&outcome);
+    assert_eq!(recent.check(&first), Seen::Done(Box::new(outcome)));
+}
+
+#[test]
+fn ids_are_scoped_per_sender_and_missing_ids_always_execute() {
+    let mut recent = RecentCommands::new();
+
+    assert_eq!(recent.check(&command("esp32-sensor-01", Some("1"))), Seen::New);
+    assert_eq!(recent.check(&command("node-red-dashboard", Some("1"))), Seen::New);
+
+    let legacy = command("telegram-bot", None);
+    assert_eq!(recent.check(&legacy), Seen::New);
+    assert_eq!(recent.check(&legacy), Seen::New);
+    assert_eq!(recent.len(), 2);
+}
+
+#[test]
+fn cache_is_bounded_and_forget_allows_retry() {
+    let mut recent = RecentCommands::with_capacity(2);
+    let oldest = command("esp32-sensor-01", Some("a"));
+
+    recent.check(&oldest);
+    recent.check(&command("esp32-sensor-01", Some("b")));
+    recent.check(&command("esp32-sensor-01", Some("c")));
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent.check(&oldest), Seen::New);
+
+    // Rechazado por cola llena: el reintento debe ejecutarse
+    let busy = command("esp32-sensor-01", Some("d"));
+    assert_eq!(recent.check(&busy), Seen::New);
+    recent.forget(&busy);
+    assert_eq!(recent.check(&busy), Seen::New);
+
+    // Un resultado sin check previo (parámetros inválidos) también se recuerda
+    let invalid = command("esp32-sensor-01", Some("e"));
+    let failed = CommandOutcome::with_code(invalid.clone(), ErrorCode::InvalidParameter, "LED ID inválido");
+    recent.complete(&failed);
+    assert_eq!(recent.check(&invalid), Seen::Done(Box::new(failed)));
+}
@@ -0,0 +1,60 @@
+use std::net::Ipv4Addr;
+
+use esp32_common::discovery::{Endpoint, Resolver, Service};
+
+fn broker_at(addr: Option<Ipv4Addr>, hostname: &str) -> Service {
+    Service { hostname: Some(hostname.to_string()), addr, port: 1883 }
+}
+
+#[test]
+fn endpoints_parse_with_an_optional_port() {
+    let plain = Endpoint::parse("192.168.1.100").unwrap();
+    assert_eq!(plain.url("mqtt", 1883), "mqtt://192.168.1.100:1883");
+
+    let with_port = Endpoint::parse("broker.lan:1884").unwrap();
+    assert_eq!(with_port.port, Some(1884));
+    assert_eq!(with_port.url("mqtts", 8883), "mqtts://broker.lan:1884");
+
+    assert_eq!(Endpoint::parse(""), None);
+    assert_eq!(Endpoint::parse("broker.lan:0"), None);
+    assert_eq!(Endpoint::parse("broker.lan:mqtt"), None);
+    assert_eq!(Endpoint::parse("mqtt://broker.lan"), None);
+}
+
+#[test]
+fn configured_address_wins_over_discovery() {
+    let mut resolver = Resolver::new(Endpoint::parse("10.0.0.5"));
+    assert!(!resolver.needs_query());
+    resolver.failed();
+    assert_eq!(resolver.current().unwrap().host, "10.0.0.5");
+    assert!(!resolver.is_discovered());
+}
+
+#[test]
+fn discovery_is_cached_until_a_session_fails() {
+    let mut resolver = Resolver::new(None);
+    assert!(resolver.needs_query());
+
+    // Sin respuestas no hay broker de reserva: no se conecta y se vuelve a preguntar
+    assert_eq!(resolver.discovered(&[]), None);
+    assert_eq!(resolver.current(), None);
+    assert!(resolver.needs_query());
+
+    // Se prefiere la IP y, sin ella, el nombre .local
+    let services = [
+        Service { hostname: None, addr: None, port: 1883 },
+        broker_at(None, "raspberrypi"),
+        broker_at(Some(Ipv4Addr::new(192, 168, 1, 50)), "nas"),
+    ];
+    assert_eq!(resolver.discovered(&services).unwrap().url("mqtt", 1), "mqtt://raspberrypi.local:1883");
+    assert_eq!(
+        resolver.discovered(&services[2..]).unwrap().url("mqtt", 1),
+        "mqtt://192.168.1.50:1883"
+    );
+    assert!(!resolver.needs_query());
+    assert!(resolver.is_discovered());
+
+    resolver.failed();
+    assert!(resolver.needs_query());
+    assert_eq!(resolver.current(), None);
+}
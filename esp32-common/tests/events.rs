@@ -0,0 +1,27 @@
+use esp32_common::event::channel;
+use esp32_common::Event;
+
+#[test]
+fn full_queue_drops_instead_of_blocking() {
+    let (tx, rx) = channel(2);
+    let press = |button_id| Event::ButtonPressed { button_id, timestamp_ms: 0 };
+
+    assert!(tx.post(press(1)));
+    assert!(tx.post(press(2)));
+    assert!(!tx.post(press(3)));
+    assert_eq!(tx.dropped(), 1);
+
+    assert_eq!(rx.try_recv(), Ok(press(1)));
+    assert!(tx.clone().post(press(4)));
+    assert_eq!(rx.try_recv(), Ok(press(2)));
+    assert_eq!(rx.try_recv(), Ok(press(4)));
+}
+
+#[test]
+fn post_fails_once_the_consumer_is_gone() {
+    let (tx, rx) = channel::<Event>(1);
+    drop(rx);
+
+    assert!(!tx.post(Event::LedStates([true; 3])));
+    assert_eq!(tx.dropped(), 0);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::MockPin;
+use esp32_common::drivers::{LedController, LedError};
+use esp32_common::ManualClock;
+
+fn pins() -> [MockPin; 3] {
+    [MockPin::new(), MockPin::new(), MockPin::new()]
+}
+
+#[test]
+fn set_led_drives_the_pin() {
+    let pins = pins();
+    let clock = ManualClock::new(0);
+    let mut leds = LedController::new(pins.clone(), &clock);
+
+    leds.set_led(2, true).unwrap();
+
+    assert!(pins[1].level());
+    assert!(!pins[0].level());
+    assert!(leds.get_state(2));
+}
+
+#[test]
+fn invalid_led_id_is_rejected() {
+    let clock = ManualClock::new(0);
+    let mut leds = LedController::new(pins(), &clock);
+
+    assert_eq!(leds.set_led(0, true), Err(LedError::InvalidId));
+    assert_eq!(leds.set_led(4, true), Err(LedError::InvalidId));
+    assert!(!leds.get_state(4));
+}
+
+#[test]
+fn toggle_flips_state() {
+    let pins = pins();
+    let clock = ManualClock::new(0);
+    let mut leds = LedController::new(pins.clone(), &clock);
+
+    assert_eq!(leds.toggle_led(1), Ok(true));
+    assert!(pins[0].level());
+    assert_eq!(leds.toggle_led(1), Ok(false));
+    assert!(!pins[0].level());
+}
+
+#[test]
+fn second_change_within_1000ms_is_rejected() {
+    let pins = pins();
+    let clock = ManualClock::new(5_000);
+    let mut leds = LedController::new(pins.clone(), &clock).with_rate_limit(1000);
+
+    leds.set_led(1, true).unwrap();
+    clock.advance(999);
+    assert_eq!(leds.set_led(1, false), Err(LedError::RateLimited));
+    // El estado y el pin no cambian cuando se rechaza
+    assert!(leds.get_state(1));
+    assert!(pins[0].level());
+
+    // Otro LED tiene su propio límite
+    leds.set_led(2, true).unwrap();
+
+    clock.advance(1);
+    leds.set_led(1, false).unwrap();
+    assert!(!pins[0].level());
+}
+
+#[test]
+fn first_change_after_boot_is_allowed() {
+    let clock = ManualClock::new(0);
+    let mut leds = LedController::new(pins(), &clock).with_rate_limit(1000);
+
+    leds.set_led(1, true).unwrap();
+}
+
+#[test]
+fn rejected_toggle_keeps_state() {
+    let clock = ManualClock::new(100);
+    let mut leds = LedController::new(pins(), &clock).with_rate_limit(1000);
+
+    assert_eq!(leds.toggle_led(3), Ok(true));
+    clock.advance(100);
+    assert_eq!(leds.toggle_led(3), Err(LedError::RateLimited));
+    assert!(leds.get_state(3));
+}
+
+#[test]
+fn emergency_shutdown_ignores_rate_limit() {
+    let pins = pins();
+    let clock = ManualClock::new(0);
+    let mut leds = LedController::new(pins.clone(), &clock).with_rate_limit(1000);
+
+    leds.turn_on_all();
+    leds.emergency_shutdown();
+
+    assert_eq!(leds.states(), [false; 3]);
+    assert!(pins.iter().all(|p| !p.level()));
+    // Tras la emergencia se puede volver a encender de inmediato
+    leds.set_led(1, true).unwrap();
+}
@@ -0,0 +1,65 @@
+use esp32_common::link::{Backoff, Link, LinkState, NetworkBackend, BACKOFF_BASE_MS, BACKOFF_MAX_MS};
+
+#[test]
+fn backoff_grows_exponentially_with_jitter_up_to_the_cap() {
+    let mut backoff = Backoff::new(0xC0FFEE);
+
+    let mut ceiling = BACKOFF_BASE_MS;
+    for _ in 0..12 {
+        let delay = backoff.next_delay_ms();
+        assert!(delay >= ceiling / 2 && delay <= ceiling, "{} fuera de [{}, {}]", delay, ceiling / 2, ceiling);
+        ceiling = (ceiling * 2).min(BACKOFF_MAX_MS);
+    }
+    assert_eq!(backoff.attempt(), 12);
+
+    // Tras conectar vuelve a empezar desde la espera base
+    backoff.reset();
+    assert!(backoff.next_delay_ms() <= BACKOFF_BASE_MS);
+}
+
+#[test]
+fn jitter_spreads_devices_with_different_seeds() {
+    let delays: Vec<u64> = (1..=8)
+        .map(|seed| {
+            let mut backoff = Backoff::new(seed).with_limits(1000, 60_000);
+            (0..5).map(|_| backoff.next_delay_ms()).last().unwrap()
+        })
+        .collect();
+
+    assert!(delays.iter().any(|&d| d != delays[0]));
+
+    // Sin overflow aunque los fallos se acumulen durante horas
+    let mut backoff = Backoff::new(7);
+    for _ in 0..200 {
+        assert!(backoff.next_delay_ms() <= BACKOFF_MAX_MS);
+    }
+}
+
+#[test]
+fn link_state_is_shared_and_counts_reconnects() {
+    let link = Link::new();
+    let view = link.clone();
+    assert_eq!(view.state(), LinkState::Down);
+
+    link.set(LinkState::WifiConnecting);
+    assert_eq!(view.state(), LinkState::WifiConnecting);
+
+    link.connected();
+    assert!(view.is_connected());
+    assert_eq!(view.reconnects(), 0);
+
+    link.failed();
+    link.failed();
+    assert_eq!(view.state(), LinkState::Down);
+    link.connected();
+    assert_eq!(view.reconnects(), 1);
+    assert_eq!(view.failures(), 2);
+}
+
+#[test]
+fn network_backend_is_chosen_by_name() {
+    assert_eq!(NetworkBackend::parse("wifi"), Some(NetworkBackend::Wifi));
+    assert_eq!(NetworkBackend::parse("ethernet"), Some(NetworkBackend::Ethernet));
+    assert_eq!(NetworkBackend::parse("openeth"), None);
+    assert_eq!(NetworkBackend::Ethernet.name(), "Ethernet");
+}
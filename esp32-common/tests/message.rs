@@ -0,0 +1,285 @@
+use esp32_common::message::{
+    decode, decode_command, decode_response, encode, encode_as, uid_hex, ButtonEvent, CommandMessage, CommandResult, Heartbeat, Presence, TemperatureReading,
+};
+use esp32_common::system::reset_reason_name;
+use esp32_common::{
+    topics, Command, CommandError, CommandOutcome, DecodeError, EncodeError, Encoding, ErrorCode, Publication, RequestIds, SCHEMA_VERSION,
+};
+use esp32_common::actuator::ActuatorError;
+use esp32_common::command::ParameterError;
+use esp32_common::drivers::{BuzzerError, LedError};
+use esp32_common::quota::QuotaError;
+
+fn encode_to_string<T: serde::Serialize>(message: &T) -> String {
+    let mut buf = [0u8; 256];
+    let len = encode(message, &mut buf).unwrap();
+    String::from_utf8(buf[..len].to_vec()).unwrap()
+}
+
+#[test]
+fn button_event_carries_schema_version() {
+    let event = ButtonEvent {
+        v: SCHEMA_VERSION,
+        device: "esp32-sensor-01",
+        button_id: 2,
+        action: "pressed",
+        boot: None,
+        timestamp: 1234,
+        security: None,
+    };
+
+    assert_eq!(
+        encode_to_string(&event),
+        r#"{"v":1,"device":"esp32-sensor-01","button_id":2,"action":"pressed","timestamp":1234}"#
+    );
+}
+
+#[test]
+fn optional_fields_are_emitted_only_when_set() {
+    let reading = TemperatureReading {
+        v: SCHEMA_VERSION,
+        device: "esp32-sensor-01",
+        temp: 23.5,
+        hum: 0.0,
+        boot: None,
+        timestamp: 10,
+        validated: Some(true),
+    };
+    assert!(encode_to_string(&reading).ends_with(r#""timestamp":10,"validated":true}"#));
+
+    let heartbeat = Heartbeat {
+        v: SCHEMA_VERSION,
+        device: "esp32-actuator-01",
+        status: "online",
+        uptime: 30,
+        security: Some("enabled"),
+        commands_processed: Some(4),
+        dropped_events: None,
+        reconnects: None,
+        outbox_pending: None,
+        outbox_dropped: None,
+        outbox_policy: None,
+        policy_version: None,
+    };
+    assert_eq!(
+        encode_to_string(&heartbeat),
+        r#"{"v":1,"device":"esp32-actuator-01","status":"online","uptime":30,"security":"enabled","commands_processed":4}"#
+    );
+}
+
+#[test]
+fn long_device_id_is_an_error_not_a_panic() {
+    let device = "x".repeat(300);
+    let event = ButtonEvent {
+        v: SCHEMA_VERSION,
+        device: &device,
+        button_id: 1,
+        action: "pressed",
+        boot: None,
+        timestamp: 0,
+        security: None,
+    };
+
+    assert_eq!(encode(&event, &mut [0u8; 128]), Err(EncodeError::BufferFull));
+}
+
+#[test]
+fn telemetry_carries_the_boot_it_was_produced_in() {
+    // Un registro reenviado desde el outbox tras un reinicio: su timestamp es
+    // del arranque 6, no del actual
+    let event = ButtonEvent {
+        v: SCHEMA_VERSION,
+        device: "esp32-sensor-01",
+        button_id: 1,
+        action: "pressed",
+        boot: Some(6),
+        timestamp: 1234,
+        security: None,
+    };
+    assert_eq!(
+        encode_to_string(&event),
+        r#"{"v":1,"device":"esp32-sensor-01","button_id":1,"action":"pressed","boot":6,"timestamp":1234}"#
+    );
+
+    let mut online = Presence::online("esp32-sensor-01", "0.1.0", "software");
+    online.boot = Some(7);
+    assert!(encode_to_string(&online).ends_with(r#""boot_reason":"software","boot":7}"#));
+}
+
+#[test]
+fn uid_is_upper_hex() {
+    assert_eq!(uid_hex(&[0xA1, 0x02, 0xFF, 0x00]).as_str(), "A102FF00");
+}
+
+#[test]
+fn command_round_trips() {
+    let mut message = CommandMessage::new("esp32-sensor-01", "esp32-actuator-01", "LED_TOGGLE");
+    message.led_id = Some(1);
+    message.request_id = Some("esp32-sensor-01-7");
+    let json = encode_to_string(&message);
+
+    let command = Command::from_json(&json).unwrap();
+    assert_eq!(command.from, "esp32-sensor-01");
+    assert_eq!(command.command, "LED_TOGGLE");
+    assert_eq!(command.led_id, Some(1));
+    assert_eq!(command.duration, None);
+    assert_eq!(command.request_id.as_deref(), Some("esp32-sensor-01-7"));
+}
+
+#[test]
+fn command_decoding_handles_real_json() {
+    // Espacios, strings escapados, orden arbitrario y una clave dentro de un valor
+    let json = r#" { "command" : "BUZZER", "note": "\"to\":\"x\"", "from":"node-red",
+        "to" : "esp32-actuator-01", "duration" : 750, "extra": [1, {"a": null}] } "#;
+
+    let command = Command::from_json(json).unwrap();
+    assert_eq!(command.to, "esp32-actuator-01");
+    assert_eq!(command.from, "node-red");
+    assert_eq!(command.duration, Some(750));
+}
+
+#[test]
+fn legacy_payload_without_version_is_accepted() {
+    let command = Command::from_json(r#"{"from":"telegram-bot","to":"esp32-actuator-01","command":"LED_ALL_ON"}"#).unwrap();
+    assert_eq!(command.command, "LED_ALL_ON");
+}
+
+#[test]
+fn decode_errors_are_explicit() {
+    assert!(matches!(
+        Command::from_json(r#"{"v":9,"from":"a","to":"b","command":"LED_ON"}"#),
+        Err(DecodeError::UnsupportedVersion(9))
+    ));
+    // Número negativo para un campo sin signo
+    assert!(matches!(
+        Command::from_json(r#"{"from":"a","to":"b","command":"LED_ON","led_id":-1}"#),
+        Err(DecodeError::Json(_))
+    ));
+    // Falta un campo obligatorio
+    assert!(matches!(
+        Command::from_json(r#"{"from":"a","command":"LED_ON"}"#),
+        Err(DecodeError::Json(_))
+    ));
+    assert_eq!(
+        decode_command(&[0xFF, 0xFE]),
+        Err(DecodeError::NotUtf8)
+    );
+}
+
+#[test]
+fn result_is_addressed_to_the_sender() {
+    let mut command = Command::new("telegram-bot", "esp32-actuator-01", "BUZZER");
+    command.request_id = Some("tg-42".to_string());
+
+    let outcome = CommandOutcome::failed(command, &QuotaError::Buzzer);
+    assert_eq!(
+        encode_to_string(&CommandResult::new("esp32-actuator-01", &outcome, [true, false, false], 99)),
+        r#"{"v":1,"device":"esp32-actuator-01","to":"telegram-bot","request_id":"tg-42","command":"BUZZER","status":"error","code":"quota_exceeded","message":"Daily buzzer limit exceeded","leds":[true,false,false],"timestamp":99}"#
+    );
+
+    let outcome = CommandOutcome::ok(Command::new("node-red", "esp32-actuator-01", "LED_ALL_ON"), "Todos los LEDs encendidos".to_string());
+    assert_eq!(
+        encode_to_string(&CommandResult::new("esp32-actuator-01", &outcome, [true; 3], 5)),
+        r#"{"v":1,"device":"esp32-actuator-01","to":"node-red","command":"LED_ALL_ON","status":"ok","message":"Todos los LEDs encendidos","leds":[true,true,true],"timestamp":5}"#
+    );
+}
+
+#[test]
+fn presence_is_retained_and_last_will_is_minimal() {
+    let online = Presence::online("esp32-sensor-01", "0.1.0", reset_reason_name(4));
+    let publication = Publication::encode(&topics::presence("esp32-sensor-01"), &online).unwrap().retained();
+    assert_eq!(publication.topic, "esp32/esp32-sensor-01/presence");
+    assert!(publication.retain);
+    assert_eq!(
+        String::from_utf8(publication.payload).unwrap(),
+        r#"{"v":1,"device":"esp32-sensor-01","status":"online","firmware":"0.1.0","boot_reason":"panic"}"#
+    );
+
+    assert_eq!(
+        encode_to_string(&Presence::offline("esp32-sensor-01")),
+        r#"{"v":1,"device":"esp32-sensor-01","status":"offline"}"#
+    );
+    assert!(!Publication::new("esp32/esp32-sensor-01/telemetry/button", b"{}").retain);
+    assert_eq!(reset_reason_name(0), "unknown");
+    assert_eq!(reset_reason_name(99), "unknown");
+}
+
+#[test]
+fn errors_carry_their_code() {
+    assert_eq!(ParameterError::DurationTooLong.code(), ErrorCode::InvalidParameter);
+    assert_eq!(ParameterError::DurationTooLong.to_string(), "Duration cannot exceed 10000ms");
+    assert_eq!(LedError::RateLimited.code(), ErrorCode::RateLimited);
+    assert_eq!(BuzzerError::PwmDuty.code(), ErrorCode::HardwareError);
+    assert_eq!(ActuatorError::Unsupported.code(), ErrorCode::UnsupportedCommand);
+    // El del driver se mantiene a través del actuador
+    assert_eq!(ActuatorError::from(BuzzerError::RateLimited).code(), ErrorCode::RateLimited);
+    assert_eq!(ActuatorError::from(LedError::InvalidId).to_string(), "LED ID must be between 1 and 3");
+    assert_eq!(QuotaError::Commands.code(), ErrorCode::QuotaExceeded);
+}
+
+#[test]
+fn request_ids_are_unique_per_sender() {
+    let mut ids = RequestIds::new("esp32-sensor-01");
+    assert_eq!(ids.next_id(), "esp32-sensor-01-1");
+    assert_eq!(ids.next_id(), "esp32-sensor-01-2");
+}
+
+#[test]
+fn response_decodes_for_the_sender() {
+    let mut command = Command::new("esp32-sensor-01", "esp32-actuator-01", "LED_TOGGLE");
+    command.request_id = Some("esp32-sensor-01-3".to_string());
+    let outcome = CommandOutcome::ok(command, "LED 1 encendido".to_string());
+    let json = encode_to_string(&CommandResult::new("esp32-actuator-01", &outcome, [true, false, false], 7));
+
+    let response = decode_response(json.as_bytes()).unwrap();
+    assert_eq!(response.to, "esp32-sensor-01");
+    assert_eq!(response.request_id.as_deref(), Some("esp32-sensor-01-3"));
+    assert!(response.ok);
+    assert_eq!(response.message, "LED 1 encendido");
+}
+
+#[test]
+fn cbor_is_smaller_and_decodes_like_json() {
+    let reading = TemperatureReading {
+        v: SCHEMA_VERSION,
+        device: "esp32-sensor-01",
+        temp: 23.5,
+        hum: 0.0,
+        boot: None,
+        timestamp: 1_700_000_000_000,
+        validated: Some(true),
+    };
+
+    let json = Publication::encode_as("t", &reading, Encoding::Json).unwrap();
+    let cbor = Publication::encode_as("t", &reading, Encoding::Cbor).unwrap();
+    assert!(cbor.payload.len() < json.payload.len());
+    assert_eq!(cbor.encoding, Encoding::Cbor);
+
+    // El consumidor no necesita saber cuál le llegó
+    assert_eq!(decode::<TemperatureReading>(&json.payload), Ok(reading.clone()));
+    assert_eq!(decode::<TemperatureReading>(&cbor.payload), Ok(reading));
+}
+
+#[test]
+fn commands_and_responses_decode_from_cbor() {
+    let mut message = CommandMessage::new("esp32-sensor-01", "esp32-actuator-01", "BUZZER");
+    message.duration = Some(500);
+    message.request_id = Some("esp32-sensor-01-3");
+    let mut buf = [0u8; 256];
+    let len = encode_as(&message, Encoding::Cbor, &mut buf).unwrap();
+    assert_eq!(Encoding::detect(&buf[..len]), Some(Encoding::Cbor));
+
+    let command = decode_command(&buf[..len]).unwrap();
+    assert_eq!(command, Command::from_json(&encode_to_string(&message)).unwrap());
+
+    let outcome = CommandOutcome::with_code(command, ErrorCode::Busy, "Actuator queue full");
+    let result = CommandResult::new("esp32-actuator-01", &outcome, [false; 3], 5);
+    let len = encode_as(&result, Encoding::Cbor, &mut buf).unwrap();
+    let response = decode_response(&buf[..len]).unwrap();
+    assert_eq!(response.request_id.as_deref(), Some("esp32-sensor-01-3"));
+    assert!(!response.ok);
+
+    // Ni '{' ni mapa CBOR
+    assert_eq!(decode_command(b"LED_ON"), Err(DecodeError::UnknownEncoding));
+    assert!(matches!(decode_command(&[0xA1, 0x61]), Err(DecodeError::Cbor(_))));
+}
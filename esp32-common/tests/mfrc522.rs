@@ -0,0 +1,92 @@
+mod common;
+
+use common::{MockDelay, MockPin, MockRc522};
+use esp32_common::drivers::mfrc522::*;
+use esp32_common::drivers::Mfrc522;
+
+fn reader() -> (MockRc522, MockPin, Mfrc522<MockRc522, MockPin, MockDelay>) {
+    let spi = MockRc522::default();
+    let rst = MockPin::new();
+    let rfid = Mfrc522::new(spi.clone(), rst.clone(), MockDelay::default());
+    (spi, rst, rfid)
+}
+
+#[test]
+fn init_resets_and_configures_timer_and_antenna() {
+    let (spi, rst, mut rfid) = reader();
+
+    rfid.init();
+
+    assert!(rst.level());
+    assert_eq!(
+        spi.writes(),
+        vec![
+            (COMMAND_REG, PCD_SOFT_RESET),
+            (T_MODE_REG, 0x8D),
+            (T_PRESCALER_REG, 0x3E),
+            (T_RELOAD_REG_L, 30),
+            (T_RELOAD_REG_H, 0),
+            (TX_AUTO_REG, 0x40),
+            (MODE_REG, 0x3D),
+            (TX_CONTROL_REG, 0x03),
+        ]
+    );
+}
+
+#[test]
+fn request_without_card_times_out() {
+    let (_, _, mut rfid) = reader();
+
+    assert_eq!(rfid.request(), None);
+}
+
+#[test]
+fn request_returns_atqa_and_sends_reqidl() {
+    let (spi, _, mut rfid) = reader();
+    spi.card_responds(&[0x04, 0x00]);
+
+    assert_eq!(rfid.request(), Some([0x04, 0x00]));
+
+    let writes = spi.writes();
+    assert_eq!(writes[0], (BIT_FRAMING_REG, 0x07));
+    assert!(writes.contains(&(FIFO_DATA_REG, PICC_REQIDL)));
+    assert!(writes.contains(&(COMMAND_REG, PCD_TRANSCEIVE)));
+    // StartSend se activa y se vuelve a limpiar
+    assert!(writes.contains(&(BIT_FRAMING_REG, 0x87)));
+    assert_eq!(writes.last(), Some(&(BIT_FRAMING_REG, 0x07)));
+}
+
+#[test]
+fn anticoll_returns_uid_with_valid_bcc() {
+    let (spi, _, mut rfid) = reader();
+    let uid = [0xDE, 0xAD, 0xBE, 0xEF];
+    let bcc = uid.iter().fold(0, |acc, b| acc ^ b);
+    spi.card_responds(&[uid[0], uid[1], uid[2], uid[3], bcc]);
+
+    assert_eq!(rfid.anticoll(), Some([0xDE, 0xAD, 0xBE, 0xEF, bcc]));
+
+    let writes = spi.writes();
+    let sent: Vec<u8> = writes
+        .iter()
+        .filter(|(reg, _)| *reg == FIFO_DATA_REG)
+        .map(|(_, v)| *v)
+        .collect();
+    assert_eq!(sent, vec![PICC_ANTICOLL, 0x20]);
+}
+
+#[test]
+fn anticoll_rejects_bad_bcc() {
+    let (spi, _, mut rfid) = reader();
+    spi.card_responds(&[0x01, 0x02, 0x03, 0x04, 0xFF]);
+
+    assert_eq!(rfid.anticoll(), None);
+}
+
+#[test]
+fn error_register_aborts_read() {
+    let (spi, _, mut rfid) = reader();
+    spi.card_responds(&[0x04, 0x00]);
+    spi.state.borrow_mut().registers.insert(ERROR_REG, 0x02);
+
+    assert_eq!(rfid.request(), None);
+}
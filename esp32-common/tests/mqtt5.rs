@@ -0,0 +1,87 @@
+use esp32_common::mqtt5::{Negotiation, Properties, ProtocolPreference, ProtocolVersion, ReplyTo};
+
+#[test]
+fn auto_falls_back_to_311_after_repeated_refusals() {
+    let mut negotiation = Negotiation::new(ProtocolPreference::Auto);
+    assert_eq!(negotiation.current(), ProtocolVersion::V5);
+
+    // Broker caído, DNS, TCP o TLS: sin CONNACK no se sabe nada del protocolo
+    for _ in 0..5 {
+        assert!(!negotiation.refused(None));
+    }
+    // Ni un CONNACK rechazado por otro motivo (0x87: not authorized)
+    assert!(!negotiation.refused(Some(0x87)));
+    assert!(!negotiation.refused(Some(0x87)));
+    assert_eq!(negotiation.current(), ProtocolVersion::V5);
+
+    // Una sesión establecida entre rechazos pone la cuenta a cero
+    assert!(!negotiation.refused(Some(0x84)));
+    negotiation.connected();
+    assert!(!negotiation.refused(Some(0x84)));
+    assert_eq!(negotiation.current(), ProtocolVersion::V5);
+
+    // Un broker 3.1.1 contesta con su propio código
+    assert!(negotiation.refused(Some(0x01)));
+    assert_eq!(negotiation.current(), ProtocolVersion::V311);
+    assert!(!negotiation.refused(Some(0x01)));
+    assert_eq!(negotiation.current(), ProtocolVersion::V311);
+
+    // Con la versión fijada no se cambia nunca
+    let mut forced = Negotiation::new(ProtocolPreference::parse("5").unwrap());
+    for _ in 0..5 {
+        assert!(!forced.refused(Some(0x84)));
+    }
+    assert_eq!(forced.current(), ProtocolVersion::V5);
+    assert_eq!(ProtocolPreference::parse("3"), None);
+}
+
+#[test]
+fn command_properties_round_trip_to_a_reply() {
+    let sent = Properties::command("esp32-sensor-01", "esp32-sensor-01-7", 30);
+    assert_eq!(sent.message_expiry_secs, Some(30));
+    assert_eq!(sent.sender(), Some("esp32-sensor-01"));
+
+    let reply_to = sent.reply_to("esp32-sensor-01").unwrap();
+    assert_eq!(
+        reply_to,
+        ReplyTo {
+            topic: "esp32/esp32-sensor-01/responses".to_string(),
+            correlation: b"esp32-sensor-01-7".to_vec(),
+        }
+    );
+
+    let reply = Properties::reply(&reply_to, "esp32-actuator-01");
+    assert_eq!(reply.correlation_data.as_deref(), Some(&b"esp32-sensor-01-7"[..]));
+    assert_eq!(reply.response_topic, None);
+    assert!(Properties::default().is_empty());
+}
+
+#[test]
+fn unusable_reply_topics_and_spoofed_senders_are_rejected() {
+    let mut properties = Properties::command("node-red", "nr-1", 60);
+    assert!(properties.reply_to("node-red").is_some());
+    // El response topic no va firmado: solo vale el buzón de quien firmó
+    assert_eq!(properties.reply_to("telegram-bot"), None);
+
+    properties.response_topic = Some("esp32/+/responses".to_string());
+    assert_eq!(properties.reply_to("node-red"), None);
+    properties.response_topic = Some("esp32/telegram-bot/responses".to_string());
+    assert_eq!(properties.reply_to("node-red"), None);
+
+    properties.response_topic = Some("esp32/node-red/responses".to_string());
+    properties.correlation_data = Some(vec![0; 65]);
+    assert_eq!(properties.reply_to("node-red"), None);
+
+    // La user property tiene que coincidir con el `from` del payload
+    assert_eq!(properties.check_sender("node-red"), Ok(()));
+    let error = properties.check_sender("esp32-sensor-01").unwrap_err();
+    assert!(error.starts_with("Untrusted command source"));
+
+    // Con propiedades MQTT 5 es obligatoria
+    properties.user_properties.clear();
+    let error = properties.check_sender("node-red").unwrap_err();
+    assert!(error.starts_with("Untrusted command source"));
+
+    // Un cliente 3.1.1 no trae ninguna: no hay nada que comparar
+    assert_eq!(Properties::default().check_sender("esp32-sensor-01"), Ok(()));
+}
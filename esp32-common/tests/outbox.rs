@@ -0,0 +1,133 @@
+mod common;
+
+use common::MockFlash;
+use esp32_common::outbox::{Outbox, OutboxError, OverflowPolicy, SECTOR_SIZE};
+use esp32_common::message::Presence;
+use esp32_common::{Encoding, Publication};
+
+fn reading(n: u32) -> Publication {
+    let payload = format!(r#"{{"v":1,"device":"esp32-sensor-01","temp":21.5,"hum":0.0,"timestamp":{}}}"#, n);
+    Publication::new("esp32/esp32-sensor-01/telemetry/temperature", payload.as_bytes()).durable()
+}
+
+fn drain<F: esp32_common::outbox::Flash>(outbox: &mut Outbox<F>) -> Vec<Publication> {
+    let mut sent = Vec::new();
+    outbox
+        .replay(usize::MAX, |p| {
+            sent.push(p.clone());
+            true
+        })
+        .unwrap();
+    sent
+}
+
+#[test]
+fn replays_in_order_and_survives_a_reboot() {
+    let flash = MockFlash::new(4);
+    let mut outbox = Outbox::open(flash.clone(), 32, OverflowPolicy::DropOldest).unwrap();
+    for n in 1..=5 {
+        outbox.push(&reading(n)).unwrap();
+    }
+    let presence = Presence::offline("esp32-sensor-01");
+    let presence = Publication::encode_as("esp32/esp32-sensor-01/presence", &presence, Encoding::Cbor).unwrap();
+    outbox.push(&presence.clone().retained()).unwrap();
+
+    // Se envían dos y el dispositivo se reinicia
+    let mut first = Vec::new();
+    let sent = outbox
+        .replay(2, |p| {
+            first.push(p.clone());
+            true
+        })
+        .unwrap();
+    assert_eq!(sent, 2);
+    assert_eq!(first, vec![reading(1), reading(2)]);
+    drop(outbox);
+
+    let mut outbox = Outbox::open(flash.clone(), 32, OverflowPolicy::DropOldest).unwrap();
+    assert_eq!(outbox.len(), 4);
+    assert_eq!(outbox.stats().pending(), 4);
+
+    // Lo nuevo va detrás de lo que quedó pendiente
+    outbox.push(&reading(6)).unwrap();
+    let rest = drain(&mut outbox);
+    assert_eq!(rest.len(), 5);
+    assert_eq!(rest[..3], [reading(3), reading(4), reading(5)]);
+    assert!(rest[3].retain && !rest[0].retain);
+    assert_eq!(rest[3].payload, presence.payload);
+    assert_eq!(rest[3].encoding, Encoding::Cbor);
+    assert_eq!(rest[4], reading(6));
+    assert!(outbox.is_empty());
+}
+
+#[test]
+fn failed_send_keeps_the_record() {
+    let mut outbox = Outbox::open(MockFlash::new(2), 16, OverflowPolicy::DropOldest).unwrap();
+    outbox.push(&reading(1)).unwrap();
+    outbox.push(&reading(2)).unwrap();
+
+    assert_eq!(outbox.replay(10, |_| false).unwrap(), 0);
+    assert_eq!(outbox.len(), 2);
+    assert_eq!(drain(&mut outbox), vec![reading(1), reading(2)]);
+}
+
+#[test]
+fn drop_oldest_loses_a_sector_and_counts_it() {
+    let flash = MockFlash::new(2);
+    let mut outbox = Outbox::open(flash.clone(), 16, OverflowPolicy::DropOldest).unwrap();
+    assert_eq!(outbox.capacity(), 16);
+
+    for n in 1..=20 {
+        outbox.push(&reading(n)).unwrap();
+    }
+
+    // El primer sector (1..=8) se borró para escribir 17..=20
+    assert_eq!(outbox.stats().dropped(), 8);
+    let sent = drain(&mut outbox);
+    assert_eq!(sent.first(), Some(&reading(9)));
+    assert_eq!(sent.last(), Some(&reading(20)));
+    assert_eq!(sent.len(), 12);
+}
+
+#[test]
+fn drop_newest_keeps_what_was_stored() {
+    let mut outbox = Outbox::open(MockFlash::new(2), 16, OverflowPolicy::DropNewest).unwrap();
+    for n in 1..=16 {
+        outbox.push(&reading(n)).unwrap();
+    }
+
+    assert_eq!(outbox.push(&reading(17)), Err(OutboxError::Full));
+    assert_eq!(outbox.stats().dropped(), 1);
+    assert_eq!(outbox.len(), 16);
+
+    // Al vaciarse el sector más antiguo vuelve a haber sitio
+    outbox.replay(8, |_| true).unwrap();
+    outbox.push(&reading(18)).unwrap();
+    assert_eq!(drain(&mut outbox).last(), Some(&reading(18)));
+}
+
+#[test]
+fn corrupt_and_oversized_records_are_dropped() {
+    let flash = MockFlash::new(2);
+    let mut outbox = Outbox::open(flash.clone(), 16, OverflowPolicy::DropOldest).unwrap();
+    outbox.push(&reading(1)).unwrap();
+    outbox.push(&reading(2)).unwrap();
+
+    let huge = Publication::new("esp32/esp32-sensor-01/telemetry/rfid", &[b'x'; 600]);
+    assert_eq!(outbox.push(&huge), Err(OutboxError::TooLarge));
+
+    // Un bit cambiado en el payload del primer registro
+    flash.corrupt(40);
+    assert_eq!(drain(&mut outbox), vec![reading(2)]);
+    assert_eq!(outbox.stats().dropped(), 2);
+}
+
+#[test]
+fn partition_must_hold_two_sectors() {
+    let result = Outbox::open(MockFlash::new(1), 128, OverflowPolicy::DropOldest);
+    assert!(matches!(result, Err(OutboxError::PartitionTooSmall)));
+
+    // La capacidad pedida se limita a la partición
+    let outbox = Outbox::open(MockFlash::new(3), 1000, OverflowPolicy::DropOldest).unwrap();
+    assert_eq!(outbox.capacity() as u32 * 512, 3 * SECTOR_SIZE);
+}
@@ -0,0 +1,60 @@
+use esp32_common::pending::{ACK_TIMEOUT_MS, MAX_PENDING};
+use esp32_common::{ManualClock, PendingAction, PendingCommands, Publication};
+
+fn publication(id: &str) -> Publication {
+    Publication::new("esp32/esp32-actuator-01/cmd", id.as_bytes())
+}
+
+#[test]
+fn acknowledged_command_is_not_retried() {
+    let clock = ManualClock::new(0);
+    let mut pending = PendingCommands::new(&clock);
+
+    pending.track("s-1", "LED_TOGGLE", "esp32-actuator-01", publication("s-1")).unwrap();
+    assert!(pending.acknowledge("s-1").is_some());
+    assert!(pending.acknowledge("s-1").is_none());
+
+    clock.advance(ACK_TIMEOUT_MS * 10);
+    assert!(pending.poll().is_empty());
+}
+
+#[test]
+fn unanswered_command_is_resent_then_fails() {
+    let clock = ManualClock::new(0);
+    let mut pending = PendingCommands::new(&clock).with_retry(1000, 3);
+    pending.track("s-1", "BUZZER", "esp32-actuator-01", publication("s-1")).unwrap();
+
+    clock.advance(999);
+    assert!(pending.poll().is_empty());
+
+    clock.advance(1);
+    assert_eq!(pending.poll(), vec![PendingAction::Resend(publication("s-1"))]);
+    clock.advance(1000);
+    assert_eq!(pending.poll(), vec![PendingAction::Resend(publication("s-1"))]);
+
+    clock.advance(1000);
+    match pending.poll().as_slice() {
+        [PendingAction::Failed(command)] => {
+            assert_eq!(command.request_id, "s-1");
+            assert_eq!(command.command, "BUZZER");
+            assert_eq!(command.attempts, 3);
+        },
+        other => panic!("unexpected actions: {:?}", other),
+    }
+    assert!(pending.is_empty());
+}
+
+#[test]
+fn pending_set_is_bounded() {
+    let clock = ManualClock::new(0);
+    let mut pending = PendingCommands::new(&clock);
+
+    for i in 0..MAX_PENDING {
+        let id = format!("s-{}", i);
+        pending.track(&id, "LED_ON", "esp32-actuator-01", publication(&id)).unwrap();
+    }
+    assert_eq!(
+        pending.track("s-x", "LED_ON", "esp32-actuator-01", publication("s-x")),
+        Err("Too many pending commands")
+    );
+}
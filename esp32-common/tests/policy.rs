@@ -0,0 +1,130 @@
+use esp32_common::message::decode_policy;
+use esp32_common::policy::{self, Accepted, Policy, PolicyError, Rule, POLICY_UPDATE};
+use esp32_common::signature::Keyring;
+use esp32_common::{Command, CommandError, ErrorCode, Rejection};
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f";
+
+fn keyring() -> Keyring {
+    Keyring::parse(&format!("node-red-dashboard={}, telegram-bot={}", KEY, KEY)).unwrap()
+}
+
+fn document(version: u32, from: &str) -> String {
+    let mut buzzer = Rule::new("esp32-sensor*", &["BUZZER"]);
+    buzzer.max_duration = Some(2000);
+    let mut leds = Rule::new("node-red*", &["LED_ON", "LED_OFF", POLICY_UPDATE]);
+    leds.leds = Some(vec![1, 2]);
+    leds.rate = Some(20);
+    let policy = Policy { version, rules: vec![buzzer, leds] };
+    let sig = policy::sign(keyring().key(from).unwrap(), from, &policy);
+
+    format!(
+        r#"{{"v":1,"version":{},"from":"{}","sig":"{}","rules":[
+            {{"source":"esp32-sensor*","commands":["BUZZER"],"max_duration":2000}},
+            {{"source":"node-red*","commands":["LED_ON","LED_OFF","POLICY_UPDATE"],"leds":[1,2],"rate":20}}]}}"#,
+        version, from, sig
+    )
+}
+
+#[test]
+fn default_policy_denies_unknown_sources_and_commands() {
+    let policy = Policy::default();
+    assert_eq!(policy.version, 0);
+
+    let command = |name: &str, from: &str| Command::new(from, "esp32-actuator-01", name);
+    assert!(policy.authorize(&command("LED_ALL_OFF", "node-red-dashboard")).is_ok());
+    assert!(policy.authorize(&command("BUZZER", "telegram-bot")).is_ok());
+
+    let denied = policy.authorize(&command("BUZZER", "node-red-dashboard")).unwrap_err();
+    assert_eq!(denied, "Command 'BUZZER' not allowed for node-red-dashboard");
+    assert_eq!(Rejection::Denied(denied).code(), ErrorCode::Unauthorized);
+    // "*" no concede la actualización de la política
+    assert!(!policy.rule("telegram-bot").unwrap().allows(POLICY_UPDATE));
+    assert!(policy.rule("mallory").is_none());
+}
+
+#[test]
+fn signed_document_replaces_the_policy_and_bounds_parameters() {
+    let active = Policy::default();
+    let raw = document(3, "node-red-dashboard");
+    let update = decode_policy(raw.as_bytes()).unwrap();
+    active.accept(&update, &keyring(), 0).unwrap();
+
+    let policy = update.policy;
+    assert_eq!(policy.version, 3);
+
+    let mut beep = Command::new("esp32-sensor-01", "esp32-actuator-01", "BUZZER");
+    beep.duration = Some(1500);
+    assert!(policy.authorize(&beep).is_ok());
+    beep.duration = Some(2500);
+    assert_eq!(
+        policy.authorize(&beep).unwrap_err(),
+        "Command 'BUZZER' limited to 2000ms for esp32-sensor-01"
+    );
+
+    let mut led = Command::new("node-red-dashboard", "esp32-actuator-01", "LED_ON");
+    led.led_id = Some(3);
+    assert!(policy.authorize(&led).is_err());
+    // Lo que la política nueva no menciona queda denegado
+    assert!(policy.authorize(&Command::new("telegram-bot", "esp32-actuator-01", "LED_ON")).is_err());
+}
+
+#[test]
+fn updates_need_a_valid_signature_permission_and_newer_version() {
+    let active = Policy::default();
+    let keys = keyring();
+
+    let mut forged = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap();
+    forged.policy.rules[0].max_duration = Some(10_000);
+    assert_eq!(active.accept(&forged, &keys, 0), Err(PolicyError::InvalidSignature));
+
+    // Firma válida, pero telegram-bot no tiene POLICY_UPDATE
+    let from_bot = decode_policy(document(3, "telegram-bot").as_bytes()).unwrap();
+    assert_eq!(active.accept(&from_bot, &keys, 0), Err(PolicyError::NotAllowed));
+
+    let unknown = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap();
+    assert_eq!(active.accept(&unknown, &Keyring::default(), 0), Err(PolicyError::UnknownSender));
+
+    let current = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap().policy;
+    let replayed = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap();
+    assert_eq!(current.accept(&replayed, &keys, 0), Err(PolicyError::StaleVersion));
+}
+
+#[test]
+fn accepted_version_stays_the_floor() {
+    let keys = keyring();
+    let older = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap();
+
+    // La guardada no se pudo restaurar y rige la de fábrica (v0), pero ya se
+    // había aceptado la v5: la v3 retenida o repetida no vuelve a entrar
+    assert_eq!(Policy::default().accept(&older, &keys, 5), Err(PolicyError::StaleVersion));
+    let newer = decode_policy(document(6, "node-red-dashboard").as_bytes()).unwrap();
+    assert_eq!(Policy::default().accept(&newer, &keys, 5), Ok(()));
+}
+
+#[test]
+fn stored_policy_is_verified_again_on_restore() {
+    let update = decode_policy(document(3, "node-red-dashboard").as_bytes()).unwrap();
+    let accepted = update.accepted();
+    assert_eq!(accepted, Accepted { version: 3, from: "node-red-dashboard".to_string() });
+    assert_eq!(Policy::restore(update.clone(), &keyring(), &accepted).unwrap(), update.policy);
+
+    // Manipulado en la flash: la firma ya no coincide
+    let mut tampered = update.clone();
+    tampered.policy.rules[0].commands.push("LED_ON".to_string());
+    assert_eq!(Policy::restore(tampered, &keyring(), &accepted), Err(PolicyError::InvalidSignature));
+
+    // Clave del firmante retirada de cmd_keys
+    let rotated = Keyring::parse(&format!("telegram-bot={}", KEY)).unwrap();
+    assert_eq!(Policy::restore(update.clone(), &rotated, &accepted), Err(PolicyError::UnknownSender));
+
+    // Firmado por telegram-bot, al que la de fábrica no concede POLICY_UPDATE:
+    // se restaura si una política posterior se lo concedió y se aceptó así
+    let delegated = decode_policy(document(4, "telegram-bot").as_bytes()).unwrap();
+    let accepted_from_bot = delegated.accepted();
+    assert!(Policy::restore(delegated.clone(), &keyring(), &accepted_from_bot).is_ok());
+
+    // Un documento válido pero distinto del registrado como aceptado (una
+    // versión anterior copiada en la flash)
+    assert_eq!(Policy::restore(update, &keyring(), &accepted_from_bot), Err(PolicyError::NotAccepted));
+}
@@ -0,0 +1,170 @@
+use esp32_common::drivers::{BuzzerError, LedError};
+use esp32_common::quota::{local_day, until_midnight_ms, QuotaError, QuotaLimits, QuotaState, Quotas, STATUS_COMMAND};
+use esp32_common::{Command, CommandError, CommandOutcome, ErrorCode};
+
+// 2025-10-09 12:00 UTC
+const NOON_UTC: u64 = 1_760_011_200_000;
+const HOUR_MS: u64 = 3_600_000;
+
+fn command(name: &str) -> Command {
+    Command::new("telegram-bot", "esp32-actuator-01", name)
+}
+
+fn limits(buzzer_per_day: u32, commands_per_day: u32) -> QuotaLimits {
+    QuotaLimits { buzzer_per_day, commands_per_day, utc_offset_minutes: -300 }
+}
+
+#[test]
+fn days_follow_the_local_midnight() {
+    // En UTC-5 las 12:00 UTC son las 07:00 del mismo día; las 04:00 UTC del
+    // día siguiente aún son las 23:00 locales
+    let today = local_day(NOON_UTC, -300);
+    assert_eq!(local_day(NOON_UTC + 16 * HOUR_MS, -300), today);
+    assert_eq!(local_day(NOON_UTC + 17 * HOUR_MS, -300), today + 1);
+    assert_eq!(until_midnight_ms(NOON_UTC, -300), 17 * HOUR_MS);
+    assert_eq!(until_midnight_ms(NOON_UTC, 0), 12 * HOUR_MS);
+}
+
+#[test]
+fn quotas_are_exhausted_and_reset_on_a_new_day() {
+    let mut quotas = Quotas::new(limits(4, 3), QuotaState::default());
+    let now = Some(NOON_UTC);
+
+    // Un triple beep son tres tonos
+    quotas.consume(&mut command("BUZZER"), now).unwrap();
+    quotas.consume(&mut command("BUZZER_TRIPLE"), now).unwrap();
+    let error = quotas.consume(&mut command("BUZZER"), now).unwrap_err();
+    assert_eq!(error, QuotaError::Buzzer);
+    assert_eq!(error.to_string(), "Daily buzzer limit exceeded");
+    assert_eq!(error.code(), ErrorCode::QuotaExceeded);
+
+    quotas.consume(&mut command("LED_ON"), now).unwrap();
+    assert_eq!(quotas.consume(&mut command("LED_OFF"), now), Err(QuotaError::Commands));
+
+    // La consulta y la parada de emergencia no gastan cuota
+    quotas.consume(&mut command(STATUS_COMMAND), now).unwrap();
+    let mut stop = command("LED_ALL_OFF");
+    stop.emergency = Some(true);
+    quotas.consume(&mut stop, now).unwrap();
+
+    let status = quotas.status(now);
+    assert_eq!((status.buzzer_remaining, status.commands_remaining), (0, 0));
+    assert_eq!(status.to_string(), "buzzer 0/4, commands 0/3 left, reset in 61200s");
+
+    assert!(quotas.roll(Some(NOON_UTC + 17 * HOUR_MS)));
+    quotas.consume(&mut command("BUZZER"), Some(NOON_UTC + 17 * HOUR_MS)).unwrap();
+}
+
+#[test]
+fn quotas_are_settled_with_what_the_actuator_did() {
+    let mut quotas = Quotas::new(limits(10, 10), QuotaState::default());
+    let now = Some(NOON_UTC);
+    let spent = |quotas: &mut Quotas| {
+        let status = quotas.status(now);
+        (10 - status.buzzer_remaining, 10 - status.commands_remaining)
+    };
+
+    // Reservado al aceptar: varios en cola no pasan del límite
+    let mut triple = command("BUZZER_TRIPLE");
+    quotas.consume(&mut triple, now).unwrap();
+    assert_eq!(spent(&mut quotas), (3, 1));
+    let mut done = CommandOutcome::ok(triple, "ok".to_string());
+    done.beeps = 3;
+    quotas.settle(&done);
+    assert_eq!(spent(&mut quotas), (3, 1));
+
+    // El intervalo mínimo del buzzer lo rechazó: no cuenta nada
+    let mut buzzer = command("BUZZER");
+    quotas.consume(&mut buzzer, now).unwrap();
+    quotas.settle(&CommandOutcome::failed(buzzer, &BuzzerError::RateLimited));
+    assert_eq!(spent(&mut quotas), (3, 1));
+
+    // ACKNOWLEDGE cobra su beep si sonó
+    let mut acknowledge = command("ACKNOWLEDGE");
+    quotas.consume(&mut acknowledge, now).unwrap();
+    let mut ack = CommandOutcome::ok(acknowledge, "ok".to_string());
+    ack.beeps = 1;
+    quotas.settle(&ack);
+    assert_eq!(spent(&mut quotas), (4, 2));
+
+    // La consulta no reserva ni ajusta nada
+    let mut status = command(STATUS_COMMAND);
+    quotas.consume(&mut status, now).unwrap();
+    assert_eq!(status.quota, None);
+    quotas.settle(&CommandOutcome::ok(status, "ok".to_string()));
+    assert_eq!(spent(&mut quotas), (4, 2));
+
+    // Ni lo que no pasó por consume()
+    quotas.settle(&CommandOutcome::failed(command("LED_ON"), &LedError::InvalidId));
+    assert_eq!(spent(&mut quotas), (4, 2));
+}
+
+#[test]
+fn local_buttons_only_spend_buzzer_quota() {
+    let mut quotas = Quotas::new(limits(1, 10), QuotaState::default());
+    let now = Some(NOON_UTC);
+
+    // El ACKNOWLEDGE del botón 2 cobra su beep, pero no cuenta como comando
+    let mut local = Command::new("esp32-actuator-01", "esp32-actuator-01", "ACKNOWLEDGE");
+    quotas.consume_local(&mut local, now).unwrap();
+    let status = quotas.status(now);
+    assert_eq!((status.buzzer_remaining, status.commands_remaining), (0, 10));
+
+    let mut again = local.clone();
+    again.quota = None;
+    assert_eq!(quotas.consume_local(&mut again, now), Err(QuotaError::Buzzer));
+
+    // Si el buzzer no sonó se devuelve el tono, y un fallo no descuenta comandos
+    quotas.settle(&CommandOutcome::failed(local, &BuzzerError::RateLimited));
+    let status = quotas.status(now);
+    assert_eq!((status.buzzer_remaining, status.commands_remaining), (1, 10));
+}
+
+#[test]
+fn settling_after_midnight_leaves_the_new_day_alone() {
+    let mut quotas = Quotas::new(limits(10, 10), QuotaState::default());
+    // 23:30 locales
+    let before = Some(NOON_UTC + 16 * HOUR_MS + HOUR_MS / 2);
+    let after = Some(NOON_UTC + 17 * HOUR_MS);
+
+    let mut triple = command("BUZZER_TRIPLE");
+    quotas.consume(&mut triple, before).unwrap();
+    let mut failed = command("BUZZER");
+    quotas.consume(&mut failed, before).unwrap();
+
+    // Pasa la medianoche con los dos en cola, y en el día nuevo se acepta otro
+    assert!(quotas.roll(after));
+    let mut led = command("LED_ON");
+    quotas.consume(&mut led, after).unwrap();
+
+    // Lo reservado ayer no se descuenta ni se cobra en los contadores de hoy
+    let mut done = CommandOutcome::ok(triple, "ok".to_string());
+    done.beeps = 1;
+    quotas.settle(&done);
+    quotas.settle(&CommandOutcome::failed(failed, &BuzzerError::RateLimited));
+    quotas.settle(&CommandOutcome::ok(led, "ok".to_string()));
+    let status = quotas.status(after);
+    assert_eq!((status.buzzer_remaining, status.commands_remaining), (10, 9));
+}
+
+#[test]
+fn state_survives_a_reboot_before_the_clock_syncs() {
+    let mut quotas = Quotas::new(limits(1, 10), QuotaState::default());
+    quotas.consume(&mut command("BUZZER"), Some(NOON_UTC)).unwrap();
+    assert!(quotas.take_dirty());
+    assert!(!quotas.take_dirty());
+
+    let saved = quotas.state().to_bytes();
+    let restored = QuotaState::from_bytes(&saved).unwrap();
+    assert_eq!(restored, quotas.state());
+    assert_eq!(QuotaState::from_bytes(&saved[..4]), None);
+
+    // Tras reiniciar, sin hora, el buzzer sigue agotado
+    let mut rebooted = Quotas::new(limits(1, 10), restored);
+    assert_eq!(rebooted.consume(&mut command("BUZZER"), None), Err(QuotaError::Buzzer));
+    assert_eq!(rebooted.status(None).to_string(), "buzzer 0/1, commands 9/10 left, clock not synchronized");
+
+    // Un reloj que retrocede no reinicia las cuotas
+    assert!(!rebooted.roll(Some(NOON_UTC - 48 * HOUR_MS)));
+    assert!(rebooted.consume(&mut command("BUZZER"), Some(NOON_UTC - 48 * HOUR_MS)).is_err());
+}
@@ -0,0 +1,43 @@
+use esp32_common::ratelimit::{Budget, CommandClass, TokenBucket, SOURCE_BURST};
+use esp32_common::Command;
+
+#[test]
+fn bucket_allows_a_burst_then_refills_at_the_rate() {
+    let mut bucket = TokenBucket::new(Budget::new(3, 6), 0);
+
+    assert!(bucket.try_take(0));
+    assert!(bucket.try_take(0));
+    assert!(bucket.try_take(0));
+    assert!(!bucket.try_take(0));
+    // 6 por minuto: un token cada 10 s, sin pasar de la ráfaga
+    assert_eq!(bucket.retry_after_ms(4_000), 6_000);
+    assert_eq!(bucket.remaining(10_000), 1);
+    assert_eq!(bucket.remaining(600_000), 3);
+}
+
+#[test]
+fn bucket_without_refill_never_recovers() {
+    let mut bucket = TokenBucket::new(Budget::new(1, 0), 0);
+    assert!(bucket.try_take(0));
+    assert_eq!(bucket.retry_after_ms(1_000_000), u64::MAX);
+}
+
+#[test]
+fn commands_are_classified_for_budgets() {
+    let mut stop = Command::new("node-red", "esp32-actuator-01", "LED_ALL_OFF");
+    assert_eq!(CommandClass::of(&stop), CommandClass::Led);
+    stop.emergency = Some(true);
+    assert_eq!(CommandClass::of(&stop), CommandClass::Emergency);
+
+    // "emergency" en otro comando no da acceso al presupuesto de emergencia
+    let mut beep = Command::new("node-red", "esp32-actuator-01", "BUZZER");
+    beep.emergency = Some(true);
+    assert_eq!(CommandClass::of(&beep), CommandClass::Buzzer);
+
+    // La consulta de cuotas no gasta del presupuesto de los LEDs
+    let status = Command::new("node-red", "esp32-actuator-01", "QUOTA_STATUS");
+    assert_eq!(CommandClass::of(&status), CommandClass::Status);
+
+    assert_eq!(Budget::per_minute(60), Budget::new(SOURCE_BURST, 60));
+    assert_eq!(Budget::per_minute(2), Budget::new(2, 2));
+}
@@ -0,0 +1,173 @@
+use esp32_common::message::{decode_desired, encode_as, ShadowDesired};
+use esp32_common::shadow::{self, DesiredError, DesiredUpdate, LedMask};
+use esp32_common::signature::Keyring;
+use esp32_common::{DecodeError, Encoding, Shadow};
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f";
+const DEVICE: &str = "esp32-actuator-01";
+
+fn keyring() -> Keyring {
+    Keyring::parse(&format!("node-red-dashboard={}", KEY)).unwrap()
+}
+
+fn desired(version: u32, leds: LedMask) -> DesiredUpdate {
+    desired_for(DEVICE, version, leds)
+}
+
+fn desired_for(device_id: &str, version: u32, leds: LedMask) -> DesiredUpdate {
+    let from = "node-red-dashboard";
+    DesiredUpdate {
+        version,
+        from: from.to_string(),
+        signature: shadow::sign(keyring().key(from).unwrap(), device_id, from, version, &leds),
+        leds,
+    }
+}
+
+#[test]
+fn boot_reconciles_to_the_retained_desired() {
+    // Tras el test de LEDs todo está apagado
+    let mut shadow = Shadow::new([false; 3]);
+    shadow.set_desired(desired(1, [Some(true), None, Some(false)]));
+    assert_eq!(shadow.delta(), [Some(true), None, None]);
+
+    let commands = shadow.reconcile("esp32-actuator-01");
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].command, "LED_ON");
+    assert_eq!(commands[0].led_id, Some(1));
+    // A nombre de quien firmó el desired: la política y las cuotas son las suyas
+    assert_eq!(commands[0].from, "node-red-dashboard");
+    assert_eq!(commands[0].to, "esp32-actuator-01");
+
+    assert!(shadow.report([true, false, false]));
+    assert!(shadow.in_sync());
+    assert!(!shadow.report([true, false, false]));
+    assert!(shadow.reconcile("esp32-actuator-01").is_empty());
+}
+
+#[test]
+fn nothing_is_reconciled_without_a_desired() {
+    let shadow = Shadow::new([true, false, true]);
+    assert_eq!(shadow.desired_version(), 0);
+    assert!(shadow.in_sync());
+    assert!(shadow.reconcile("esp32-actuator-01").is_empty());
+}
+
+#[test]
+fn divergence_publishes_one_delta_per_change() {
+    let mut shadow = Shadow::new([false; 3]);
+    shadow.set_desired(desired(1, [Some(false), Some(true), None]));
+    assert_eq!(shadow.take_delta(), Some([None, Some(true), None]));
+    assert_eq!(shadow.take_delta(), None);
+
+    // Un botón enciende el LED 1: nueva divergencia
+    shadow.report([true, false, false]);
+    assert_eq!(shadow.take_delta(), Some([Some(false), Some(true), None]));
+
+    // En sincronía no se publica nada, pero la próxima divergencia sí
+    shadow.report([false, true, false]);
+    assert_eq!(shadow.take_delta(), None);
+    shadow.report([false, false, false]);
+    assert_eq!(shadow.take_delta(), Some([None, Some(true), None]));
+}
+
+#[test]
+fn only_signed_newer_desired_documents_are_accepted() {
+    let keys = keyring();
+    let mut shadow = Shadow::new([false; 3]);
+
+    let first = desired(3, [Some(true), None, None]);
+    assert_eq!(shadow.accept(&first, &keys, DEVICE), Ok(()));
+    shadow.set_desired(first.clone());
+
+    // El retenido que vuelve al reconectar es el mismo documento
+    assert!(shadow.is_current(&first));
+    assert_eq!(shadow.accept(&first, &keys, DEVICE), Err(DesiredError::StaleVersion));
+    assert_eq!(shadow.accept(&desired(2, [None; 3]), &keys, DEVICE), Err(DesiredError::StaleVersion));
+
+    // Firma de otro contenido
+    let mut tampered = desired(4, [Some(true), None, None]);
+    tampered.leds = [Some(true), Some(true), Some(true)];
+    assert_eq!(shadow.accept(&tampered, &keys, DEVICE), Err(DesiredError::InvalidSignature));
+
+    let mut unsigned = desired(4, [None; 3]);
+    unsigned.signature.clear();
+    assert_eq!(shadow.accept(&unsigned, &keys, DEVICE), Err(DesiredError::InvalidSignature));
+
+    let mut stranger = desired(4, [None; 3]);
+    stranger.from = "esp32-sensor-01".to_string();
+    assert_eq!(shadow.accept(&stranger, &keys, DEVICE), Err(DesiredError::UnknownSender));
+
+    let next = desired(4, [None, Some(false), None]);
+    assert!(!shadow.is_current(&next));
+    assert_eq!(shadow.accept(&next, &keys, DEVICE), Ok(()));
+}
+
+#[test]
+fn desired_signed_for_another_device_is_rejected() {
+    let keys = keyring();
+    let shadow = Shadow::new([false; 3]);
+    let other = desired_for("esp32-actuator-02", 1, [Some(true); 3]);
+    assert_eq!(shadow.accept(&other, &keys, DEVICE), Err(DesiredError::InvalidSignature));
+    assert_eq!(shadow.accept(&other, &keys, "esp32-actuator-02"), Ok(()));
+}
+
+#[test]
+fn accepted_version_survives_a_reboot() {
+    let keys = keyring();
+    let stored = desired(5, [Some(true), None, None]);
+
+    // El documento guardado se restaura y el retenido que llega es el aplicado
+    let mut shadow = Shadow::new([false; 3]).with_floor(5);
+    assert_eq!(shadow.restore(stored.clone(), &keys, DEVICE), Ok(()));
+    assert!(shadow.is_current(&stored));
+    assert_eq!(shadow.reconcile(DEVICE).len(), 1);
+
+    // Sin documento restaurado la versión guardada sigue siendo el mínimo
+    let shadow = Shadow::new([false; 3]).with_floor(5);
+    assert_eq!(shadow.desired_version(), 0);
+    assert_eq!(shadow.floor(), 5);
+    assert_eq!(shadow.accept(&desired(3, [None; 3]), &keys, DEVICE), Err(DesiredError::StaleVersion));
+    assert_eq!(shadow.accept(&stored, &keys, DEVICE), Err(DesiredError::StaleVersion));
+    assert_eq!(shadow.accept(&desired(6, [None; 3]), &keys, DEVICE), Ok(()));
+
+    // Un documento guardado que no es el de la versión aceptada no se restaura
+    let mut shadow = Shadow::new([false; 3]).with_floor(6);
+    assert_eq!(shadow.restore(stored.clone(), &keys, DEVICE), Err(DesiredError::NotAccepted));
+    let mut tampered = stored;
+    tampered.leds = [Some(true); 3];
+    let mut shadow = Shadow::new([false; 3]).with_floor(5);
+    assert_eq!(shadow.restore(tampered, &keys, DEVICE), Err(DesiredError::InvalidSignature));
+    assert_eq!(shadow.desired_version(), 0);
+}
+
+#[test]
+fn desired_decodes_from_json_cbor_or_empty() {
+    let signed = desired(2, [Some(true), None, Some(false)]);
+    let json = format!(
+        r#"{{"v":1,"version":2,"from":"node-red-dashboard","sig":"{}","leds":[true,null,false]}}"#,
+        signed.signature
+    );
+    assert_eq!(decode_desired(json.as_bytes()), Ok(Some(signed.clone())));
+
+    let mut buf = [0u8; 160];
+    let wire = ShadowDesired {
+        v: Some(1),
+        version: 2,
+        from: "node-red-dashboard",
+        sig: &signed.signature,
+        leds: signed.leds,
+    };
+    let len = encode_as(&wire, Encoding::Cbor, &mut buf).unwrap();
+    assert_eq!(decode_desired(&buf[..len]), Ok(Some(signed)));
+
+    // Retenido borrado: no cambia el desired aplicado
+    assert_eq!(decode_desired(b""), Ok(None));
+    assert_eq!(
+        decode_desired(br#"{"v":2,"version":1,"from":"a","sig":"","leds":[true,true,true]}"#),
+        Err(DecodeError::UnsupportedVersion(2))
+    );
+    // Sin versión ni firma ya no es un desired válido
+    assert!(decode_desired(br#"{"leds":[true,null,false]}"#).is_err());
+    assert!(decode_desired(br#"{"version":1,"from":"a","sig":"","leds":[true]}"#).is_err());
+}
@@ -0,0 +1,168 @@
+use esp32_common::message::{decode_command, CommandMessage, MAX_ID_LEN, MAX_PAYLOAD_LEN};
+use esp32_common::signature::{self, Delivery, Fields, Keyring, SignatureError, Signed, Verifier, MAX_CLOCK_SKEW_MS};
+use esp32_common::{topics, Command, Publication};
+
+const NOW: u64 = 1_760_000_000_000;
+const KEY: &str = "000102030405060708090a0b0c0d0e0f";
+
+fn keyring() -> Keyring {
+    Keyring::parse(&format!("telegram-bot={}, esp32-sensor-01=ffeeddccbbaa99887766554433221100", KEY)).unwrap()
+}
+
+fn signed(from: &str, timestamp: u64, nonce: &str) -> Command {
+    let mut command = Command::new(from, "esp32-actuator-01", "BUZZER");
+    command.duration = Some(1000);
+    command.request_id = Some("tg-42".to_string());
+    command.signed = Some(Box::new(Signed { timestamp, nonce: nonce.to_string(), signature: String::new() }));
+    let key = keyring().key(from).unwrap().to_vec();
+    let sig = signature::sign(&key, &Fields::from(&command));
+    command.signed.as_mut().unwrap().signature = sig;
+    command
+}
+
+// Vuelve a firmar después de cambiar algún campo
+fn sign_again(command: &mut Command) {
+    let key = keyring().key(&command.from).unwrap().to_vec();
+    command.signed.as_mut().unwrap().signature = signature::sign(&key, &Fields::from(&*command));
+}
+
+#[test]
+fn sender_and_receiver_sign_the_same_canonical_form() {
+    let mut message = CommandMessage::new("telegram-bot", "esp32-actuator-01", "BUZZER");
+    message.duration = Some(1000);
+    message.request_id = Some("tg-42");
+    message.timestamp = Some(NOW);
+    message.nonce = Some("a1b2");
+
+    let canonical = Fields::from(&message).canonical();
+    assert_eq!(
+        canonical,
+        "esp32-cmd-v1\ntelegram-bot\nesp32-actuator-01\nBUZZER\n\n1000\n\ntg-42\n1760000000000\na1b2"
+    );
+
+    let key = signature::from_hex(KEY).unwrap();
+    let command = signed("telegram-bot", NOW, "a1b2");
+    assert_eq!(Fields::from(&command).canonical(), canonical);
+    let sig = &command.signed.as_ref().unwrap().signature;
+    assert_eq!(*sig, signature::sign(&key, &Fields::from(&message)));
+    // Mismo valor que calcula el flujo de Node-RED con crypto.createHmac
+    assert_eq!(sig, "34330896f8e4be5adf2558b09f7ae18bc1a2f5856d09ab139f2e2322b3be7e21");
+
+    // Lo que viaja por MQTT llega al actuador con la firma intacta
+    message.sig = Some(sig);
+    let mut buf = [0u8; 512];
+    let len = esp32_common::message::encode(&message, &mut buf).unwrap();
+    let decoded = Command::from_json(core::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+    assert_eq!(decoded.signed, command.signed);
+}
+
+#[test]
+fn forged_and_unsigned_commands_are_rejected() {
+    let mut verifier = Verifier::new(keyring());
+
+    assert_eq!(
+        verifier.verify(&Command::new("telegram-bot", "esp32-actuator-01", "BUZZER"), Some(NOW)),
+        Err(SignatureError::Unsigned)
+    );
+
+    // Mismo contenido firmado con la clave de otro emisor
+    let mut forged = signed("esp32-sensor-01", NOW, "n1");
+    forged.from = "telegram-bot".to_string();
+    assert_eq!(verifier.verify(&forged, Some(NOW)), Err(SignatureError::InvalidSignature));
+
+    let mut tampered = signed("telegram-bot", NOW, "n2");
+    tampered.duration = Some(10_000);
+    assert_eq!(verifier.verify(&tampered, Some(NOW)), Err(SignatureError::InvalidSignature));
+
+    let mut unknown = signed("telegram-bot", NOW, "n3");
+    unknown.from = "mallory".to_string();
+    assert_eq!(verifier.verify(&unknown, Some(NOW)), Err(SignatureError::UnknownSender));
+
+    verifier.verify(&signed("telegram-bot", NOW, "n4"), Some(NOW)).unwrap();
+    assert_eq!(Keyring::parse("node-red=abcd"), None);
+}
+
+#[test]
+fn stale_timestamps_and_reused_nonces_are_reported_distinctly() {
+    let mut verifier = Verifier::new(keyring());
+
+    let command = signed("telegram-bot", NOW, "n1");
+    assert_eq!(verifier.verify(&command, None), Err(SignatureError::ClockNotSynchronized));
+    assert_eq!(verifier.verify(&command, Some(NOW + 1000)), Ok(Delivery::Fresh));
+    // Sin request_id no hay resultado que reenviar: es una repetición
+    let mut anonymous = signed("telegram-bot", NOW, "n0");
+    anonymous.request_id = None;
+    sign_again(&mut anonymous);
+    verifier.verify(&anonymous, Some(NOW)).unwrap();
+    assert_eq!(verifier.verify(&anonymous, Some(NOW + 2000)), Err(SignatureError::ReplayedNonce));
+
+    // El mismo nonce de otro emisor es otro nonce
+    verifier.verify(&signed("esp32-sensor-01", NOW, "n1"), Some(NOW)).unwrap();
+
+    let old = signed("telegram-bot", NOW - MAX_CLOCK_SKEW_MS - 1, "n2");
+    assert_eq!(verifier.verify(&old, Some(NOW)), Err(SignatureError::StaleTimestamp));
+    let future = signed("telegram-bot", NOW + MAX_CLOCK_SKEW_MS + 1, "n3");
+    assert_eq!(verifier.verify(&future, Some(NOW)), Err(SignatureError::StaleTimestamp));
+    assert_eq!(SignatureError::ReplayedNonce.code(), "replayed_nonce");
+}
+
+#[test]
+fn the_same_message_again_is_a_redelivery() {
+    let mut verifier = Verifier::new(keyring());
+
+    // QoS 1: el broker entrega otra vez el mismo mensaje, nonce incluido
+    let command = signed("telegram-bot", NOW, "n1");
+    assert_eq!(verifier.verify(&command, Some(NOW)), Ok(Delivery::Fresh));
+    assert_eq!(verifier.verify(&command, Some(NOW + 5000)), Ok(Delivery::Redelivered));
+
+    // El mismo nonce firmado para otro request_id no es el mismo mensaje
+    let mut other = signed("telegram-bot", NOW, "n1");
+    other.request_id = Some("tg-43".to_string());
+    sign_again(&mut other);
+    assert_eq!(verifier.verify(&other, Some(NOW)), Err(SignatureError::ReplayedNonce));
+}
+
+#[test]
+fn forgotten_nonces_cannot_be_replayed() {
+    let mut verifier = Verifier::new(keyring()).with_limits(MAX_CLOCK_SKEW_MS, 2);
+
+    let first = signed("telegram-bot", NOW, "n1");
+    verifier.verify(&first, Some(NOW)).unwrap();
+    verifier.verify(&signed("telegram-bot", NOW + 1, "n2"), Some(NOW)).unwrap();
+    verifier.verify(&signed("telegram-bot", NOW + 2, "n3"), Some(NOW)).unwrap();
+
+    // "n1" ya no está en memoria, pero su timestamp queda por debajo del mínimo
+    assert_eq!(verifier.verify(&first, Some(NOW)), Err(SignatureError::StaleTimestamp));
+}
+
+#[test]
+fn signed_commands_fit_in_a_publication() {
+    let key = keyring().key("esp32-sensor-01").unwrap().to_vec();
+    let sign = |mut message: CommandMessage<'_>| {
+        message.timestamp = Some(NOW);
+        let sig = signature::sign(&key, &Fields::from(&message));
+        Publication::encode(&topics::command(message.to), &CommandMessage { sig: Some(&sig), ..message }).unwrap()
+    };
+
+    // Lo que envía ESP32 #1 al pulsar un botón
+    let mut toggle = CommandMessage::new("esp32-sensor-01", "esp32-actuator-01", "LED_TOGGLE");
+    toggle.led_id = Some(2);
+    toggle.security = Some("validated");
+    toggle.request_id = Some("esp32-sensor-01-1");
+    toggle.nonce = Some("0123456789abcdef");
+    let publication = sign(toggle);
+    let command = decode_command(&publication.payload).unwrap();
+    let mut verifier = Verifier::new(keyring());
+    assert_eq!(verifier.verify(&command, Some(NOW)), Ok(Delivery::Fresh));
+
+    // Y el peor caso: cada identificador con la longitud máxima que se decodifica
+    let id = "x".repeat(MAX_ID_LEN);
+    let mut largest = CommandMessage::new(&id, &id, &id);
+    largest.led_id = Some(u8::MAX);
+    largest.duration = Some(u64::MAX);
+    largest.emergency = Some(false);
+    largest.security = Some(&id);
+    largest.request_id = Some(&id);
+    largest.nonce = Some(&id);
+    assert!(sign(largest).payload.len() <= MAX_PAYLOAD_LEN);
+}
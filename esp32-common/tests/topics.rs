@@ -0,0 +1,93 @@
+use esp32_common::topics::{self, Route, Target};
+
+#[test]
+fn topics_are_namespaced_by_device() {
+    assert_eq!(topics::command("esp32-actuator-01"), "esp32/esp32-actuator-01/cmd");
+    assert_eq!(topics::responses("esp32-sensor-01"), "esp32/esp32-sensor-01/responses");
+    assert_eq!(topics::state("esp32-actuator-01"), "esp32/esp32-actuator-01/state");
+    assert_eq!(
+        topics::telemetry("esp32-sensor-01", "temperature"),
+        "esp32/esp32-sensor-01/telemetry/temperature"
+    );
+    assert_eq!(topics::group_command("lab"), "esp32/group/lab/cmd");
+}
+
+#[test]
+fn signed_target_must_match_the_topic() {
+    let addressed = |topic: &str, to: &str| match topics::parse(topic) {
+        Some(Route::Command(target)) => target.is_addressed_by(to),
+        other => panic!("{:?}", other),
+    };
+
+    // `to` es el nivel del tópico al que se envía
+    assert!(addressed(&topics::command("esp32-actuator-01"), "esp32-actuator-01"));
+    assert!(addressed(&topics::group_command("lab"), "group/lab"));
+    assert!(addressed(topics::BROADCAST_COMMANDS, "all"));
+
+    // Un comando firmado para un dispositivo republicado en el buzón de
+    // otro, en un grupo o en el broadcast
+    let to = "esp32-actuator-01";
+    assert!(!addressed(&topics::command("esp32-actuator-02"), to));
+    assert!(!addressed(&topics::group_command("lab"), to));
+    assert!(!addressed(topics::BROADCAST_COMMANDS, to));
+    assert!(!addressed(&topics::group_command("esp32-actuator-01"), to));
+    assert!(!addressed(&topics::group_command("lab"), "lab"));
+}
+
+#[test]
+fn parse_routes_commands_and_responses() {
+    assert_eq!(
+        topics::parse("esp32/esp32-actuator-01/cmd"),
+        Some(Route::Command(Target::Device("esp32-actuator-01")))
+    );
+    assert_eq!(topics::parse("esp32/group/lab/cmd"), Some(Route::Command(Target::Group("lab"))));
+    assert_eq!(topics::parse("esp32/all/cmd"), Some(Route::Command(Target::Broadcast)));
+    assert_eq!(
+        topics::parse("esp32/esp32-sensor-01/responses"),
+        Some(Route::Responses("esp32-sensor-01"))
+    );
+
+    assert_eq!(
+        topics::parse("esp32/esp32-actuator-01/shadow/desired"),
+        Some(Route::ShadowDesired("esp32-actuator-01"))
+    );
+    assert_eq!(topics::parse("esp32/esp32-actuator-01/shadow/reported"), None);
+    assert_eq!(topics::parse("esp32/esp32-sensor-01/telemetry/rfid"), None);
+    assert_eq!(topics::parse("esp32/esp32-actuator-01/cmd/extra"), None);
+    assert_eq!(topics::parse("other/esp32-actuator-01/cmd"), None);
+    assert_eq!(topics::parse("esp32/commands"), None);
+}
+
+#[test]
+fn target_includes_own_id_groups_and_broadcast() {
+    let groups = vec!["lab".to_string()];
+
+    assert!(Target::Device("esp32-actuator-01").includes("esp32-actuator-01", &groups));
+    assert!(!Target::Device("esp32-actuator-02").includes("esp32-actuator-01", &groups));
+    assert!(Target::Group("lab").includes("esp32-actuator-01", &groups));
+    assert!(!Target::Group("office").includes("esp32-actuator-01", &groups));
+    assert!(Target::Broadcast.includes("esp32-actuator-01", &[]));
+}
+
+#[test]
+fn subscriptions_and_id_validation() {
+    let groups = vec!["lab".to_string(), "alarms".to_string()];
+    assert_eq!(
+        topics::command_subscriptions("esp32-actuator-01", &groups),
+        vec![
+            "esp32/esp32-actuator-01/cmd",
+            "esp32/all/cmd",
+            "esp32/group/lab/cmd",
+            "esp32/group/alarms/cmd",
+        ]
+    );
+
+    assert!(topics::is_valid_id("esp32-actuator-01"));
+    assert!(!topics::is_valid_id(""));
+    assert!(!topics::is_valid_id("a/b"));
+    assert!(!topics::is_valid_id("esp32-+"));
+    assert!(!topics::is_valid_id("#"));
+    assert!(!topics::is_valid_id("all"));
+    assert!(!topics::is_valid_id("group"));
+    assert!(!topics::is_valid_id(&"x".repeat(49)));
+}
@@ -0,0 +1,182 @@
+use esp32_common::policy::{Policy, Rule};
+use esp32_common::ratelimit::{CommandClass, Limit, BUZZER_BUDGET, EMERGENCY_BUDGET, SOURCE_BURST, SOURCE_EMERGENCY_BUDGET};
+use esp32_common::{Command, CommandError, CommandValidator, ErrorCode, ManualClock, Rejection};
+
+fn command(name: &str, from: &str) -> Command {
+    Command::new(from, "esp32-actuator-01", name)
+}
+
+fn emergency_stop(from: &str) -> Command {
+    let mut command = command("LED_ALL_OFF", from);
+    command.emergency = Some(true);
+    command
+}
+
+fn limited(result: Result<(), Rejection>) -> esp32_common::ratelimit::RateLimited {
+    match result {
+        Err(Rejection::RateLimited(limited)) => limited,
+        other => panic!("expected a rate limit, got {:?}", other),
+    }
+}
+
+#[test]
+fn one_chatty_source_does_not_exhaust_the_others() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(2, &clock);
+
+    validator.validate_command(&command("LED_ON", "node-red")).unwrap();
+    validator.validate_command(&command("LED_OFF", "node-red")).unwrap();
+    let rejected = validator.validate_command(&command("LED_ON", "node-red"));
+    assert_eq!(rejected.clone().unwrap_err().to_string(), "Source rate limit exceeded");
+    assert_eq!(rejected.clone().unwrap_err().code(), ErrorCode::RateLimited);
+
+    let limited = limited(rejected);
+    assert_eq!(limited.limit, Limit::Source);
+    assert_eq!(limited.source_remaining, Some(0));
+    // 2 tokens por minuto: uno cada 30 s
+    assert_eq!(limited.retry_after_ms, 30_000);
+
+    validator.validate_command(&command("LED_ON", "telegram-bot")).unwrap();
+}
+
+#[test]
+fn tokens_refill_continuously_without_window_bursts() {
+    let clock = ManualClock::new(1_000);
+    let mut validator = CommandValidator::new(2, &clock);
+
+    validator.validate_command(&command("LED_ON", "node-red")).unwrap();
+    validator.validate_command(&command("LED_ON", "node-red")).unwrap();
+    clock.advance(29_999);
+    assert!(validator.validate_command(&command("LED_ON", "node-red")).is_err());
+
+    // Medio minuto después hay un token, no una ventana nueva entera
+    clock.advance(1);
+    validator.validate_command(&command("LED_ON", "node-red")).unwrap();
+    assert!(validator.validate_command(&command("LED_ON", "node-red")).is_err());
+    assert_eq!(validator.command_count(), 3);
+}
+
+#[test]
+fn unknown_commands_and_sources_are_rejected() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(60, &clock);
+
+    assert_eq!(
+        validator.validate_command(&command("REBOOT", "node-red")),
+        Err(Rejection::Denied("Command 'REBOOT' not allowed".to_string()))
+    );
+    assert_eq!(
+        validator.validate_command(&command("LED_ON", "mallory")),
+        Err(Rejection::Denied("Untrusted command source: mallory".to_string()))
+    );
+    assert_eq!(validator.command_count(), 0);
+}
+
+#[test]
+fn buzzer_only_from_specific_sources() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(60, &clock);
+
+    assert!(validator.validate_command(&command("BUZZER", "node-red")).is_err());
+    validator.validate_command(&command("BUZZER", "telegram-bot")).unwrap();
+    validator.validate_command(&command("BUZZER_TRIPLE", "esp32-sensor-01")).unwrap();
+}
+
+#[test]
+fn command_classes_have_shared_budgets() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(60, &clock);
+
+    for _ in 0..BUZZER_BUDGET.burst {
+        validator.validate_command(&command("BUZZER", "telegram-bot")).unwrap();
+    }
+    let limited = limited(validator.validate_command(&command("BUZZER", "esp32-sensor-01")));
+    assert_eq!(limited.limit, Limit::Class(CommandClass::Buzzer));
+    assert_eq!(limited.class_remaining, 0);
+    assert_eq!(limited.source_remaining, Some(SOURCE_BURST));
+
+    // El presupuesto de los LEDs es otro
+    validator.validate_command(&command("LED_ON", "esp32-sensor-01")).unwrap();
+}
+
+#[test]
+fn quota_status_does_not_spend_led_tokens() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(60, &clock);
+
+    for _ in 0..5 {
+        validator.validate_command(&command("QUOTA_STATUS", "node-red-dashboard")).unwrap();
+    }
+    let limited = limited(validator.validate_command(&command("QUOTA_STATUS", "node-red-dashboard")));
+    assert_eq!(limited.limit, Limit::Class(CommandClass::Status));
+
+    // Los LEDs conservan toda su ráfaga
+    for _ in 0..5 {
+        validator.validate_command(&command("LED_ON", "node-red-dashboard")).unwrap();
+    }
+}
+
+#[test]
+fn emergency_stops_have_their_own_budget() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(1, &clock);
+
+    // El emisor y los LEDs agotados no bloquean la parada de emergencia
+    validator.validate_command(&command("LED_ALL_OFF", "node-red")).unwrap();
+    assert!(validator.validate_command(&command("LED_ALL_OFF", "node-red")).is_err());
+    validator.validate_command(&emergency_stop("node-red")).unwrap();
+    validator.validate_command(&emergency_stop("telegram-bot")).unwrap();
+    validator.validate_command(&emergency_stop("telegram-bot")).unwrap();
+
+    let limited = limited(validator.validate_command(&emergency_stop("esp32-sensor-01")));
+    assert_eq!(limited.limit, Limit::Class(CommandClass::Emergency));
+    assert_eq!(limited.source_remaining, Some(SOURCE_EMERGENCY_BUDGET.burst));
+    // Y las emergencias no gastaron del emisor telegram-bot
+    validator.validate_command(&command("LED_ON", "telegram-bot")).unwrap();
+}
+
+#[test]
+fn one_source_cannot_drain_the_emergency_budget() {
+    let clock = ManualClock::new(0);
+    let mut validator = CommandValidator::new(60, &clock);
+
+    for _ in 0..SOURCE_EMERGENCY_BUDGET.burst {
+        validator.validate_command(&emergency_stop("node-red")).unwrap();
+    }
+    let limited = limited(validator.validate_command(&emergency_stop("node-red")));
+    assert_eq!(limited.limit, Limit::Source);
+    assert_eq!(limited.source_remaining, Some(0));
+    assert_eq!(limited.class_remaining, EMERGENCY_BUDGET.burst - SOURCE_EMERGENCY_BUDGET.burst);
+    // 3 por minuto: uno cada 20 s
+    assert_eq!(limited.retry_after_ms, 20_000);
+
+    // Insistir no gasta nada: a los demás les sigue quedando presupuesto
+    for _ in 0..10 {
+        assert!(validator.validate_command(&emergency_stop("node-red")).is_err());
+    }
+    validator.validate_command(&emergency_stop("telegram-bot")).unwrap();
+
+    // La asignación del emisor se recarga más despacio que la compartida
+    clock.advance(60_000);
+    for _ in 0..SOURCE_EMERGENCY_BUDGET.burst {
+        validator.validate_command(&emergency_stop("node-red")).unwrap();
+    }
+    validator.validate_command(&emergency_stop("esp32-sensor-01")).unwrap();
+}
+
+#[test]
+fn policy_rate_overrides_the_source_budget() {
+    let clock = ManualClock::new(0);
+    let mut node_red = Rule::new("node-red*", &["*"]);
+    node_red.rate = Some(1);
+    let policy = Policy { version: 1, rules: vec![node_red] };
+    let mut validator = CommandValidator::new(60, &clock).with_policy(policy.clone());
+
+    validator.validate_command(&command("LED_ON", "node-red-dashboard")).unwrap();
+    assert!(validator.validate_command(&command("LED_ON", "node-red-dashboard")).is_err());
+    // Cada emisor tiene su bucket aunque compartan regla
+    validator.validate_command(&command("LED_ON", "node-red-flow")).unwrap();
+
+    validator.set_policy(policy);
+    validator.validate_command(&command("LED_ON", "node-red-dashboard")).unwrap();
+}
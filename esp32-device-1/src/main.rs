@@ -1,243 +1,101 @@
 use esp_idf_svc::hal::peripherals::Peripherals;
-use esp_idf_svc::hal::gpio::{PinDriver, OutputPin, InputPin, Pull};
+use esp_idf_svc::hal::gpio::{AnyOutputPin, InputPin, Output, OutputPin, PinDriver};
 use esp_idf_svc::hal::spi::{SpiDeviceDriver, SpiDriver, SpiDriverConfig, config::Config as SpiConfig};
 use esp_idf_svc::hal::adc::{AdcDriver, AdcChannelDriver, Atten};
 use esp_idf_svc::hal::delay::FreeRtos;
-use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::mqtt::client::{EspMqttClient, MqttClientConfiguration, QoS};
+use esp_idf_svc::hal::gpio::AnyIOPin;
+use esp_idf_svc::hal::uart::{config::Config as UartConfig, UartDriver};
+use esp_idf_svc::sntp::EspSntp;
+#[cfg(feature = "tls")]
+use esp_idf_svc::tls::X509;
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, MqttProtocolVersion, QoS};
 use nb::block;
-use core::fmt::Write;
-
-// Comandos básicos del RC522
-const PCD_IDLE: u8 = 0x00;
-const PCD_TRANSCEIVE: u8 = 0x0C;
-const PICC_REQIDL: u8 = 0x26;
-const PICC_ANTICOLL: u8 = 0x93;
-
-// Registros del RC522
-const COMMAND_REG: u8 = 0x01;
-const COM_IRQ_REG: u8 = 0x04;
-const DIV_IRQ_REG: u8 = 0x05;
-const ERROR_REG: u8 = 0x06;
-const STATUS2_REG: u8 = 0x08;
-const FIFO_DATA_REG: u8 = 0x09;
-const FIFO_LEVEL_REG: u8 = 0x0A;
-const BIT_FRAMING_REG: u8 = 0x0D;
-const COLL_REG: u8 = 0x0E;
-const MODE_REG: u8 = 0x11;
-const TX_CONTROL_REG: u8 = 0x14;
-const TX_AUTO_REG: u8 = 0x15;
-const T_MODE_REG: u8 = 0x2A;
-const T_PRESCALER_REG: u8 = 0x2B;
-const T_RELOAD_REG_H: u8 = 0x2C;
-const T_RELOAD_REG_L: u8 = 0x2D;
-
-// Estructura para RFID RC522
-struct Mfrc522<'a> {
-    spi: SpiDeviceDriver<'a, SpiDriver<'a>>,
-    rst: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-}
-
-impl<'a> Mfrc522<'a> {
-    fn new(
-        spi: SpiDeviceDriver<'a, SpiDriver<'a>>,
-        rst: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-    ) -> Self {
-        Mfrc522 { spi, rst }
-    }
-
-    fn init(&mut self) {
-        println!("🔧 Iniciando RFID RC522...");
-        
-        // Hard reset
-        self.rst.set_low().ok();
-        FreeRtos::delay_ms(50);
-        self.rst.set_high().ok();
-        FreeRtos::delay_ms(50);
-        
-        // Soft reset
-        self.write_register(COMMAND_REG, 0x0F);
-        FreeRtos::delay_ms(50);
-        
-        // Timer configuration
-        self.write_register(T_MODE_REG, 0x8D);
-        self.write_register(T_PRESCALER_REG, 0x3E);
-        self.write_register(T_RELOAD_REG_L, 30);
-        self.write_register(T_RELOAD_REG_H, 0);
-        
-        // Force 100% ASK modulation
-        self.write_register(TX_AUTO_REG, 0x40);
-        
-        // CRC preset value 0x6363
-        self.write_register(MODE_REG, 0x3D);
-        
-        // Turn on antenna
-        self.antenna_on();
-        
-        println!("✅ RFID RC522 inicializado");
-    }
-
-    fn write_register(&mut self, reg: u8, value: u8) {
-        let addr = (reg << 1) & 0x7E;
-        let _ = self.spi.write(&[addr, value]);
-    }
-
-    fn read_register(&mut self, reg: u8) -> u8 {
-        let addr = ((reg << 1) & 0x7E) | 0x80;
-        let mut rx = [0u8; 2];
-        let tx = [addr, 0x00];
-        
-        let _ = self.spi.transfer(&mut rx, &tx);
-        rx[1]
-    }
-
-    fn antenna_on(&mut self) {
-        let value = self.read_register(TX_CONTROL_REG);
-        if (value & 0x03) != 0x03 {
-            self.write_register(TX_CONTROL_REG, value | 0x03);
-        }
-    }
-
-    fn request(&mut self) -> Option<[u8; 2]> {
-        self.write_register(COM_IRQ_REG, 0x7F);
-        self.write_register(DIV_IRQ_REG, 0x7F);
-        
-        self.write_register(BIT_FRAMING_REG, 0x07);
-        self.write_register(FIFO_LEVEL_REG, 0x80);
-        self.write_register(FIFO_DATA_REG, PICC_REQIDL);
-        self.write_register(COMMAND_REG, PCD_TRANSCEIVE);
-        
-        let val = self.read_register(BIT_FRAMING_REG);
-        self.write_register(BIT_FRAMING_REG, val | 0x80);
-        
-        let mut timeout = 2000;
-        let mut fifo_level = 0;
-        loop {
-            let irq = self.read_register(COM_IRQ_REG);
-            fifo_level = self.read_register(FIFO_LEVEL_REG);
-            
-            timeout -= 1;
-            if timeout == 0 || (irq & 0x01) != 0 || (irq & 0x20) != 0 || fifo_level >= 2 {
-                break;
-            }
-        }
-        
-        let val = self.read_register(BIT_FRAMING_REG);
-        self.write_register(BIT_FRAMING_REG, val & (!0x80));
-        
-        if timeout != 0 && fifo_level >= 2 {
-            let error = self.read_register(ERROR_REG);
-            if (error & 0x1B) == 0x00 {
-                let atqa1 = self.read_register(FIFO_DATA_REG);
-                let atqa2 = self.read_register(FIFO_DATA_REG);
-                return Some([atqa1, atqa2]);
-            }
-        }
-        
-        None
-    }
-
-    fn anticoll(&mut self) -> Option<[u8; 5]> {
-        self.write_register(COM_IRQ_REG, 0x7F);
-        self.write_register(DIV_IRQ_REG, 0x7F);
-        
-        self.write_register(BIT_FRAMING_REG, 0x00);
-        self.write_register(FIFO_LEVEL_REG, 0x80);
-        
-        self.write_register(FIFO_DATA_REG, PICC_ANTICOLL);
-        self.write_register(FIFO_DATA_REG, 0x20);
-        
-        self.write_register(COMMAND_REG, PCD_TRANSCEIVE);
-        
-        let val = self.read_register(BIT_FRAMING_REG);
-        self.write_register(BIT_FRAMING_REG, val | 0x80);
-        
-        let mut timeout = 2000;
-        let mut fifo_level = 0;
-        loop {
-            let irq = self.read_register(COM_IRQ_REG);
-            fifo_level = self.read_register(FIFO_LEVEL_REG);
-            
-            timeout -= 1;
-            if timeout == 0 || (irq & 0x01) != 0 || (irq & 0x20) != 0 || fifo_level >= 5 {
-                break;
-            }
-        }
-        
-        let val = self.read_register(BIT_FRAMING_REG);
-        self.write_register(BIT_FRAMING_REG, val & (!0x80));
-        
-        if timeout != 0 && fifo_level >= 5 {
-            let error = self.read_register(ERROR_REG);
-            if (error & 0x1B) == 0x00 {
-                let mut uid = [0u8; 5];
-                for i in 0..5 {
-                    uid[i] = self.read_register(FIFO_DATA_REG);
-                }
-                
-                let bcc = uid[0] ^ uid[1] ^ uid[2] ^ uid[3];
-                if bcc == uid[4] {
-                    return Some(uid);
-                }
-            }
-        }
-        
-        None
-    }
-
-    fn halt(&mut self) {
-        self.write_register(FIFO_LEVEL_REG, 0x80);
-        self.write_register(FIFO_DATA_REG, 0x50);
-        self.write_register(FIFO_DATA_REG, 0x00);
-        self.write_register(COMMAND_REG, PCD_TRANSCEIVE);
-        FreeRtos::delay_ms(10);
-    }
-}
-
-// Estructura para los botones
-struct ButtonManager<'a> {
-    button1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    button2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    button3: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    last_states: [bool; 3],
-}
-
-impl<'a> ButtonManager<'a> {
-    fn new(
-        button1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-        button2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-        button3: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    ) -> Self {
-        ButtonManager {
-            button1,
-            button2,
-            button3,
-            last_states: [false; 3],
-        }
-    }
-    
-    fn check_buttons(&mut self) -> Option<u8> {
-        let states = [
-            self.button1.is_low(),
-            self.button2.is_low(),
-            self.button3.is_low(),
-        ];
-        
-        for (i, (&current, &last)) in states.iter().zip(self.last_states.iter()).enumerate() {
-            if current && !last { // Detecta flanco descendente (botón presionado)
-                self.last_states[i] = current;
-                return Some(i as u8 + 1);
-            }
-            self.last_states[i] = current;
-        }
-        
-        None
-    }
-}
+use serde::Serialize;
+use esp32_common::{spawn_task, Clock, Encoding, EspTimerClock, Event, PendingAction, PendingCommands, Publication, RequestIds, SecurityConfig, SCHEMA_VERSION};
+use esp32_common::drivers::{ButtonBank, Mfrc522};
+use esp32_common::command::validate_command;
+use esp32_common::event::{self, Sender, EVENT_QUEUE_CAPACITY, PUBLISH_QUEUE_CAPACITY};
+use esp32_common::topics::{self, Route};
+use esp32_common::clock::wall_clock_ms;
+#[cfg(feature = "tls")]
+use esp32_common::cert::ExpiryCheck;
+use esp32_common::credentials::Credentials;
+use esp32_common::discovery::{self, Resolver};
+use esp32_common::link::{Backoff, Link, LinkState, NetworkBackend};
+use esp32_common::mqtt5::{Negotiation, Properties, ProtocolVersion};
+use esp32_common::signature::{Delivery, SignatureError, Verifier};
+use esp32_common::espidf::boot::next_boot_id;
+use esp32_common::espidf::credentials::{open_credentials, provision, take_credential_partition};
+use esp32_common::espidf::mdns::{query_services, resolve, start_responder};
+use esp32_common::espidf::mqtt::{register_refusal, send, subscribe_all, ConnackRefusal, SharedClient};
+use esp32_common::espidf::network::{connect_network, open_ethernet, open_wifi, Network};
+use esp32_common::espidf::outbox::open_outbox;
+use esp32_common::espidf::signing::{encode_signed, resign};
+use esp32_common::system;
+use esp32_common::message::{decode_command, decode_response, uid_hex, ButtonEvent, CommandMessage, DeliveryFailed, Presence, RfidEvent, TemperatureReading};
+#[cfg(feature = "secure")]
+use esp32_common::message::Heartbeat;
+use esp32_common::task::{LARGE_STACK_SIZE, SMALL_STACK_SIZE};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type RfidReader = Mfrc522<
+    SpiDeviceDriver<'static, SpiDriver<'static>>,
+    PinDriver<'static, AnyOutputPin, Output>,
+    FreeRtos,
+>;
+
+// Campos extra que añade la variante segura a los mensajes publicados
+#[cfg(feature = "secure")]
+const SECURITY_ENABLED: Option<&str> = Some("enabled");
+#[cfg(not(feature = "secure"))]
+const SECURITY_ENABLED: Option<&str> = None;
+#[cfg(feature = "secure")]
+const SECURITY_VALIDATED: Option<&str> = Some("validated");
+#[cfg(not(feature = "secure"))]
+const SECURITY_VALIDATED: Option<&str> = None;
+
+// El mismo id en las dos variantes: con TLS es el CN del certificado y el
+// usuario de la ACL del broker
+const DEFAULT_DEVICE_ID: &str = "esp32-sensor-01";
+
+// ESP32 #2 si PEER_DEVICE_ID no se definió al compilar
+const DEFAULT_PEER_ID: &str = "esp32-actuator-01";
+
+// Periodo de muestreo del LM35
+const TEMPERATURE_INTERVAL_MS: u32 = 5000;
+// Versión publicada en la presencia del dispositivo
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Registros del outbox reenviados como máximo en cada vuelta de la tarea de publicación
+const OUTBOX_REPLAY_BATCH: usize = 8;
+
+// Cada cuánto revisa la tarea principal los envíos periódicos si no llegan eventos
+const IDLE_TICK_MS: u64 = 100;
+
+// Esquema, puerto por defecto y servicio mDNS del broker
+#[cfg(feature = "tls")]
+const BROKER_SCHEME: &str = "mqtts";
+#[cfg(feature = "tls")]
+const BROKER_PORT: u16 = 8883;
+#[cfg(feature = "tls")]
+const BROKER_SERVICE: &str = discovery::MQTTS_SERVICE;
+#[cfg(not(feature = "tls"))]
+const BROKER_SCHEME: &str = "mqtt";
+#[cfg(not(feature = "tls"))]
+const BROKER_PORT: u16 = 1883;
+#[cfg(not(feature = "tls"))]
+const BROKER_SERVICE: &str = discovery::MQTT_SERVICE;
+
+// Puerto del servidor Rust si el registro mDNS no trae otro
+const API_PORT: u16 = 8123;
 
 // Función para leer temperatura de sensor analógico (LM35)
+#[cfg(not(feature = "secure"))]
 fn read_temperature_sensor(
     adc: &mut AdcDriver,
     adc_channel: &mut AdcChannelDriver<esp_idf_svc::hal::adc::Adc1, esp_idf_svc::hal::gpio::Gpio32>,
@@ -249,32 +107,128 @@ fn read_temperature_sensor(
     Ok(temperature)
 }
 
-// Helper para JSON sin heap allocation
-struct ArrayWriter<'a> {
-    buf: &'a mut [u8],
-    pos: usize,
+// Función mejorada para leer temperatura con calibración
+#[cfg(feature = "secure")]
+fn read_temperature_sensor(
+    adc: &mut AdcDriver,
+    adc_channel: &mut AdcChannelDriver<esp_idf_svc::hal::adc::Adc1, esp_idf_svc::hal::gpio::Gpio32>,
+) -> Result<f32, esp_idf_svc::sys::EspError> {
+    // Tomar múltiples lecturas para promediar
+    let mut readings = [0u16; 5];
+    for i in 0..5 {
+        readings[i] = block!(adc.read(adc_channel))?;
+        FreeRtos::delay_ms(10);
+    }
+    
+    // Filtrar outliers y promediar
+    readings.sort_unstable();
+    let avg_reading = (readings[1] + readings[2] + readings[3]) / 3; // Descartar min y max
+    
+    // LM35: 10mV por °C, con referencia de 3.3V y resolución de 12 bits (4096)
+    let voltage = (avg_reading as f32 * 3.3) / 4095.0;
+    let temperature = voltage * 100.0; // LM35 da 10mV por °C
+    
+    // Validar rango razonable (0-50°C para interiores)
+    if temperature >= -10.0 && temperature <= 60.0 {
+        Ok(temperature)
+    } else {
+        Err(esp_idf_svc::sys::EspError::from_infallible::<{esp_idf_svc::sys::ESP_ERR_INVALID_RESPONSE}>())
+    }
 }
 
-impl<'a> ArrayWriter<'a> {
-    fn new(buf: &'a mut [u8]) -> Self {
-        ArrayWriter { buf, pos: 0 }
+// Codifica y encola un mensaje; si no cabe en el buffer se descarta con aviso
+fn publish<T: Serialize>(publisher: &Sender<Publication>, encoding: Encoding, topic: &str, message: &T) -> bool {
+    match Publication::encode_as(topic, message, encoding) {
+        Ok(publication) => publisher.post(publication),
+        Err(e) => {
+            println!("❌ Mensaje para {} descartado: {}", topic, e);
+            false
+        }
     }
-    
-    fn pos(&self) -> usize {
-        self.pos
+}
+
+// Telemetría: si no hay conexión se guarda en flash y se reenvía después
+fn publish_telemetry<T: Serialize>(publisher: &Sender<Publication>, encoding: Encoding, topic: &str, message: &T) -> bool {
+    match Publication::encode_as(topic, message, encoding) {
+        Ok(publication) => publisher.post(publication.durable()),
+        Err(e) => {
+            println!("❌ Mensaje para {} descartado: {}", topic, e);
+            false
+        }
     }
 }
 
-impl<'a> core::fmt::Write for ArrayWriter<'a> {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        let bytes = s.as_bytes();
-        let end = self.pos + bytes.len();
-        if end > self.buf.len() {
-            return Err(core::fmt::Error);
+// Publica un comando para ESP32 #2 si pasa la validación y lo deja pendiente
+// hasta que llegue su respuesta
+fn send_command(
+    publisher: &Sender<Publication>,
+    request_ids: &mut RequestIds,
+    pending: &mut PendingCommands<EspTimerClock>,
+    message: CommandMessage<'_>,
+    signing_key: Option<&[u8]>,
+    expiry_secs: u32,
+    encoding: Encoding,
+) -> bool {
+    let (from, to, name) = (message.from, message.to, message.command);
+    if !validate_command(name) {
+        println!("🚫 Comando {} no permitido", name);
+        return false;
+    }
+
+    // El resultado llega por esp32/{device_id}/responses con el mismo request_id;
+    // con MQTT 5 además como response topic y correlation data, y el broker
+    // descarta el comando si no se entrega en `expiry_secs`
+    let request_id = request_ids.next_id();
+    let message = CommandMessage {
+        request_id: Some(&request_id),
+        security: SECURITY_VALIDATED,
+        ..message
+    };
+
+    let encoded = match signing_key {
+        Some(key) => encode_signed(message, key, encoding),
+        // Solo sin la feature secure y sin cmd_keys: la variante segura no arranca sin clave
+        None => Publication::encode_as(&topics::command(to), &message, encoding).map_err(|e| e.to_string()),
+    };
+    let publication = match encoded {
+        Ok(publication) => publication.with_properties(Properties::command(from, &request_id, expiry_secs)),
+        Err(e) => {
+            println!("❌ Comando {} descartado: {}", name, e);
+            return false;
+        }
+    };
+
+    if let Err(e) = pending.track(&request_id, name, to, publication.clone()) {
+        println!("⚠️  Comando {} no enviado: {}", name, e);
+        return false;
+    }
+    publisher.post(publication)
+}
+
+// Certificados embebidos por build.rs desde security/certs
+#[cfg(feature = "tls")]
+const CA_CERT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ca.der"));
+#[cfg(feature = "tls")]
+const CLIENT_CERT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/client.der"));
+#[cfg(feature = "tls")]
+const CLIENT_KEY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/client.der.key"));
+
+// build.rs ya rechaza certificados caducados al compilar; aquí se repite la
+// comprobación con la hora de SNTP por si el firmware lleva tiempo flasheado
+// o encendido. Un certificado caducado no se arregla reiniciando, así que
+// solo se avisa: el handshake TLS fallará igualmente
+#[cfg(feature = "tls")]
+fn check_certificates(now: u64) {
+    use esp32_common::cert::{self, EXPIRY_WARNING_SECS};
+
+    for (name, der) in [("CA", CA_CERT), ("cliente", CLIENT_CERT)] {
+        match cert::validity(der).and_then(|v| v.check(now).map(|()| v.remaining_secs(now))) {
+            Ok(remaining) if remaining < EXPIRY_WARNING_SECS => {
+                println!("⚠️ Certificado {} caduca en {} días", name, remaining / 86400)
+            },
+            Ok(_) => println!("🔒 Certificado {} vigente", name),
+            Err(e) => println!("❌ Certificado {} inutilizable: {}", name, e),
         }
-        self.buf[self.pos..end].copy_from_slice(bytes);
-        self.pos = end;
-        Ok(())
     }
 }
 
@@ -282,63 +236,356 @@ fn main() {
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
 
+    #[cfg(feature = "secure")]
+    println!("🔒 ESP32 Device #1 SECURE - Sensor & RFID & Buttons");
+    #[cfg(not(feature = "secure"))]
     println!("🚀 ESP32 Device #1 - Sensor & RFID & Buttons");
-    println!("📡 Conectando a WiFi y MQTT...");
+    println!("📡 Conectando a la red y MQTT...");
 
     let p = Peripherals::take().unwrap();
+
+    // Credenciales desde la NVS cifrada; si faltan, aprovisionamiento por UART0.
+    // La variante segura exige las claves de firma y WiFi solo se pide con NETWORK=wifi
+    let requirements = match SecurityConfig::requirements(cfg!(feature = "secure")) {
+        Ok(requirements) => requirements,
+        Err(e) => panic!("Configuración de compilación inválida: {}", e),
+    };
+    let mut secrets = match take_credential_partition().and_then(open_credentials) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            println!("❌ Partición de credenciales no disponible: {:?}", e);
+            panic!("No se puede continuar sin la partición creds");
+        }
+    };
+    let credentials = match Credentials::load(&secrets, requirements) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            println!("🔐 {}", e);
+            let uart = UartDriver::new(
+                p.uart0,
+                p.pins.gpio1,
+                p.pins.gpio3,
+                Option::<AnyIOPin>::None,
+                Option::<AnyIOPin>::None,
+                &UartConfig::default(),
+            ).unwrap();
+            provision(&mut secrets, requirements, DEFAULT_DEVICE_ID, uart);
+        }
+    };
+
+    // Cargar configuración de seguridad
+    let security_config = match SecurityConfig::load(credentials) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("❌ Error cargando configuración de seguridad: {}", e);
+            panic!("No se puede continuar sin configuración segura");
+        }
+    };
+    
+    println!("🔑 Configuración cargada para device: {}", security_config.device_id);
+
+    // Con TLS el broker toma el CN del certificado como usuario de la ACL:
+    // con otro device_id el dispositivo no podría usar sus propios tópicos
+    #[cfg(feature = "tls")]
+    match esp32_common::cert::common_name(CLIENT_CERT) {
+        Ok(cn) if cn == security_config.device_id => {},
+        Ok(cn) => panic!("El certificado de cliente es de '{}' pero el dispositivo está aprovisionado como '{}'", cn, security_config.device_id),
+        Err(e) => panic!("Certificado de cliente inutilizable: {}", e),
+    }
+
+    // JSON o CBOR para todo lo que publica este dispositivo
+    let encoding = security_config.payload_encoding;
+    println!("📦 Payloads en {}", encoding.name());
+
+    let boot_reason = system::reset_reason();
+    println!("🔄 Motivo del arranque: {}", boot_reason);
+
     let s = EspSystemEventLoop::take().unwrap();
     let n = EspDefaultNvsPartition::take().unwrap();
+    // Acompaña a los timestamps de la telemetría, que son desde el arranque
+    let boot_id = next_boot_id(n.clone());
+    if let Some(boot_id) = boot_id {
+        println!("🔢 Arranque #{}", boot_id);
+    }
 
-    // Configurar WiFi
-    let mut w = BlockingWifi::wrap(
-        EspWifi::new(p.modem, s.clone(), Some(n.clone())).unwrap(),
-        s,
-    ).unwrap();
-
-    w.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: "UTP".try_into().unwrap(),
-        password: "tecnologica".try_into().unwrap(),
-        ..Default::default()
-    })).unwrap();
-
-    w.start().unwrap();
-    w.connect().unwrap();
-    w.wait_netif_up().unwrap();
-    println!("✅ WiFi conectado");
-
-    // Configurar MQTT
-    let mqtt_conf = MqttClientConfiguration {
-        keep_alive_interval: Some(core::time::Duration::from_secs(30)),
-        ..Default::default()
+    // Red elegida con NETWORK; la conexión la gestiona la tarea supervisora
+    println!("🌐 Red: {}", security_config.network.name());
+    let mut network: Box<dyn Network> = match security_config.network {
+        NetworkBackend::Wifi => open_wifi(
+            p.modem,
+            s,
+            n,
+            security_config.wifi.as_ref().expect("SecurityConfig exige WiFi con NETWORK=wifi"),
+        ),
+        NetworkBackend::Ethernet => open_ethernet(p.mac, s),
     };
 
-    let (mut mqtt, mut conn) = EspMqttClient::new(
-        "mqtt://broker.hivemq.com:1883",
-        &mqtt_conf,
-    ).unwrap();
+    // Hora real: los comandos firmados llevan timestamp Unix
+    let _sntp = EspSntp::new_default()
+        .map_err(|e| println!("⚠️ SNTP no disponible: {:?}", e))
+        .ok();
+
+    // mDNS: el dispositivo se anuncia como {device_id}.local y busca el broker
+    // y el servidor si no se configuraron al compilar
+    let mdns = start_responder(&security_config.device_id);
+    let mut broker = Resolver::new(security_config.mqtt_broker.clone());
+    let mut api_server = Resolver::new(security_config.api_server.clone());
+
+    // Comandos (ACKs de ESP32 #2) y resultados de los comandos enviados
+    let mut subscriptions = topics::command_subscriptions(&security_config.device_id, &security_config.groups);
+    subscriptions.push(topics::responses(&security_config.device_id));
+
+    let presence_topic = topics::presence(&security_config.device_id);
+
+    // Colas entre tareas: los periféricos producen eventos, la tarea principal
+    // los convierte en mensajes y la tarea de publicación los envía
+    let (events, event_rx) = event::channel::<Event>(EVENT_QUEUE_CAPACITY);
+    let (publisher, publish_rx) = event::channel::<Publication>(PUBLISH_QUEUE_CAPACITY);
+
+    let client: SharedClient = Arc::new(Mutex::new(None));
+    let link = Link::new();
+
+    // Tarea supervisora: red, sesión MQTT y recepción. Si algo falla espera
+    // con backoff y vuelve a empezar, con nuevas suscripciones en cada sesión
+    let session_client = client.clone();
+    let session_link = link.clone();
+    let mqtt_events = events.clone();
+    let device_id = security_config.device_id.clone();
+    let groups = security_config.groups.clone();
+    // Los ACK de ESP32 #2 también llegan firmados; la variante segura no
+    // acepta ninguno sin firma válida
+    let command_keys = security_config.command_keys.clone();
+    let will_topic = presence_topic.clone();
+    let mqtt_protocol = security_config.mqtt_protocol;
+    let mqtt_username = security_config.mqtt_username.clone();
+    let mqtt_password = security_config.mqtt_password.clone();
+    spawn_task("link", LARGE_STACK_SIZE, move || {
+        // Si el dispositivo se cae sin desconectar, el broker publica "offline"
+        // retenido en su tópico de presencia
+        let last_will = Publication::encode_as(&will_topic, &Presence::offline(&device_id), encoding).unwrap();
+        let mut mqtt_conf = MqttClientConfiguration {
+            #[cfg(feature = "secure")]
+            client_id: Some(&device_id),
+            #[cfg(feature = "tls")]
+            server_certificate: Some(X509::der(CA_CERT)),
+            #[cfg(feature = "tls")]
+            client_certificate: Some(X509::der(CLIENT_CERT)),
+            #[cfg(feature = "tls")]
+            private_key: Some(X509::der(CLIENT_KEY)),
+            // Usuario y contraseña del broker, si se aprovisionaron
+            username: mqtt_username.as_deref(),
+            password: mqtt_password.as_deref(),
+            keep_alive_interval: Some(core::time::Duration::from_secs(30)),
+            lwt: Some(LwtConfiguration {
+                topic: &will_topic,
+                payload: &last_will.payload,
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            // Los reintentos los decide el backoff de esta tarea
+            disable_auto_reconnect: true,
+            ..Default::default()
+        };
+
+        let mut backoff = Backoff::new(unsafe { esp_idf_svc::sys::esp_random() });
+        let mut negotiation = Negotiation::new(mqtt_protocol);
+        let refusal = Box::new(ConnackRefusal::default());
+        let mut verifier = (cfg!(feature = "secure") || !command_keys.is_empty()).then(|| Verifier::new(command_keys));
+        loop {
+            // Con la red arriba se pregunta por mDNS lo que no se configuró al compilar
+            let session = connect_network(network.as_mut(), &session_link).map(|()| {
+                if api_server.needs_query() {
+                    if let Some(endpoint) = api_server.discovered(&query_services(mdns.as_ref(), discovery::API_SERVICE)) {
+                        println!("🔎 Servidor encontrado por mDNS: {}", endpoint.url("http", API_PORT));
+                    }
+                }
+                resolve(mdns.as_ref(), &mut broker, BROKER_SERVICE)
+            });
+            match session {
+                Ok(Some(endpoint)) => {
+                    let mqtt_url = endpoint.url(BROKER_SCHEME, BROKER_PORT);
+
+                    session_link.set(LinkState::MqttConnecting);
+                    session_link.set_protocol(negotiation.current());
+                    mqtt_conf.protocol_version = Some(match negotiation.current() {
+                        ProtocolVersion::V5 => MqttProtocolVersion::V5,
+                        ProtocolVersion::V311 => MqttProtocolVersion::V3_1_1,
+                    });
+                    match EspMqttClient::new(&mqtt_url, &mqtt_conf) {
+                        Ok((mqtt, mut conn)) => {
+                            let mut established = false;
+                            // `refusal` vive mientras la tarea supervisora, es decir, siempre
+                            if let Err(e) = unsafe { register_refusal(&mqtt, &*refusal) } {
+                                println!("❌ No se pudo registrar el rechazo del CONNACK: {:?}", e);
+                            }
+                            *session_client.lock().unwrap() = Some(mqtt);
+
+                            // La sesión dura hasta que se pierde el broker o la red
+                            while let Ok(event) = conn.next() {
+                                match event.payload() {
+                                    EventPayload::Connected(_) => {
+                                        // El broker aceptó la versión aunque falle lo siguiente
+                                        negotiation.connected();
+                                        if !subscribe_all(&session_client, &subscriptions) {
+                                            break;
+                                        }
+                                        backoff.reset();
+                                        established = true;
+                                        session_link.connected();
+                                        println!(
+                                            "✅ MQTT {} conectado (reconexiones: {})",
+                                            negotiation.current().name(),
+                                            session_link.reconnects()
+                                        );
+                                        mqtt_events.post(Event::MqttConnected);
+                                    },
+                                    EventPayload::Disconnected => break,
+                                    EventPayload::Received { topic, data, .. } => match topic.and_then(topics::parse) {
+                                        Some(Route::Responses(id)) if id == device_id => match decode_response(data) {
+                                            Ok(response) => {
+                                                mqtt_events.post(Event::ResponseReceived(response));
+                                            },
+                                            Err(e) => println!("❌ Respuesta inválida: {}", e),
+                                        },
+                                        Some(Route::Command(target)) if target.includes(&device_id, &groups) => match decode_command(data) {
+                                            // El `to` firmado tiene que ser el destino del tópico
+                                            Ok(command) if !target.is_addressed_by(&command.to) => {
+                                                println!("🚫 Comando de {} para {} descartado: {}", command.from, command.to, SignatureError::WrongTarget)
+                                            },
+                                            Ok(command) => match verifier.as_mut().map_or(Ok(Delivery::Fresh), |v| v.verify(&command, wall_clock_ms())) {
+                                                Ok(Delivery::Fresh) => {
+                                                    mqtt_events.post(Event::CommandReceived(command));
+                                                },
+                                                // Redelivery de QoS 1 de un comando ya procesado
+                                                Ok(Delivery::Redelivered) => println!("🔁 Comando {} de {} repetido, ignorado", command.command, command.from),
+                                                Err(reason) => println!("🚫 Comando de {} descartado: {}", command.from, reason),
+                                            },
+                                            Err(e) => println!("❌ Comando inválido: {}", e),
+                                        },
+                                        _ => {},
+                                    },
+                                    // Un handshake TLS rechazado (CA, certificado de cliente) llega aquí
+                                    EventPayload::Error(e) => println!("❌ Error de conexión MQTT: {:?}", e),
+                                    _ => {},
+                                }
+                            }
+
+                            session_client.lock().unwrap().take();
+                            println!("⚠️ Sesión MQTT perdida");
+
+                            // Cerrada antes de conectar: un broker descubierto que ya no está en
+                            // esa dirección, o uno que rechazó MQTT 5 en el CONNACK
+                            if !established {
+                                broker.failed();
+                            }
+                            if !established && negotiation.refused(refusal.take()) {
+                                println!("⚠️ El broker no acepta MQTT 5, se usará MQTT 3.1.1");
+                            }
+                        },
+                        Err(e) => {
+                            println!("❌ No se pudo crear el cliente MQTT: {:?}", e);
+                            broker.failed();
+                        },
+                    }
+                },
+                // Nunca se recurre a un broker público: se vuelve a preguntar tras el backoff
+                Ok(None) => println!("⚠️ Sin broker configurado ni descubierto, se reintentará"),
+                Err(e) => println!("❌ Red no disponible: {:?}", e),
+            }
 
-    // Maneja la conexión MQTT en thread separado
-    std::thread::spawn(move || {
-        while conn.next().is_ok() {}
+            session_link.failed();
+            let delay = backoff.next_delay_ms();
+            println!("⏳ Reintento de conexión {} en {} ms", backoff.attempt(), delay);
+            thread::sleep(Duration::from_millis(delay));
+        }
     });
 
-    FreeRtos::delay_ms(1000);
-    println!("✅ MQTT conectado");
+    // Tarea de publicación. Sin sesión, la telemetría durable se guarda en el
+    // outbox de flash y se reenvía en orden al volver la conexión; el resto se
+    // descarta
+    let tx_client = client.clone();
+    let tx_link = link.clone();
+    let mut outbox = open_outbox(&security_config);
+    #[cfg(feature = "secure")]
+    let outbox_stats = outbox.as_ref().map(|outbox| outbox.stats());
+    spawn_task("mqtt_tx", LARGE_STACK_SIZE, move || loop {
+        let next = match publish_rx.recv_timeout(Duration::from_millis(IDLE_TICK_MS)) {
+            Ok(publication) => Some(publication),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Primero lo que quedó guardado, en el orden en que se produjo
+        if let Some(outbox) = outbox.as_mut() {
+            if tx_link.is_connected() && !outbox.is_empty() {
+                match outbox.replay(OUTBOX_REPLAY_BATCH, |p| tx_link.is_connected() && send(&tx_client, &tx_link, p)) {
+                    Ok(0) => {},
+                    Ok(sent) => println!("📤 {} mensajes reenviados desde flash ({} pendientes)", sent, outbox.len()),
+                    Err(e) => println!("❌ Error leyendo el outbox: {}", e),
+                }
+            }
+        }
+
+        let Some(publication) = next else {
+            continue;
+        };
+
+        // Mientras quede telemetría en flash la nueva va detrás, para no desordenarla
+        let backlog = outbox.as_ref().is_some_and(|outbox| !outbox.is_empty());
+        if !(publication.durable && backlog) && tx_link.is_connected() && send(&tx_client, &tx_link, &publication) {
+            continue;
+        }
 
-    // Configurar botones con pull-up interno
+        match outbox.as_mut() {
+            Some(outbox) if publication.durable => {
+                if let Err(e) = outbox.push(&publication) {
+                    println!("⚠️ Telemetría para {} descartada: {}", publication.topic, e);
+                }
+            },
+            _ => println!("⚠️ Sin conexión MQTT, mensaje para {} descartado", publication.topic),
+        }
+    });
+
+    let clock = EspTimerClock;
+
+    // Tarea de entrada: botones con pull-up interno
     let button1 = PinDriver::input(p.pins.gpio18.downgrade_input()).unwrap();
     let button2 = PinDriver::input(p.pins.gpio19.downgrade_input()).unwrap();  
     let button3 = PinDriver::input(p.pins.gpio21.downgrade_input()).unwrap();
         
-    let mut button_manager = ButtonManager::new(button1, button2, button3);
-    println!("✅ Botones configurados (GPIO18, 19, 21)");
+    let mut button_manager = ButtonBank::new([button1, button2, button3], clock);
+    let input_events = events.clone();
+    spawn_task("input", SMALL_STACK_SIZE, move || loop {
+        if let Some(button_id) = button_manager.check_buttons() {
+            input_events.post(Event::ButtonPressed { button_id, timestamp_ms: clock.now_ms() });
+        }
+        FreeRtos::delay_ms(10);
+    });
+    println!("✅ Botones configurados con debouncing (GPIO18, 19, 21)");
     
-    // Configurar ADC para sensor de temperatura (GPIO32)
-    let mut adc1 = AdcDriver::new(p.adc1).unwrap();
-    let mut adc1_ch6 = AdcChannelDriver::new(&mut adc1, p.pins.gpio32).unwrap();
-    println!("✅ Sensor de temperatura configurado (GPIO32)");
+    // Tarea de sensado: ADC para sensor de temperatura (GPIO32)
+    let adc1 = p.adc1;
+    let gpio32 = p.pins.gpio32;
+    let sensor_events = events.clone();
+    spawn_task("sensing", SMALL_STACK_SIZE, move || {
+        let mut adc1 = AdcDriver::new(adc1).unwrap();
+        let mut adc1_ch6 = AdcChannelDriver::new(&mut adc1, gpio32).unwrap();
+        println!("✅ Sensor de temperatura configurado (GPIO32)");
 
-    // Configurar SPI para RFID
+        loop {
+            FreeRtos::delay_ms(TEMPERATURE_INTERVAL_MS);
+            match read_temperature_sensor(&mut adc1, &mut adc1_ch6) {
+                Ok(celsius) => {
+                    sensor_events.post(Event::Temperature { celsius, timestamp_ms: clock.now_ms() });
+                },
+                Err(e) => {
+                    println!("⚠️  Error leyendo temperatura (fuera de rango): {:?}", e);
+                }
+            }
+        }
+    });
+
+    // Tarea RFID: SPI para el RC522
     let spi_driver = SpiDriver::new(
         p.spi2,
         p.pins.gpio14,  // SCK
@@ -355,153 +602,215 @@ fn main() {
 
     let rst = PinDriver::output(p.pins.gpio27.downgrade_output()).unwrap();
 
-    let mut rfid = Mfrc522::new(spi_device, rst);
-    rfid.init();
+    let mut rfid: RfidReader = Mfrc522::new(spi_device, rst, FreeRtos);
+    let rfid_events = events;
+    spawn_task("rfid", SMALL_STACK_SIZE, move || {
+        println!("🔧 Iniciando RFID RC522...");
+        rfid.init();
+        println!("✅ RFID RC522 inicializado");
+
+        loop {
+            if let Some(uid) = rfid.request().and_then(|_atqa| rfid.anticoll()) {
+                rfid_events.post(Event::CardDetected { uid, timestamp_ms: clock.now_ms() });
+                rfid.halt();
+                // Evita leer la misma tarjeta varias veces seguidas
+                FreeRtos::delay_ms(1000);
+            } else {
+                FreeRtos::delay_ms(50);
+            }
+        }
+    });
 
     println!("🎯 Sistema listo - presiona botones o acerca tarjeta RFID");
 
-    // Variables de control
+    // Tarea principal: convierte eventos en mensajes MQTT
     let mut rfid_counter = 0u32;
-    let mut last_temp_time = 0u64;
+    let mut request_ids = RequestIds::new(&security_config.device_id);
+    let mut pending = PendingCommands::new(clock);
+
+    // Los comandos se firman con la clave de este dispositivo en cmd_keys
+    let signing_key = security_config.command_keys.key(&security_config.device_id).map(<[u8]>::to_vec);
+    if signing_key.is_none() {
+        if cfg!(feature = "secure") {
+            panic!("Sin clave en cmd_keys para {}: la variante segura no envía comandos sin firma", security_config.device_id);
+        }
+        println!("⚠️  Sin clave en cmd_keys para {}: los comandos se envían sin firma", security_config.device_id);
+    }
+    let peer_id = security_config.peer_device_id.clone().unwrap_or_else(|| DEFAULT_PEER_ID.to_string());
+    #[cfg(feature = "secure")]
+    let mut heartbeat_time = 0u64;
+    // Con TLS el broker se verifica contra la CA del proyecto y el dispositivo
+    // se autentica con su propio certificado: su vigencia se revisa cuando
+    // SNTP da la hora y después periódicamente
+    #[cfg(feature = "tls")]
+    let mut cert_check = ExpiryCheck::new();
 
-    // Loop principal
     loop {
-        let current_time = esp_idf_svc::sys::esp_timer_get_time() / 1000; // ms
-        
-        // 1. Verificar botones
-        if let Some(button_id) = button_manager.check_buttons() {
-            println!("🔘 Botón {} presionado!", button_id);
+        match event_rx.recv_timeout(Duration::from_millis(IDLE_TICK_MS)) {
+            Ok(Event::ButtonPressed { button_id, timestamp_ms }) => {
+                println!("🔘 Botón {} presionado!", button_id);
             
-            // Crear mensaje JSON para botón
-            let mut msg_buf = [0u8; 128];
-            let msg_len = {
-                let mut cursor = ArrayWriter::new(&mut msg_buf);
-                write!(
-                    cursor,
-                    r#"{{"device":"esp32-sensor-01","button_id":{},"action":"pressed","timestamp":{}}}"#,
+                // Publicar evento de botón
+                publish_telemetry(&publisher, encoding, &topics::telemetry(&security_config.device_id, "button"), &ButtonEvent {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
                     button_id,
-                    current_time
-                ).unwrap();
-                cursor.pos()
-            };
-
-            // Publicar evento de botón
-            let _ = mqtt.publish(
-                "esp32/button/events",
-                QoS::AtLeastOnce,
-                false,
-                &msg_buf[..msg_len],
-            );
+                    action: "pressed",
+                    boot: boot_id,
+                    timestamp: timestamp_ms,
+                    security: SECURITY_ENABLED,
+                });
             
-            // Si es botón 1, enviar comando a ESP32 #2 (LED toggle)
-            if button_id == 1 {
-                let mut cmd_buf = [0u8; 128];
-                let cmd_len = {
-                    let mut cursor = ArrayWriter::new(&mut cmd_buf);
-                    write!(
-                        cursor,
-                        r#"{{"from":"esp32-sensor-01","to":"esp32-actuator-01","command":"LED_TOGGLE","led_id":1}}"#,
-                    ).unwrap();
-                    cursor.pos()
+                // Comandos hacia ESP32 #2 según el botón presionado
+                let mut command = CommandMessage::new(&security_config.device_id, &peer_id, "");
+                let log = match button_id {
+                    1 => {
+                        command.command = "LED_TOGGLE";
+                        command.led_id = Some(1);
+                        "➡️  Comando LED_TOGGLE enviado a ESP32 #2"
+                    },
+                    2 => {
+                        command.command = "BUZZER";
+                        command.duration = Some(1000);
+                        "🔊 Comando BUZZER enviado a ESP32 #2"
+                    },
+                    #[cfg(feature = "secure")]
+                    3 => {
+                        // Botón de emergencia - apagar todos los LEDs
+                        command.command = "LED_ALL_OFF";
+                        command.emergency = Some(true);
+                        "🚨 Botón de EMERGENCIA - Apagando todos los LEDs"
+                    },
+                    _ => continue,
                 };
-
-                let _ = mqtt.publish(
-                    "esp32/commands",
-                    QoS::AtLeastOnce,
-                    false,
-                    &cmd_buf[..cmd_len],
-                );
+                if send_command(&publisher, &mut request_ids, &mut pending, command, signing_key.as_deref(), security_config.command_expiry_secs, encoding) {
+                    println!("{}", log);
+                }
+            },
+            Ok(Event::Temperature { celsius, timestamp_ms }) => {
+                println!("🌡️  Temperatura: {:.1}°C", celsius);
+                    
+                // Enviar datos de temperatura (redondeados a una décima)
+                publish_telemetry(&publisher, encoding, &topics::telemetry(&security_config.device_id, "temperature"), &TemperatureReading {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
+                    temp: (celsius * 10.0).round() / 10.0,
+                    hum: 0.0,
+                    boot: boot_id,
+                    timestamp: timestamp_ms,
+                    validated: cfg!(feature = "secure").then_some(true),
+                });
+            },
+            Ok(Event::CardDetected { uid, timestamp_ms }) => {
+                rfid_counter += 1;
                 
-                println!("➡️  Comando LED_TOGGLE enviado a ESP32 #2");
-            }
-            
-            // Si es botón 2, activar buzzer en ESP32 #2
-            if button_id == 2 {
-                let mut cmd_buf = [0u8; 128];
-                let cmd_len = {
-                    let mut cursor = ArrayWriter::new(&mut cmd_buf);
-                    write!(
-                        cursor,
-                        r#"{{"from":"esp32-sensor-01","to":"esp32-actuator-01","command":"BUZZER","duration":1000}}"#,
-                    ).unwrap();
-                    cursor.pos()
-                };
-
-                let _ = mqtt.publish(
-                    "esp32/commands",
-                    QoS::AtLeastOnce,
-                    false,
-                    &cmd_buf[..cmd_len],
-                );
+                println!("🏷️  Tarjeta RFID detectada! UID: {:02X}:{:02X}:{:02X}:{:02X} (#{}) ", 
+                         uid[0], uid[1], uid[2], uid[3], rfid_counter);
                 
-                println!("🔊 Comando BUZZER enviado a ESP32 #2");
+                // Publicar evento RFID
+                publish_telemetry(&publisher, encoding, &topics::telemetry(&security_config.device_id, "rfid"), &RfidEvent {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
+                    uid: &uid_hex(&uid),
+                    count: rfid_counter,
+                    boot: boot_id,
+                    timestamp: timestamp_ms,
+                    security: SECURITY_VALIDATED,
+                });
+            },
+            Ok(Event::ResponseReceived(response)) => {
+                let request_id = response.request_id.as_deref().unwrap_or_default();
+                if pending.acknowledge(request_id).is_some() {
+                    if response.ok {
+                        println!("✅ {} confirmado por {}: {}", response.command, response.device, response.message);
+                    } else {
+                        println!("❌ {} falló en {}: {}", response.command, response.device, response.message);
+                    }
+                }
+            },
+            Ok(Event::CommandReceived(command)) if command.command == "ACKNOWLEDGE" => {
+                println!("🤝 ACKNOWLEDGE recibido de {}", command.from);
+            },
+            Ok(Event::MqttConnected) => {
+                // En cada (re)conexión: sustituye el "offline" retenido
+                let mut online = Presence::online(&security_config.device_id, FIRMWARE_VERSION, boot_reason);
+                online.reconnects = Some(link.reconnects());
+                online.boot = boot_id;
+                match Publication::encode_as(&presence_topic, &online, encoding) {
+                    Ok(publication) => {
+                        publisher.post(publication.retained());
+                        println!("🟢 Presencia online publicada (firmware {}, arranque: {})", FIRMWARE_VERSION, boot_reason);
+                    },
+                    Err(e) => println!("❌ Presencia descartada: {}", e),
+                }
+            },
+            Ok(_) => {},
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => {
+                panic!("Todas las tareas productoras terminaron");
             }
-            
-            FreeRtos::delay_ms(300); // Debounce
         }
-        
-        // 2. Leer sensor de temperatura cada 5 segundos
-        if current_time - last_temp_time > 5000 {
-            if let Ok(temperature) = read_temperature_sensor(&mut adc1, &mut adc1_ch6) {
-                println!("🌡️  Temperatura: {:.1}°C", temperature);
-                
-                // Enviar datos de temperatura
-                let mut temp_buf = [0u8; 128];
-                let temp_len = {
-                    let mut cursor = ArrayWriter::new(&mut temp_buf);
-                    write!(
-                        cursor,
-                        r#"{{"device":"esp32-sensor-01","temp":{:.1},"hum":0.0,"timestamp":{}}}"#,
-                        temperature,
-                        current_time
-                    ).unwrap();
-                    cursor.pos()
-                };
 
-                let _ = mqtt.publish(
-                    "esp32/hardware/data",
-                    QoS::AtLeastOnce,
-                    false,
-                    &temp_buf[..temp_len],
-                );
+        // Reenviar comandos sin respuesta y avisar de los que se agotaron
+        for action in pending.poll() {
+            match action {
+                PendingAction::Resend(publication) => {
+                    // Sin clave (solo fuera de la variante segura) se reenvía tal cual
+                    let resent = match signing_key.as_deref() {
+                        Some(key) => resign(&publication, key),
+                        None => Ok(publication),
+                    };
+                    match resent {
+                        Ok(publication) => {
+                            println!("🔁 Reenviando comando sin respuesta");
+                            publisher.post(publication);
+                        },
+                        Err(e) => println!("⚠️  Reintento no enviado: {}", e),
+                    }
+                },
+                PendingAction::Failed(command) => {
+                    println!("🚫 {} ({}) sin respuesta tras {} intentos", command.command, command.request_id, command.attempts);
+                    publish_telemetry(&publisher, encoding, &topics::event(&security_config.device_id, "delivery_failed"), &DeliveryFailed {
+                        v: SCHEMA_VERSION,
+                        device: &security_config.device_id,
+                        to: &command.to,
+                        request_id: &command.request_id,
+                        command: &command.command,
+                        attempts: command.attempts,
+                        boot: boot_id,
+                        timestamp: clock.now_ms(),
+                    });
+                },
             }
-            
-            last_temp_time = current_time;
         }
         
-        // 3. Verificar tarjeta RFID
-        if let Some(_atqa) = rfid.request() {
-            if let Some(uid) = rfid.anticoll() {
-                rfid_counter += 1;
-                
-                println!("🏷️  Tarjeta RFID detectada! UID: {:02X}:{:02X}:{:02X}:{:02X} (#{}) ", 
-                         uid[0], uid[1], uid[2], uid[3], rfid_counter);
-                
-                // Crear mensaje JSON para RFID
-                let mut rfid_buf = [0u8; 128];
-                let rfid_len = {
-                    let mut cursor = ArrayWriter::new(&mut rfid_buf);
-                    write!(
-                        cursor,
-                        r#"{{"device":"esp32-sensor-01","uid":"{:02X}{:02X}{:02X}{:02X}","count":{}}}"#,
-                        uid[0], uid[1], uid[2], uid[3], rfid_counter
-                    ).unwrap();
-                    cursor.pos()
-                };
-
-                // Publicar evento RFID
-                let _ = mqtt.publish(
-                    "esp32/rfid/events",
-                    QoS::AtLeastOnce,
-                    false,
-                    &rfid_buf[..rfid_len],
-                );
-                
-                rfid.halt();
-                FreeRtos::delay_ms(1000);
+        // Heartbeat cada 30 segundos para monitoreo
+        #[cfg(feature = "secure")]
+        {
+            let current_time = clock.now_ms();
+            if current_time - heartbeat_time > 30000 && link.is_connected() {
+                publish(&publisher, encoding, &topics::heartbeat(&security_config.device_id), &Heartbeat {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
+                    status: "healthy",
+                    uptime: current_time / 1000,
+                    security: SECURITY_ENABLED,
+                    commands_processed: None,
+                    dropped_events: Some(publisher.dropped()),
+                    reconnects: Some(link.reconnects()),
+                    outbox_pending: outbox_stats.as_ref().map(|stats| stats.pending()),
+                    outbox_dropped: outbox_stats.as_ref().map(|stats| stats.dropped()),
+                    outbox_policy: outbox_stats.as_ref().map(|_| security_config.outbox_policy.name()),
+                    policy_version: None,
+                });
+
+                heartbeat_time = current_time;
             }
         }
-        
-        FreeRtos::delay_ms(50); // Delay reducido para mejor responsividad
+
+        #[cfg(feature = "tls")]
+        if let Some(now) = cert_check.due(wall_clock_ms().map(|ms| ms / 1000)) {
+            check_certificates(now);
+        }
     }
-}
\ No newline at end of file
+}
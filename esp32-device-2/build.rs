@@ -1,3 +1,67 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+
+use esp32_common::cert::{self, EXPIRY_WARNING_SECS};
+
+// Certificados que genera security/generate_certificates.sh
+const DEVICE_CERT_NAME: &str = "esp32-device-2";
+
 fn main() {
     embuild::espidf::sysenv::output();
-}
\ No newline at end of file
+
+    if env::var_os("CARGO_FEATURE_TLS").is_some() {
+        embed_certificates();
+    }
+}
+
+// Copia a OUT_DIR la CA y el certificado y la clave del dispositivo para que
+// main.rs los incluya con include_bytes!. Si falta alguno o ya caducó la
+// compilación falla aquí, no en el handshake.
+fn embed_certificates() {
+    println!("cargo:rerun-if-env-changed=CERTS_DIR");
+    let certs_dir = env::var("CERTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("../security/certs"));
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let files = [
+        ("ca.der", "ca.der"),
+        (&format!("{}.der", DEVICE_CERT_NAME), "client.der"),
+        (&format!("{}.der.key", DEVICE_CERT_NAME), "client.der.key"),
+    ];
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    for (source, target) in files {
+        let path = certs_dir.join(source);
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let der = fs::read(&path).unwrap_or_else(|e| {
+            panic!(
+                "TLS certificate {} not found ({}). Run security/generate_certificates.sh or set CERTS_DIR",
+                path.display(),
+                e
+            )
+        });
+
+        if source.ends_with(".key") {
+            if der.first() != Some(&0x30) {
+                panic!("{} is not a DER private key", path.display());
+            }
+        } else {
+            let validity = cert::validity(&der).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            if let Err(e) = validity.check(now) {
+                panic!("{}: {}. Regenerate the certificates", path.display(), e);
+            }
+            if validity.remaining_secs(now) < EXPIRY_WARNING_SECS {
+                println!(
+                    "cargo:warning={} expires in {} days",
+                    path.display(),
+                    validity.remaining_secs(now) / 86400
+                );
+            }
+        }
+
+        fs::write(out_dir.join(target), der).unwrap();
+    }
+}
@@ -1,255 +1,537 @@
 use esp_idf_svc::hal::peripherals::Peripherals;
-use esp_idf_svc::hal::gpio::{PinDriver, OutputPin, InputPin};
+use esp_idf_svc::hal::gpio::{AnyOutputPin, InputPin, Output, OutputPin, PinDriver};
 use esp_idf_svc::hal::ledc::{LedcDriver, LedcTimerDriver, config::TimerConfig};
 use esp_idf_svc::hal::delay::FreeRtos;
-use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::mqtt::client::{EspMqttClient, MqttClientConfiguration, QoS, Event, EventPayload};
-use core::fmt::Write;
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use esp_idf_svc::hal::gpio::AnyIOPin;
+use esp_idf_svc::hal::uart::{config::Config as UartConfig, UartDriver};
+use esp_idf_svc::sntp::EspSntp;
+#[cfg(feature = "tls")]
+use esp_idf_svc::tls::X509;
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, MqttProtocolVersion, QoS};
+use esp_idf_svc::sys::{esp, EspError};
+use embedded_hal::pwm::SetDutyCycle;
+use serde::Serialize;
+use esp32_common::{spawn_task, Actuator, Clock, Command, CommandOutcome, Encoding, EspTimerClock, ErrorCode, Event, Publication, RecentCommands, RequestIds, SecurityConfig, Seen, Shadow, SCHEMA_VERSION};
+use esp32_common::drivers::{BuzzerController, BuzzerLimits, ButtonBank, LedController, Tone};
+use esp32_common::event::{self, Sender, EVENT_QUEUE_CAPACITY, PUBLISH_QUEUE_CAPACITY};
+use esp32_common::topics::{self, Route};
+use esp32_common::clock::wall_clock_ms;
+#[cfg(feature = "tls")]
+use esp32_common::cert::ExpiryCheck;
+use esp32_common::credentials::Credentials;
+use esp32_common::discovery::{self, Resolver};
+use esp32_common::link::{Backoff, Link, LinkState, NetworkBackend};
+use esp32_common::mqtt5::{Negotiation, Properties, ProtocolVersion};
+use esp32_common::policy;
+use esp32_common::shadow::DesiredUpdate;
+use esp32_common::signature::Keyring;
+use esp32_common::signature::{Delivery, SignatureError, Verifier};
+use esp32_common::espidf::boot::next_boot_id;
+use esp32_common::espidf::credentials::{open_credentials, provision, take_credential_partition};
+use esp32_common::espidf::credentials::{CredentialNvs, CredentialPartition};
+use esp32_common::espidf::mdns::{query_services, resolve, start_responder};
+use esp32_common::espidf::mqtt::{register_receiver, register_refusal, send, subscribe_all, ConnackRefusal, Receiver, SharedClient};
+use esp32_common::espidf::network::{connect_network, open_ethernet, open_wifi, Network};
+use esp32_common::espidf::outbox::open_outbox;
+use esp32_common::espidf::signing::encode_signed;
+use esp32_common::system;
+use esp32_common::message::{
+    decode_command, decode_desired, encode_as, ButtonEvent, CommandMessage, CommandRejected, CommandResult, DesiredRejected, LedStatus, Presence,
+    QuotaReport, ShadowDelta, ShadowDesired, ShadowReported, MAX_PAYLOAD_LEN,
+};
+#[cfg(feature = "secure")]
+use esp32_common::message::{decode_policy, Heartbeat, PolicyRejected, RateLimitExceeded};
+use esp32_common::quota::{self, QuotaState, QuotaStatus, Quotas};
+#[cfg(feature = "secure")]
+use esp32_common::{Policy, Rejection};
+#[cfg(feature = "secure")]
+use esp32_common::message::MAX_ID_LEN;
+#[cfg(feature = "secure")]
+use esp32_common::policy::Accepted;
+use esp32_common::task::{LARGE_STACK_SIZE, SMALL_STACK_SIZE};
+#[cfg(feature = "secure")]
+use esp32_common::CommandValidator;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-// Estructura para LEDs controlables
-struct LedController<'a> {
-    led1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-    led2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-    led3: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-    states: [bool; 3],
+// Campos extra que añade la variante segura a los mensajes publicados
+#[cfg(feature = "secure")]
+const SECURITY_ENABLED: Option<&str> = Some("enabled");
+#[cfg(not(feature = "secure"))]
+const SECURITY_ENABLED: Option<&str> = None;
+#[cfg(feature = "secure")]
+const SECURITY_VALIDATED: Option<&str> = Some("validated");
+#[cfg(not(feature = "secure"))]
+const SECURITY_VALIDATED: Option<&str> = None;
+
+// El mismo id en las dos variantes: con TLS es el CN del certificado y el
+// usuario de la ACL del broker
+const DEFAULT_DEVICE_ID: &str = "esp32-actuator-01";
+
+// ESP32 #1 si PEER_DEVICE_ID no se definió al compilar
+const DEFAULT_PEER_ID: &str = "esp32-sensor-01";
+
+// Intervalo de publicación de esp32/{device_id}/state
+#[cfg(feature = "secure")]
+const STATUS_INTERVAL_MS: u64 = 15000;
+#[cfg(not(feature = "secure"))]
+const STATUS_INTERVAL_MS: u64 = 10000;
+
+// Cada cuánto se guardan las cuotas en la NVS si cambiaron (agrupa escrituras
+// para no gastar la flash con cada comando)
+const QUOTA_SAVE_INTERVAL_MS: u64 = 10000;
+
+// Salida PWM del buzzer: LEDC con cambio de frecuencia sobre el timer 0
+struct BuzzerPwm<'a>(LedcDriver<'a>);
+
+impl embedded_hal::pwm::ErrorType for BuzzerPwm<'_> {
+    type Error = EspError;
 }
 
-impl<'a> LedController<'a> {
-    fn new(
-        led1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-        led2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-        led3: PinDriver<'a, esp_idf_svc::hal::gpio::AnyOutputPin, esp_idf_svc::hal::gpio::Output>,
-    ) -> Self {
-        LedController {
-            led1,
-            led2,
-            led3,
-            states: [false; 3],
-        }
+impl SetDutyCycle for BuzzerPwm<'_> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.0.get_max_duty() as u16
     }
 
-    fn set_led(&mut self, led_id: u8, state: bool) -> Result<(), esp_idf_svc::sys::EspError> {
-        if led_id < 1 || led_id > 3 {
-            return Err(esp_idf_svc::sys::EspError::from_infallible::<{esp_idf_svc::sys::ESP_ERR_INVALID_ARG}>());
-        }
-        
-        let index = (led_id - 1) as usize;
-        self.states[index] = state;
-        
-        match led_id {
-            1 => if state { self.led1.set_high() } else { self.led1.set_low() },
-            2 => if state { self.led2.set_high() } else { self.led2.set_low() },
-            3 => if state { self.led3.set_high() } else { self.led3.set_low() },
-            _ => return Err(esp_idf_svc::sys::EspError::from_infallible::<{esp_idf_svc::sys::ESP_ERR_INVALID_ARG}>()),
-        }
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), EspError> {
+        self.0.set_duty(duty as u32)
     }
+}
 
-    fn toggle_led(&mut self, led_id: u8) -> Result<bool, esp_idf_svc::sys::EspError> {
-        if led_id < 1 || led_id > 3 {
-            return Err(esp_idf_svc::sys::EspError::from_infallible::<{esp_idf_svc::sys::ESP_ERR_INVALID_ARG}>());
-        }
-        
-        let index = (led_id - 1) as usize;
-        let new_state = !self.states[index];
-        self.set_led(led_id, new_state)?;
-        Ok(new_state)
+impl Tone for BuzzerPwm<'_> {
+    fn set_frequency(&mut self, frequency_hz: u32) -> Result<(), EspError> {
+        esp!(unsafe {
+            esp_idf_svc::sys::ledc_set_freq(
+                esp_idf_svc::sys::ledc_mode_t_LEDC_LOW_SPEED_MODE,
+                esp_idf_svc::sys::ledc_timer_t_LEDC_TIMER_0,
+                frequency_hz,
+            )
+        })
     }
+}
 
-    fn get_state(&self, led_id: u8) -> bool {
-        if led_id < 1 || led_id > 3 {
-            return false;
-        }
-        self.states[(led_id - 1) as usize]
-    }
+type Leds = LedController<PinDriver<'static, AnyOutputPin, Output>, EspTimerClock>;
+type Buzzer = BuzzerController<BuzzerPwm<'static>, FreeRtos, EspTimerClock>;
 
-    fn turn_off_all(&mut self) {
-        for i in 1..=3 {
-            let _ = self.set_led(i, false);
+// Versión publicada en la presencia del dispositivo
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Registros del outbox reenviados como máximo en cada vuelta de la tarea de publicación
+const OUTBOX_REPLAY_BATCH: usize = 8;
+
+// Cada cuánto revisa la tarea principal los envíos periódicos si no llegan eventos
+const IDLE_TICK_MS: u64 = 100;
+
+// Esquema, puerto por defecto y servicio mDNS del broker
+#[cfg(feature = "tls")]
+const BROKER_SCHEME: &str = "mqtts";
+#[cfg(feature = "tls")]
+const BROKER_PORT: u16 = 8883;
+#[cfg(feature = "tls")]
+const BROKER_SERVICE: &str = discovery::MQTTS_SERVICE;
+#[cfg(not(feature = "tls"))]
+const BROKER_SCHEME: &str = "mqtt";
+#[cfg(not(feature = "tls"))]
+const BROKER_PORT: u16 = 1883;
+#[cfg(not(feature = "tls"))]
+const BROKER_SERVICE: &str = discovery::MQTT_SERVICE;
+
+// Puerto del servidor Rust si el registro mDNS no trae otro
+const API_PORT: u16 = 8123;
+
+// Codifica y encola un mensaje; si no cabe en el buffer se descarta con aviso
+fn publish<T: Serialize>(publisher: &Sender<Publication>, encoding: Encoding, topic: &str, message: &T) -> bool {
+    match Publication::encode_as(topic, message, encoding) {
+        Ok(publication) => publisher.post(publication),
+        Err(e) => {
+            println!("❌ Mensaje para {} descartado: {}", topic, e);
+            false
         }
     }
+}
 
-    fn turn_on_all(&mut self) {
-        for i in 1..=3 {
-            let _ = self.set_led(i, true);
+// Telemetría: si no hay conexión se guarda en flash y se reenvía después
+fn publish_telemetry<T: Serialize>(publisher: &Sender<Publication>, encoding: Encoding, topic: &str, message: &T) -> bool {
+    match Publication::encode_as(topic, message, encoding) {
+        Ok(publication) => publisher.post(publication.durable()),
+        Err(e) => {
+            println!("❌ Mensaje para {} descartado: {}", topic, e);
+            false
         }
     }
 }
 
-// Estructura para botones
-struct ButtonController<'a> {
-    button1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    button2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    last_states: [bool; 2],
+// Comandos y estado deseado que llegan por MQTT, con sus propiedades si la sesión es MQTT 5
+struct Inbox {
+    device_id: String,
+    groups: Vec<String>,
+    events: Sender<Event>,
+    // Firmas HMAC; None solo fuera de la variante segura y sin cmd_keys
+    verifier: Option<Mutex<Verifier>>,
 }
 
-impl<'a> ButtonController<'a> {
-    fn new(
-        button1: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-        button2: PinDriver<'a, esp_idf_svc::hal::gpio::AnyInputPin, esp_idf_svc::hal::gpio::Input>,
-    ) -> Self {
-        ButtonController {
-            button1,
-            button2,
-            last_states: [false; 2],
+impl Receiver for Inbox {
+    fn receive(&self, topic: Option<&str>, data: &[u8], properties: Properties) {
+        // Validar que el comando está dirigido a este dispositivo
+        let target = match topic.and_then(topics::parse) {
+            Some(Route::Command(target)) if target.includes(&self.device_id, &self.groups) => target,
+            Some(Route::ShadowDesired(id)) if id == self.device_id => {
+                match decode_desired(data) {
+                    Ok(Some(update)) => {
+                        if !self.events.post(Event::DesiredState(Box::new(update))) {
+                            println!("⚠️ Cola de eventos llena, estado deseado descartado");
+                        }
+                    },
+                    // Retenido borrado: el desired aplicado se mantiene
+                    Ok(None) => {},
+                    Err(e) => println!("❌ Estado deseado inválido: {}", e),
+                }
+                return;
+            },
+            // Un payload vacío borra el documento retenido: la política activa se
+            // mantiene. Se decodifica en el bucle principal, no en la pila de esta tarea
+            Some(Route::Policy(id)) if id == self.device_id && !data.is_empty() => {
+                if data.len() > policy::MAX_POLICY_LEN {
+                    println!("❌ Política inválida: {} bytes (máximo {})", data.len(), policy::MAX_POLICY_LEN);
+                } else if !self.events.post(Event::PolicyReceived(data.to_vec())) {
+                    println!("⚠️ Cola de eventos llena, política descartada");
+                }
+                return;
+            },
+            _ => {
+                println!("⚠️ Mensaje ignorado en {:?}", topic);
+                return;
+            }
+        };
+        println!("📨 Comando recibido: {}", String::from_utf8_lossy(data));
+
+        let mut command = match decode_command(data) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("❌ Comando inválido: {}", e);
+                return;
+            }
+        };
+
+        // Con MQTT 5 la identidad del emisor viaja también como user property
+        if let Err(e) = properties.check_sender(&command.from) {
+            println!("🚫 Comando descartado: {}", e);
+            return;
         }
-    }
-    
-    fn check_buttons(&mut self) -> Option<u8> {
-        let states = [
-            self.button1.is_low(),
-            self.button2.is_low(),
-        ];
-        
-        for (i, (&current, &last)) in states.iter().zip(self.last_states.iter()).enumerate() {
-            if current && !last { // Detecta flanco descendente
-                self.last_states[i] = current;
-                return Some(i as u8 + 1);
+
+        // El `to` va firmado: un comando para otro destino republicado aquí se
+        // descarta como una firma inválida
+        if !target.is_addressed_by(&command.to) {
+            let reason = SignatureError::WrongTarget;
+            println!("🚫 Comando de {} para {} descartado: {}", command.from, command.to, reason);
+            if !self.events.post(Event::CommandRejected { command, reason }) {
+                println!("⚠️ Cola de eventos llena, rechazo no publicado");
             }
-            self.last_states[i] = current;
+            return;
+        }
+
+        // Firma, timestamp y nonce antes que nada más: hasta aquí el `from` no
+        // está verificado, así que el rechazo no se responde
+        let delivery = match &self.verifier {
+            Some(verifier) => match verifier.lock().unwrap().verify(&command, wall_clock_ms()) {
+                Ok(delivery) => delivery,
+                Err(reason) => {
+                    println!("🚫 Comando de {} descartado: {}", command.from, reason);
+                    if !self.events.post(Event::CommandRejected { command, reason }) {
+                        println!("⚠️ Cola de eventos llena, rechazo no publicado");
+                    }
+                    return;
+                }
+            },
+            None => Delivery::Fresh,
+        };
+        if delivery == Delivery::Redelivered {
+            if !self.events.post(Event::CommandRedelivered(command)) {
+                println!("⚠️ Cola de eventos llena, comando repetido descartado");
+            }
+            return;
+        }
+        command.reply_to = properties.reply_to(&command.from).map(Box::new);
+
+        // Validar parámetros del comando
+        let event = match command.validate_parameters() {
+            Ok(_) => Event::CommandReceived(command),
+            Err(e) => {
+                println!("❌ Comando rechazado por parámetros inválidos: {}", e);
+                Event::CommandDone(CommandOutcome::failed(command, &e))
+            }
+        };
+        if !self.events.post(event) {
+            println!("⚠️ Cola de eventos llena, comando descartado");
         }
-        
-        None
     }
 }
 
-// Estructura para buzzer
-struct BuzzerController<'a> {
-    pwm: LedcDriver<'a>,
+// Publica el estado real de los LEDs, retenido
+fn publish_reported(publisher: &Sender<Publication>, encoding: Encoding, device_id: &str, shadow: &Shadow, timestamp: u64) {
+    let reported = ShadowReported {
+        v: SCHEMA_VERSION,
+        device: device_id,
+        leds: shadow.reported(),
+        timestamp,
+    };
+    match Publication::encode_as(&topics::shadow(device_id, "reported"), &reported, encoding) {
+        Ok(publication) => {
+            publisher.post(publication.retained());
+        },
+        Err(e) => println!("❌ Estado reportado descartado: {}", e),
+    }
 }
 
-impl<'a> BuzzerController<'a> {
-    fn new(pwm: LedcDriver<'a>) -> Self {
-        BuzzerController { pwm }
+// Publica el delta si el estado real diverge del deseado y no se publicó ya
+fn publish_delta(publisher: &Sender<Publication>, encoding: Encoding, device_id: &str, shadow: &mut Shadow, timestamp: u64) {
+    if let Some(leds) = shadow.take_delta() {
+        println!("🔀 LEDs distintos del estado deseado: {:?}", leds);
+        publish(publisher, encoding, &topics::shadow(device_id, "delta"), &ShadowDelta {
+            v: SCHEMA_VERSION,
+            device: device_id,
+            leds,
+            timestamp,
+        });
     }
+}
 
-    fn beep(&mut self, frequency: u32, duration_ms: u64) -> Result<(), esp_idf_svc::sys::EspError> {
-        // Configurar frecuencia y duty cycle
-        self.pwm.set_frequency(frequency)?;
-        self.pwm.set_duty(512)?; // 50% duty cycle (máx 1023 para 10 bits)
-        
-        FreeRtos::delay_ms(duration_ms as u32);
-        
-        // Apagar buzzer
-        self.pwm.set_duty(0)?;
-        Ok(())
-    }
+// Publica el resultado de un comando para quien lo envió
+fn respond(
+    publisher: &Sender<Publication>,
+    encoding: Encoding,
+    device_id: &str,
+    outcome: &CommandOutcome,
+    leds: [bool; 3],
+    timestamp: u64,
+) {
+    let result = CommandResult::new(device_id, outcome, leds, timestamp);
 
-    fn triple_beep(&mut self) -> Result<(), esp_idf_svc::sys::EspError> {
-        for _ in 0..3 {
-            self.beep(1000, 200)?;
-            FreeRtos::delay_ms(100);
+    // MQTT 5: al response topic del comando, con su correlation data
+    if let Some(reply_to) = &outcome.command.reply_to {
+        match Publication::encode_as(&reply_to.topic, &result, encoding) {
+            Ok(publication) => {
+                publisher.post(publication.with_properties(Properties::reply(reply_to, device_id)));
+            },
+            Err(e) => println!("❌ Respuesta para {} descartada: {}", reply_to.topic, e),
         }
-        Ok(())
+        return;
     }
 
-    fn startup_sound(&mut self) -> Result<(), esp_idf_svc::sys::EspError> {
-        self.beep(500, 100)?;
-        FreeRtos::delay_ms(50);
-        self.beep(750, 100)?;
-        FreeRtos::delay_ms(50);
-        self.beep(1000, 200)?;
-        Ok(())
+    // Si no, al buzón de quien envió el comando
+    let sender = &outcome.command.from;
+    if !topics::is_valid_id(sender) {
+        println!("⚠️ No se puede responder a '{}': id inválido para un tópico", sender);
+        return;
     }
+    publish(publisher, encoding, &topics::responses(sender), &result);
 }
 
-// Helper para JSON
-struct ArrayWriter<'a> {
-    buf: &'a mut [u8],
-    pos: usize,
+// Certificados embebidos por build.rs desde security/certs
+#[cfg(feature = "tls")]
+const CA_CERT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ca.der"));
+#[cfg(feature = "tls")]
+const CLIENT_CERT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/client.der"));
+#[cfg(feature = "tls")]
+const CLIENT_KEY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/client.der.key"));
+
+// build.rs ya rechaza certificados caducados al compilar; aquí se repite la
+// comprobación con la hora de SNTP por si el firmware lleva tiempo flasheado
+// o encendido. Un certificado caducado no se arregla reiniciando, así que
+// solo se avisa: el handshake TLS fallará igualmente
+#[cfg(feature = "tls")]
+fn check_certificates(now: u64) {
+    use esp32_common::cert::{self, EXPIRY_WARNING_SECS};
+
+    for (name, der) in [("CA", CA_CERT), ("cliente", CLIENT_CERT)] {
+        match cert::validity(der).and_then(|v| v.check(now).map(|()| v.remaining_secs(now))) {
+            Ok(remaining) if remaining < EXPIRY_WARNING_SECS => {
+                println!("⚠️ Certificado {} caduca en {} días", name, remaining / 86400)
+            },
+            Ok(_) => println!("🔒 Certificado {} vigente", name),
+            Err(e) => println!("❌ Certificado {} inutilizable: {}", name, e),
+        }
+    }
 }
 
-impl<'a> ArrayWriter<'a> {
-    fn new(buf: &'a mut [u8]) -> Self {
-        ArrayWriter { buf, pos: 0 }
+// Política de autorización activa, guardada tal como llegó (JSON o CBOR)
+// en la partición cifrada de las credenciales, con la versión y el emisor
+// con los que se aceptó
+#[cfg(feature = "secure")]
+struct PolicyStore(EspNvs<CredentialPartition>);
+
+#[cfg(feature = "secure")]
+impl PolicyStore {
+    const NAMESPACE: &'static str = "policy";
+    const KEY: &'static str = "active";
+    const VERSION_KEY: &'static str = "version";
+    const FROM_KEY: &'static str = "from";
+
+    fn open(partition: CredentialNvs) -> Result<Self, EspError> {
+        Ok(PolicyStore(EspNvs::new(partition, Self::NAMESPACE, true)?))
     }
-    
-    fn pos(&self) -> usize {
-        self.pos
+
+    // None si nunca se aceptó ninguna
+    fn accepted(&self) -> Result<Option<Accepted>, EspError> {
+        let Some(version) = self.0.get_u32(Self::VERSION_KEY)? else {
+            return Ok(None);
+        };
+        let mut buf = [0u8; MAX_ID_LEN + 1];
+        let from = self.0.get_str(Self::FROM_KEY, &mut buf)?.unwrap_or_default().to_string();
+        Ok(Some(Accepted { version, from }))
     }
-}
 
-impl<'a> core::fmt::Write for ArrayWriter<'a> {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        let bytes = s.as_bytes();
-        let end = self.pos + bytes.len();
-        if end > self.buf.len() {
-            return Err(core::fmt::Error);
+    // La política guardada y la versión mínima para la siguiente. Si el
+    // documento no se puede leer o ya no se verifica rige la de fábrica, pero
+    // la versión aceptada sigue siendo el mínimo
+    fn load(&self, keys: &Keyring) -> (Policy, u32) {
+        let accepted = match self.accepted() {
+            Ok(Some(accepted)) => accepted,
+            Ok(None) => return (Policy::default(), 0),
+            Err(e) => {
+                println!("⚠️ No se pudo leer la política guardada: {:?}", e);
+                return (Policy::default(), 0);
+            }
+        };
+
+        let mut buf = vec![0u8; policy::MAX_POLICY_LEN];
+        let restored = match self.0.get_raw(Self::KEY, &mut buf) {
+            Ok(Some(raw)) => decode_policy(raw)
+                .map_err(|e| e.to_string())
+                .and_then(|update| Policy::restore(update, keys, &accepted).map_err(|e| e.to_string())),
+            Ok(None) => Err("document missing".to_string()),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        match restored {
+            Ok(policy) => (policy, accepted.version),
+            Err(e) => {
+                println!("⚠️ Política guardada v{} inválida, se usa la de fábrica: {}", accepted.version, e);
+                (Policy::default(), accepted.version)
+            }
         }
-        self.buf[self.pos..end].copy_from_slice(bytes);
-        self.pos = end;
-        Ok(())
     }
-}
 
-// Estructura para comandos recibidos
-#[derive(Debug)]
-struct Command {
-    from: String,
-    to: String,
-    command: String,
-    led_id: Option<u8>,
-    duration: Option<u64>,
+    // La versión primero: si la escritura se corta a medias el documento no
+    // se restaura, pero tampoco se puede volver a aceptar uno anterior
+    fn save(&mut self, raw: &[u8], accepted: &Accepted) -> Result<(), EspError> {
+        self.0.set_u32(Self::VERSION_KEY, accepted.version)?;
+        self.0.set_str(Self::FROM_KEY, &accepted.from)?;
+        self.0.set_raw(Self::KEY, raw).map(|_| ())
+    }
 }
 
-impl Command {
-    fn from_json(json_str: &str) -> Option<Self> {
-        // Parser JSON básico manual (sin serde para ahorrar memoria)
-        if let (Some(from), Some(to), Some(command)) = (
-            extract_json_string(json_str, "from"),
-            extract_json_string(json_str, "to"),
-            extract_json_string(json_str, "command"),
-        ) {
-            let led_id = extract_json_number(json_str, "led_id").map(|n| n as u8);
-            let duration = extract_json_number(json_str, "duration").map(|n| n as u64);
-            
-            Some(Command {
-                from,
-                to,
-                command,
-                led_id,
-                duration,
-            })
-        } else {
-            None
-        }
+// Último estado deseado aceptado y su versión, en la partición cifrada como
+// la política: tras un reinicio un desired antiguo retenido o repetido no
+// vuelve a aplicarse
+struct DesiredStore(EspNvs<CredentialPartition>);
+
+impl DesiredStore {
+    const NAMESPACE: &'static str = "shadow";
+    const KEY: &'static str = "desired";
+    const VERSION_KEY: &'static str = "version";
+
+    fn open(partition: CredentialNvs) -> Result<Self, EspError> {
+        Ok(DesiredStore(EspNvs::new(partition, Self::NAMESPACE, true)?))
     }
-}
 
-// Helpers para parsing JSON manual
-fn extract_json_string(json: &str, key: &str) -> Option<String> {
-    let search = format!("\"{}\":", key);
-    if let Some(start) = json.find(&search) {
-        let after_colon = &json[start + search.len()..];
-        if let Some(quote_start) = after_colon.find('\"') {
-            let value_start = quote_start + 1;
-            if let Some(quote_end) = after_colon[value_start..].find('\"') {
-                return Some(after_colon[value_start..value_start + quote_end].to_string());
+    // La sombra con el desired guardado. Si el documento no se puede leer o
+    // ya no se verifica queda sin desired, pero la versión aceptada sigue
+    // siendo el mínimo
+    fn load(&self, keys: &Keyring, device_id: &str, reported: [bool; 3]) -> Shadow {
+        let version = match self.0.get_u32(Self::VERSION_KEY) {
+            Ok(Some(version)) => version,
+            Ok(None) => return Shadow::new(reported),
+            Err(e) => {
+                println!("⚠️ No se pudo leer el estado deseado guardado: {:?}", e);
+                return Shadow::new(reported);
             }
+        };
+        let mut shadow = Shadow::new(reported).with_floor(version);
+
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        let restored = match self.0.get_raw(Self::KEY, &mut buf) {
+            Ok(Some(raw)) => match decode_desired(raw) {
+                Ok(Some(update)) => shadow.restore(update, keys, device_id).map_err(|e| e.to_string()),
+                Ok(None) => Err("document missing".to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+            Ok(None) => Err("document missing".to_string()),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        if let Err(e) = restored {
+            println!("⚠️ Estado deseado guardado v{} inválido, se espera uno nuevo: {}", version, e);
         }
+        shadow
+    }
+
+    // La versión primero: si la escritura se corta a medias el documento no
+    // se restaura, pero tampoco se puede volver a aceptar uno anterior
+    fn save(&mut self, update: &DesiredUpdate) -> Result<(), EspError> {
+        self.0.set_u32(Self::VERSION_KEY, update.version)?;
+        let wire = ShadowDesired {
+            v: None,
+            version: update.version,
+            from: &update.from,
+            sig: &update.signature,
+            leds: update.leds,
+        };
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        let len = encode_as(&wire, Encoding::Json, &mut buf).map_err(|_| EspError::from_infallible::<{esp_idf_svc::sys::ESP_ERR_INVALID_SIZE}>())?;
+        self.0.set_raw(Self::KEY, &buf[..len]).map(|_| ())
     }
-    None
 }
 
-fn extract_json_number(json: &str, key: &str) -> Option<u32> {
-    let search = format!("\"{}\":", key);
-    if let Some(start) = json.find(&search) {
-        let after_colon = &json[start + search.len()..];
-        let mut number_str = String::new();
-        for c in after_colon.chars() {
-            if c.is_ascii_digit() {
-                number_str.push(c);
-            } else if !number_str.is_empty() {
-                break;
-            }
+// Cuotas diarias (esp32_common::quota): día y contadores, para que reiniciar
+// no las ponga a cero
+struct QuotaStore(EspNvs<NvsDefault>);
+
+impl QuotaStore {
+    const NAMESPACE: &'static str = "quota";
+    const KEY: &'static str = "state";
+
+    fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(QuotaStore(EspNvs::new(partition, Self::NAMESPACE, true)?))
+    }
+
+    fn load(&self) -> QuotaState {
+        let mut buf = [0u8; quota::STATE_LEN];
+        match self.0.get_raw(Self::KEY, &mut buf) {
+            Ok(Some(raw)) => QuotaState::from_bytes(raw).unwrap_or_default(),
+            Ok(None) => QuotaState::default(),
+            Err(e) => {
+                println!("⚠️ No se pudieron leer las cuotas guardadas: {:?}", e);
+                QuotaState::default()
+            },
         }
-        number_str.parse().ok()
-    } else {
-        None
+    }
+
+    fn save(&mut self, state: &QuotaState) -> Result<(), EspError> {
+        self.0.set_raw(Self::KEY, &state.to_bytes()).map(|_| ())
+    }
+}
+
+// Lo que queda de las cuotas, retenido en esp32/{device_id}/quota
+fn publish_quota(publisher: &Sender<Publication>, encoding: Encoding, device_id: &str, status: &QuotaStatus, timestamp: u64) {
+    let report = QuotaReport {
+        v: SCHEMA_VERSION,
+        device: device_id,
+        buzzer_remaining: status.buzzer_remaining,
+        buzzer_limit: status.buzzer_limit,
+        commands_remaining: status.commands_remaining,
+        commands_limit: status.commands_limit,
+        resets_in_ms: status.resets_in_ms,
+        timestamp,
+    };
+    match Publication::encode_as(&topics::quota(device_id), &report, encoding) {
+        Ok(publication) => {
+            publisher.post(publication.retained());
+        },
+        Err(e) => println!("❌ Estado de las cuotas descartado: {}", e),
     }
 }
 
@@ -257,44 +539,116 @@ fn main() {
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
 
+    #[cfg(feature = "secure")]
+    println!("🔒 ESP32 Device #2 SECURE - Actuator (LEDs + Buzzer + Buttons)");
+    #[cfg(not(feature = "secure"))]
     println!("🚀 ESP32 Device #2 - Actuator (LEDs + Buzzer + Buttons)");
-    println!("📡 Conectando a WiFi y MQTT...");
+    println!("📡 Conectando a la red y MQTT...");
 
     let p = Peripherals::take().unwrap();
-    let s = EspSystemEventLoop::take().unwrap();
-    let n = EspDefaultNvsPartition::take().unwrap();
 
-    // Configurar WiFi
-    let mut w = BlockingWifi::wrap(
-        EspWifi::new(p.modem, s.clone(), Some(n.clone())).unwrap(),
-        s,
-    ).unwrap();
+    // Credenciales desde la NVS cifrada; si faltan, aprovisionamiento por UART0.
+    // La variante segura exige las claves de firma y WiFi solo se pide con NETWORK=wifi
+    let requirements = match SecurityConfig::requirements(cfg!(feature = "secure")) {
+        Ok(requirements) => requirements,
+        Err(e) => panic!("Configuración de compilación inválida: {}", e),
+    };
+    let credential_partition = match take_credential_partition() {
+        Ok(partition) => partition,
+        Err(e) => {
+            println!("❌ Partición de credenciales no disponible: {:?}", e);
+            panic!("No se puede continuar sin la partición creds");
+        }
+    };
+    let mut secrets = match open_credentials(credential_partition.clone()) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            println!("❌ Partición de credenciales no disponible: {:?}", e);
+            panic!("No se puede continuar sin la partición creds");
+        }
+    };
+    let credentials = match Credentials::load(&secrets, requirements) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            println!("🔐 {}", e);
+            let uart = UartDriver::new(
+                p.uart0,
+                p.pins.gpio1,
+                p.pins.gpio3,
+                Option::<AnyIOPin>::None,
+                Option::<AnyIOPin>::None,
+                &UartConfig::default(),
+            ).unwrap();
+            provision(&mut secrets, requirements, DEFAULT_DEVICE_ID, uart);
+        }
+    };
 
-    w.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: "UTP".try_into().unwrap(),
-        password: "tecnologica".try_into().unwrap(),
-        ..Default::default()
-    })).unwrap();
+    // Cargar configuración de seguridad
+    let security_config = match SecurityConfig::load(credentials) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("❌ Error cargando configuración de seguridad: {}", e);
+            panic!("No se puede continuar sin configuración segura");
+        }
+    };
 
-    w.start().unwrap();
-    w.connect().unwrap();
-    w.wait_netif_up().unwrap();
-    println!("✅ WiFi conectado");
+    println!("🔑 Configuración cargada para device: {}", security_config.device_id);
+
+    // Con TLS el broker toma el CN del certificado como usuario de la ACL:
+    // con otro device_id el dispositivo no podría usar sus propios tópicos
+    #[cfg(feature = "tls")]
+    match esp32_common::cert::common_name(CLIENT_CERT) {
+        Ok(cn) if cn == security_config.device_id => {},
+        Ok(cn) => panic!("El certificado de cliente es de '{}' pero el dispositivo está aprovisionado como '{}'", cn, security_config.device_id),
+        Err(e) => panic!("Certificado de cliente inutilizable: {}", e),
+    }
+
+    // JSON o CBOR para todo lo que publica este dispositivo
+    let encoding = security_config.payload_encoding;
+    println!("📦 Payloads en {}", encoding.name());
+
+    let boot_reason = system::reset_reason();
+    println!("🔄 Motivo del arranque: {}", boot_reason);
+
+    let s = EspSystemEventLoop::take().unwrap();
+    let n = EspDefaultNvsPartition::take().unwrap();
+    // Acompaña a los timestamps de la telemetría, que son desde el arranque
+    let boot_id = next_boot_id(n.clone());
+    if let Some(boot_id) = boot_id {
+        println!("🔢 Arranque #{}", boot_id);
+    }
+
+    // Red elegida con NETWORK; la conexión la gestiona la tarea supervisora
+    println!("🌐 Red: {}", security_config.network.name());
+    let mut network: Box<dyn Network> = match security_config.network {
+        NetworkBackend::Wifi => open_wifi(
+            p.modem,
+            s,
+            n.clone(),
+            security_config.wifi.as_ref().expect("SecurityConfig exige WiFi con NETWORK=wifi"),
+        ),
+        NetworkBackend::Ethernet => open_ethernet(p.mac, s),
+    };
 
     // Configurar LEDs (GPIO 25, 26, 27)
     let led1 = PinDriver::output(p.pins.gpio25.downgrade_output()).unwrap();
     let led2 = PinDriver::output(p.pins.gpio26.downgrade_output()).unwrap();
     let led3 = PinDriver::output(p.pins.gpio27.downgrade_output()).unwrap();
-    
-    let mut led_controller = LedController::new(led1, led2, led3);
+
+    let clock = EspTimerClock;
+    let mut led_controller: Leds = LedController::new([led1, led2, led3], clock);
+    if cfg!(feature = "secure") {
+        // Rate limiting: no más de un cambio por segundo por LED
+        led_controller = led_controller.with_rate_limit(1000);
+    }
     println!("✅ LEDs configurados (GPIO25, 26, 27)");
-    
+
     // Configurar botones (GPIO 18, 19)
     let button1 = PinDriver::input(p.pins.gpio18.downgrade_input()).unwrap();
     let button2 = PinDriver::input(p.pins.gpio19.downgrade_input()).unwrap();
-    
-    let mut button_controller = ButtonController::new(button1, button2);
-    println!("✅ Botones configurados (GPIO18, 19)");
+
+    let mut button_controller = ButtonBank::new([button1, button2], clock);
+    println!("✅ Botones configurados con debouncing (GPIO18, 19)");
 
     // Configurar buzzer con PWM (GPIO 21)
     let timer_config = TimerConfig::new().frequency(1000.into());
@@ -304,221 +658,682 @@ fn main() {
         timer,
         p.pins.gpio21,
     ).unwrap();
-    
-    let mut buzzer = BuzzerController::new(pwm);
-    println!("✅ Buzzer configurado (GPIO21)");
 
-    // Sonido de inicio
-    let _ = buzzer.startup_sound();
+    let mut buzzer: Buzzer = BuzzerController::new(BuzzerPwm(pwm), FreeRtos, clock);
+    if cfg!(feature = "secure") {
+        buzzer = buzzer.with_limits(BuzzerLimits::default());
+    }
+    println!("✅ Buzzer configurado (GPIO21)");
 
-    // Test de LEDs
-    println!("🔄 Test de LEDs...");
-    for i in 1..=3 {
-        let _ = led_controller.set_led(i, true);
-        FreeRtos::delay_ms(200);
-        let _ = led_controller.set_led(i, false);
-        FreeRtos::delay_ms(200);
+    let mut actuator = Actuator::new(led_controller, buzzer);
+    if cfg!(feature = "secure") {
+        actuator = actuator.with_emergency_stop();
     }
 
-    // Configurar MQTT
-    let mqtt_conf = MqttClientConfiguration {
-        keep_alive_interval: Some(core::time::Duration::from_secs(30)),
-        ..Default::default()
-    };
+    // Hora real: sin ella no se puede juzgar el timestamp de los comandos firmados
+    let _sntp = EspSntp::new_default()
+        .map_err(|e| println!("⚠️ SNTP no disponible: {:?}", e))
+        .ok();
 
-    let (mqtt, mut conn) = EspMqttClient::new(
-        "mqtt://broker.hivemq.com:1883",
-        &mqtt_conf,
-    ).unwrap();
+    // mDNS: el dispositivo se anuncia como {device_id}.local y busca el broker
+    // y el servidor si no se configuraron al compilar
+    let mdns = start_responder(&security_config.device_id);
+    let mut broker = Resolver::new(security_config.mqtt_broker.clone());
+    let mut api_server = Resolver::new(security_config.api_server.clone());
 
-    // Suscribirse a comandos
-    mqtt.subscribe("esp32/commands", QoS::AtLeastOnce).unwrap();
-    println!("✅ Suscrito a esp32/commands");
-
-    // Variables compartidas para comunicación entre threads
-    let led_states = Arc::new(Mutex::new([false; 3]));
-    let command_queue = Arc::new(Mutex::new(Vec::<Command>::new()));
-    
-    // Thread para manejar MQTT
-    let mqtt_clone = mqtt.clone();
-    let led_states_clone = led_states.clone();
-    let command_queue_clone = command_queue.clone();
-    
-    thread::spawn(move || {
-        println!("🔄 Iniciando thread MQTT...");
+    // Comandos: buzón propio, grupos y broadcast; y el desired de la sombra,
+    // que al ser retenido llega en cada suscripción
+    let mut subscriptions = topics::command_subscriptions(&security_config.device_id, &security_config.groups);
+    subscriptions.push(topics::shadow(&security_config.device_id, "desired"));
+    // Política de autorización firmada, también retenida
+    #[cfg(feature = "secure")]
+    subscriptions.push(topics::policy(&security_config.device_id));
+
+    let presence_topic = topics::presence(&security_config.device_id);
+
+    // Colas entre tareas: todos los eventos llegan a la tarea principal, que
+    // reparte comandos a la tarea de actuación y mensajes a la de publicación
+    let (events, event_rx) = event::channel::<Event>(EVENT_QUEUE_CAPACITY);
+    let (actions, action_rx) = event::channel::<Command>(EVENT_QUEUE_CAPACITY);
+    let (publisher, publish_rx) = event::channel::<Publication>(PUBLISH_QUEUE_CAPACITY);
+
+    let client: SharedClient = Arc::new(Mutex::new(None));
+    let link = Link::new();
+
+    // Tarea supervisora: red, sesión MQTT y recepción de comandos. Si algo
+    // falla espera con backoff y vuelve a empezar, con nuevas suscripciones en
+    // cada sesión
+    let session_client = client.clone();
+    let session_link = link.clone();
+    let mqtt_events = events.clone();
+    let device_id = security_config.device_id.clone();
+    let groups = security_config.groups.clone();
+    let command_keys = security_config.command_keys.clone();
+    // La variante segura no arranca sin cmd_keys (credentials::Requirements) y
+    // rechaza todo comando sin firma válida, aunque ningún emisor tenga clave
+    let verify_signatures = cfg!(feature = "secure") || !command_keys.is_empty();
+    if !verify_signatures {
+        println!("⚠️ cmd_keys sin aprovisionar: se aceptan comandos sin firma");
+    } else {
+        println!("🔏 Comandos firmados de: {}", command_keys.senders().collect::<Vec<_>>().join(", "));
+    }
+    let will_topic = presence_topic.clone();
+    let mqtt_protocol = security_config.mqtt_protocol;
+    let mqtt_username = security_config.mqtt_username.clone();
+    let mqtt_password = security_config.mqtt_password.clone();
+    spawn_task("link", LARGE_STACK_SIZE, move || {
+        // Si el dispositivo se cae sin desconectar, el broker publica "offline"
+        // retenido en su tópico de presencia
+        let last_will = Publication::encode_as(&will_topic, &Presence::offline(&device_id), encoding).unwrap();
+        let mut mqtt_conf = MqttClientConfiguration {
+            #[cfg(feature = "secure")]
+            client_id: Some(&device_id),
+            #[cfg(feature = "tls")]
+            server_certificate: Some(X509::der(CA_CERT)),
+            #[cfg(feature = "tls")]
+            client_certificate: Some(X509::der(CLIENT_CERT)),
+            #[cfg(feature = "tls")]
+            private_key: Some(X509::der(CLIENT_KEY)),
+            // Usuario y contraseña del broker, si se aprovisionaron
+            username: mqtt_username.as_deref(),
+            password: mqtt_password.as_deref(),
+            keep_alive_interval: Some(core::time::Duration::from_secs(30)),
+            lwt: Some(LwtConfiguration {
+                topic: &will_topic,
+                payload: &last_will.payload,
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            // Los reintentos los decide el backoff de esta tarea
+            disable_auto_reconnect: true,
+            ..Default::default()
+        };
+
+        let mut backoff = Backoff::new(unsafe { esp_idf_svc::sys::esp_random() });
+        let mut negotiation = Negotiation::new(mqtt_protocol);
+        let refusal = Box::new(ConnackRefusal::default());
+        let inbox = Box::new(Inbox {
+            device_id: device_id.clone(),
+            groups,
+            events: mqtt_events.clone(),
+            verifier: verify_signatures.then(|| Mutex::new(Verifier::new(command_keys))),
+        });
         loop {
-            match conn.next() {
-                Ok(Event::Received(msg)) => {
-                    if let Ok(payload) = std::str::from_utf8(&msg.payload) {
-                        println!("📨 Comando recibido: {}", payload);
-                        
-                        if let Some(command) = Command::from_json(payload) {
-                            if command.to == "esp32-actuator-01" {
-                                let mut queue = command_queue_clone.lock().unwrap();
-                                queue.push(command);
+            // Con la red arriba se pregunta por mDNS lo que no se configuró al compilar
+            let session = connect_network(network.as_mut(), &session_link).map(|()| {
+                if api_server.needs_query() {
+                    if let Some(endpoint) = api_server.discovered(&query_services(mdns.as_ref(), discovery::API_SERVICE)) {
+                        println!("🔎 Servidor encontrado por mDNS: {}", endpoint.url("http", API_PORT));
+                    }
+                }
+                resolve(mdns.as_ref(), &mut broker, BROKER_SERVICE)
+            });
+            match session {
+                Ok(Some(endpoint)) => {
+                    let mqtt_url = endpoint.url(BROKER_SCHEME, BROKER_PORT);
+
+                    session_link.set(LinkState::MqttConnecting);
+                    session_link.set_protocol(negotiation.current());
+                    mqtt_conf.protocol_version = Some(match negotiation.current() {
+                        ProtocolVersion::V5 => MqttProtocolVersion::V5,
+                        ProtocolVersion::V311 => MqttProtocolVersion::V3_1_1,
+                    });
+                    match EspMqttClient::new(&mqtt_url, &mqtt_conf) {
+                        Ok((mqtt, mut conn)) => {
+                            let mut established = false;
+                            // `refusal` vive mientras la tarea supervisora, es decir, siempre
+                            if let Err(e) = unsafe { register_refusal(&mqtt, &*refusal) } {
+                                println!("❌ No se pudo registrar el rechazo del CONNACK: {:?}", e);
                             }
-                        }
+                            // El Inbox vive mientras la tarea supervisora, es decir, siempre
+                            if let Err(e) = unsafe { register_receiver(&mqtt, &*inbox) } {
+                                println!("❌ No se pudo registrar la recepción de comandos: {:?}", e);
+                            }
+                            *session_client.lock().unwrap() = Some(mqtt);
+
+                            // La sesión dura hasta que se pierde el broker o la red
+                            while let Ok(event) = conn.next() {
+                                match event.payload() {
+                                    EventPayload::Connected(_) => {
+                                        // El broker aceptó la versión aunque falle lo siguiente
+                                        negotiation.connected();
+                                        if !subscribe_all(&session_client, &subscriptions) {
+                                            break;
+                                        }
+                                        backoff.reset();
+                                        established = true;
+                                        session_link.connected();
+                                        println!(
+                                            "✅ MQTT {} conectado (reconexiones: {})",
+                                            negotiation.current().name(),
+                                            session_link.reconnects()
+                                        );
+                                        mqtt_events.post(Event::MqttConnected);
+                                    },
+                                    EventPayload::Disconnected => break,
+                                    // Un handshake TLS rechazado (CA, certificado de cliente) llega aquí
+                                    EventPayload::Error(e) => println!("❌ Error de conexión MQTT: {:?}", e),
+                                    // Los comandos los recibe el Inbox
+                                    _ => {},
+                                }
+                            }
+
+                            session_client.lock().unwrap().take();
+                            println!("⚠️ Sesión MQTT perdida");
+
+                            // Cerrada antes de conectar: un broker descubierto que ya no está en
+                            // esa dirección, o uno que rechazó MQTT 5 en el CONNACK
+                            if !established {
+                                broker.failed();
+                            }
+                            if !established && negotiation.refused(refusal.take()) {
+                                println!("⚠️ El broker no acepta MQTT 5, se usará MQTT 3.1.1");
+                            }
+                        },
+                        Err(e) => {
+                            println!("❌ No se pudo crear el cliente MQTT: {:?}", e);
+                            broker.failed();
+                        },
                     }
                 },
-                Ok(_) => {},
-                Err(e) => {
-                    eprintln!("❌ Error MQTT: {:?}", e);
-                    thread::sleep(Duration::from_secs(1));
+                // Nunca se recurre a un broker público: se vuelve a preguntar tras el backoff
+                Ok(None) => println!("⚠️ Sin broker configurado ni descubierto, se reintentará"),
+                Err(e) => println!("❌ Red no disponible: {:?}", e),
+            }
+
+            session_link.failed();
+            let delay = backoff.next_delay_ms();
+            println!("⏳ Reintento de conexión {} en {} ms", backoff.attempt(), delay);
+            thread::sleep(Duration::from_millis(delay));
+        }
+    });
+
+    // Tarea de publicación. Sin sesión, la telemetría durable se guarda en el
+    // outbox de flash y se reenvía en orden al volver la conexión; el resto se
+    // descarta
+    let tx_client = client.clone();
+    let tx_link = link.clone();
+    let mut outbox = open_outbox(&security_config);
+    #[cfg(feature = "secure")]
+    let outbox_stats = outbox.as_ref().map(|outbox| outbox.stats());
+    spawn_task("mqtt_tx", LARGE_STACK_SIZE, move || loop {
+        let next = match publish_rx.recv_timeout(Duration::from_millis(IDLE_TICK_MS)) {
+            Ok(publication) => Some(publication),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Primero lo que quedó guardado, en el orden en que se produjo
+        if let Some(outbox) = outbox.as_mut() {
+            if tx_link.is_connected() && !outbox.is_empty() {
+                match outbox.replay(OUTBOX_REPLAY_BATCH, |p| tx_link.is_connected() && send(&tx_client, &tx_link, p)) {
+                    Ok(0) => {},
+                    Ok(sent) => println!("📤 {} mensajes reenviados desde flash ({} pendientes)", sent, outbox.len()),
+                    Err(e) => println!("❌ Error leyendo el outbox: {}", e),
                 }
             }
         }
+
+        let Some(publication) = next else {
+            continue;
+        };
+
+        // Mientras quede telemetría en flash la nueva va detrás, para no desordenarla
+        let backlog = outbox.as_ref().is_some_and(|outbox| !outbox.is_empty());
+        if !(publication.durable && backlog) && tx_link.is_connected() && send(&tx_client, &tx_link, &publication) {
+            continue;
+        }
+
+        match outbox.as_mut() {
+            Some(outbox) if publication.durable => {
+                if let Err(e) = outbox.push(&publication) {
+                    println!("⚠️ Telemetría para {} descartada: {}", publication.topic, e);
+                }
+            },
+            _ => println!("⚠️ Sin conexión MQTT, mensaje para {} descartado", publication.topic),
+        }
+    });
+
+    // Tarea de actuación: los beeps bloquean solo esta tarea
+    let actuation_events = events.clone();
+    spawn_task("actuation", SMALL_STACK_SIZE, move || {
+        // Sonido de inicio
+        #[cfg(not(feature = "secure"))]
+        let _ = actuator.buzzer().startup_sound();
+
+        // Test de LEDs (el rate limiting de la variante segura exige más de 1s entre cambios)
+        println!("🔄 Test de LEDs...");
+        let led_test_delay_ms = if cfg!(feature = "secure") { 1100 } else { 200 };
+        for i in 1..=3 {
+            match actuator.leds().set_led(i, true) {
+                Ok(_) => println!("  LED {} encendido", i),
+                Err(e) => println!("  Error LED {}: {}", i, e),
+            }
+            FreeRtos::delay_ms(led_test_delay_ms);
+            let _ = actuator.leds().set_led(i, false);
+            FreeRtos::delay_ms(200);
+        }
+
+        for command in action_rx {
+            println!("⚡ Ejecutando comando: {} de {}", command.command, command.from);
+
+            let outcome = actuator.handle(command);
+            match &outcome.result {
+                Ok(msg) => {
+                    println!("✅ {}", msg);
+                },
+                Err((_, e)) => {
+                    println!("❌ Error ejecutando comando: {}", e);
+                }
+            }
+
+            actuation_events.post(Event::LedStates(actuator.led_states()));
+            actuation_events.post(Event::CommandDone(outcome));
+        }
+    });
+
+    // Tarea de entrada: muestrea los botones sin depender de nadie más
+    let input_events = events.clone();
+    spawn_task("input", SMALL_STACK_SIZE, move || loop {
+        if let Some(button_id) = button_controller.check_buttons() {
+            input_events.post(Event::ButtonPressed { button_id, timestamp_ms: clock.now_ms() });
+        }
+        FreeRtos::delay_ms(10);
     });
 
-    FreeRtos::delay_ms(1000);
     println!("✅ MQTT conectado y suscrito");
     println!("🎯 Sistema listo - esperando comandos y botones");
 
-    // Loop principal
+    // Política de la NVS (o la de fábrica); se sustituye por config/policy
+    #[cfg(feature = "secure")]
+    let mut policy_store = match PolicyStore::open(credential_partition.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            println!("⚠️ NVS de la política no disponible: {:?}", e);
+            None
+        }
+    };
+    #[cfg(feature = "secure")]
+    let (policy, mut policy_floor) = policy_store.as_ref().map(|store| store.load(&security_config.command_keys)).unwrap_or_default();
+    #[cfg(feature = "secure")]
+    println!("📜 Política de autorización v{} ({} reglas)", policy.version, policy.rules.len());
+    #[cfg(feature = "secure")]
+    let mut command_validator = CommandValidator::new(security_config.max_command_rate, clock).with_policy(policy);
+
+    // Cuotas diarias guardadas: un reinicio no las pone a cero
+    let mut quota_store = match QuotaStore::open(n.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            println!("⚠️ NVS de las cuotas no disponible, se reiniciarán con el dispositivo: {:?}", e);
+            None
+        }
+    };
+    let mut quotas = Quotas::new(security_config.quota, quota_store.as_ref().map(QuotaStore::load).unwrap_or_default());
+    println!("🎫 Cuotas: {}", quotas.status(wall_clock_ms()));
+    let mut quota_save_time = 0u64;
+
+    // Tarea principal: decide qué hacer con cada evento y publica el estado
+    let mut request_ids = RequestIds::new(&security_config.device_id);
+    let mut recent = RecentCommands::new();
+    let peer_id = security_config.peer_device_id.clone().unwrap_or_else(|| DEFAULT_PEER_ID.to_string());
+    // Los ACK a ESP32 #1 se firman con la clave de este dispositivo en cmd_keys
+    let signing_key = security_config.command_keys.key(&security_config.device_id).map(<[u8]>::to_vec);
+    if signing_key.is_none() {
+        if cfg!(feature = "secure") {
+            panic!("Sin clave en cmd_keys para {}: la variante segura no envía comandos sin firma", security_config.device_id);
+        }
+        println!("⚠️ Sin clave en cmd_keys para {}: los ACK se envían sin firma", security_config.device_id);
+    }
+    let mut led_states = [false; 3];
+    // Tras el test de LEDs todo está apagado; el desired guardado se
+    // reconcilia cuando el retenido llega al suscribirse
+    let mut desired_store = match DesiredStore::open(credential_partition.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            println!("⚠️ NVS del estado deseado no disponible: {:?}", e);
+            None
+        }
+    };
+    let mut shadow = desired_store
+        .as_ref()
+        .map(|store| store.load(&security_config.command_keys, &security_config.device_id, led_states))
+        .unwrap_or_else(|| Shadow::new(led_states));
+    if shadow.floor() > 0 {
+        println!("🪞 Estado deseado aceptado hasta v{}", shadow.floor());
+    }
+    let mut last_status_time = 0u64;
+    #[cfg(feature = "secure")]
+    let mut heartbeat_time = 0u64;
+    // Con TLS el broker se verifica contra la CA del proyecto y el dispositivo
+    // se autentica con su propio certificado: su vigencia se revisa cuando
+    // SNTP da la hora y después periódicamente
+    #[cfg(feature = "tls")]
+    let mut cert_check = ExpiryCheck::new();
+
     loop {
-        // 1. Procesar comandos recibidos
-        let commands_to_process: Vec<Command> = {
-            let mut queue = command_queue.lock().unwrap();
-            let commands = queue.clone();
-            queue.clear();
-            commands
-        };
+        match event_rx.recv_timeout(Duration::from_millis(IDLE_TICK_MS)) {
+            Ok(Event::CommandReceived(mut command)) => {
+                // Redelivery de QoS 1 o reintento del emisor: no se ejecuta otra vez
+                match recent.check(&command) {
+                    Seen::New => {},
+                    Seen::InProgress => {
+                        println!("🔁 Comando {} duplicado, aún en ejecución", command.command);
+                        continue;
+                    },
+                    Seen::Done(outcome) => {
+                        println!("🔁 Comando {} duplicado, se reenvía el resultado original", command.command);
+                        respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                        continue;
+                    },
+                }
 
-        for command in commands_to_process {
-            println!("⚡ Ejecutando comando: {} de {}", command.command, command.from);
-            
-            match command.command.as_str() {
-                "LED_ON" => {
-                    if let Some(led_id) = command.led_id {
-                        let _ = led_controller.set_led(led_id, true);
-                        println!("💡 LED {} encendido", led_id);
+                // Validar comando con el validador
+                #[cfg(feature = "secure")]
+                if let Err(rejection) = command_validator.validate_command(&command) {
+                    println!("🚫 Comando rechazado por validador: {}", rejection);
+                    // Sin tokens: además de responder, se publica cuánto presupuesto queda
+                    if let Rejection::RateLimited(limited) = &rejection {
+                        publish_telemetry(&publisher, encoding, &topics::event(&security_config.device_id, "rate_limited"), &RateLimitExceeded {
+                            v: SCHEMA_VERSION,
+                            device: &security_config.device_id,
+                            from: &command.from,
+                            command: &command.command,
+                            request_id: command.request_id.as_deref(),
+                            limit: limited.limit.name(),
+                            class: limited.class.name(),
+                            source_remaining: limited.source_remaining,
+                            class_remaining: limited.class_remaining,
+                            retry_after_ms: limited.retry_after_ms,
+                            timestamp: clock.now_ms(),
+                        });
                     }
+                    let outcome = CommandOutcome::failed(command, &rejection);
+                    recent.complete(&outcome);
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                    continue;
+                }
+
+                // Consulta de cuotas: se responde aquí, sin pasar por los actuadores
+                if command.command == quota::STATUS_COMMAND {
+                    let status = quotas.status(wall_clock_ms());
+                    publish_quota(&publisher, encoding, &security_config.device_id, &status, clock.now_ms());
+                    let outcome = CommandOutcome::ok(command, status.to_string());
+                    recent.complete(&outcome);
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                    continue;
+                }
+
+                // Cuotas diarias de comandos y de buzzer: se reservan aquí y se
+                // ajustan con el resultado del actuador
+                if let Err(e) = quotas.consume(&mut command, wall_clock_ms()) {
+                    println!("🚫 Comando rechazado por cuota: {}", e);
+                    let outcome = CommandOutcome::failed(command, &e);
+                    recent.complete(&outcome);
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                    continue;
+                }
+
+                // Se clona para poder responder si la cola de actuación está llena
+                if !actions.post(command.clone()) {
+                    println!("⚠️ Actuadores ocupados, comando descartado");
+                    // No se ejecutó: un reintento con el mismo id debe ejecutarse
+                    recent.forget(&command);
+                    let outcome = CommandOutcome::with_code(command, ErrorCode::Busy, "Actuator queue full");
+                    quotas.settle(&outcome);
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                }
+            },
+            Ok(Event::CommandRedelivered(command)) => match recent.check(&command) {
+                Seen::InProgress => println!("🔁 Comando {} repetido, aún en ejecución", command.command),
+                Seen::Done(outcome) => {
+                    println!("🔁 Comando {} repetido, se reenvía el resultado original", command.command);
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
                 },
-                "LED_OFF" => {
-                    if let Some(led_id) = command.led_id {
-                        let _ = led_controller.set_led(led_id, false);
-                        println!("💡 LED {} apagado", led_id);
-                    }
+                // Su resultado ya se olvidó: no se distingue de una repetición
+                // maliciosa y no se ejecuta otra vez
+                Seen::New => {
+                    recent.forget(&command);
+                    println!("🚫 Comando de {} descartado: {}", command.from, SignatureError::ReplayedNonce);
+                    events.post(Event::CommandRejected { command, reason: SignatureError::ReplayedNonce });
                 },
-                "LED_TOGGLE" => {
-                    if let Some(led_id) = command.led_id {
-                        if let Ok(new_state) = led_controller.toggle_led(led_id) {
-                            println!("🔄 LED {} {}", led_id, if new_state { "encendido" } else { "apagado" });
+            },
+            Ok(Event::CommandDone(outcome)) => {
+                recent.complete(&outcome);
+                // Los de los botones locales también: el beep de ACKNOWLEDGE
+                // cuenta para la cuota del buzzer
+                quotas.settle(&outcome);
+
+                // Los comandos de los botones locales no necesitan respuesta
+                if outcome.command.from != security_config.device_id {
+                    respond(&publisher, encoding, &security_config.device_id, &outcome, led_states, clock.now_ms());
+                }
+            },
+            Ok(Event::ButtonPressed { button_id, timestamp_ms }) => {
+                println!("🔘 Botón {} presionado!", button_id);
+
+                match button_id {
+                    1 => {
+                        // Botón 1: Toggle LED 1 local
+                        let mut toggle = Command::new(&security_config.device_id, &security_config.device_id, "LED_TOGGLE");
+                        toggle.led_id = Some(1);
+                        actions.post(toggle);
+                    },
+                    2 => {
+                        // Botón 2: Activar buzzer y enviar acknowledge a ESP32 #1.
+                        // El beep local gasta cuota de buzzer; sin cuota solo se
+                        // envía el acknowledge
+                        let mut beep = Command::new(&security_config.device_id, &security_config.device_id, "ACKNOWLEDGE");
+                        match quotas.consume_local(&mut beep, wall_clock_ms()) {
+                            Ok(()) => {
+                                if !actions.post(beep.clone()) {
+                                    quotas.settle(&CommandOutcome::with_code(beep, ErrorCode::Busy, "Actuator queue full"));
+                                }
+                            },
+                            Err(e) => println!("🔇 Beep local omitido: {}", e),
+                        }
+
+                        // Firmado como cualquier comando: hora SNTP, nonce y la clave propia
+                        let mut ack = CommandMessage::new(&security_config.device_id, &peer_id, "ACKNOWLEDGE");
+                        ack.security = SECURITY_VALIDATED;
+                        let request_id = request_ids.next_id();
+                        ack.request_id = Some(&request_id);
+                        let encoded = match signing_key.as_deref() {
+                            Some(key) => encode_signed(ack, key, encoding),
+                            // Solo sin la feature secure y sin cmd_keys
+                            None => Publication::encode_as(&topics::command(&peer_id), &ack, encoding).map_err(|e| e.to_string()),
+                        };
+                        match encoded {
+                            Ok(publication) => {
+                                let properties = Properties::command(&security_config.device_id, &request_id, security_config.command_expiry_secs);
+                                publisher.post(publication.with_properties(properties));
+                            },
+                            Err(e) => println!("❌ Comando ACKNOWLEDGE descartado: {}", e),
                         }
+                        println!("🔊 Buzzer + comando ACKNOWLEDGE enviado");
+                    },
+                    _ => {}
+                }
+
+                // Publicar evento de botón
+                publish_telemetry(&publisher, encoding, &topics::telemetry(&security_config.device_id, "button"), &ButtonEvent {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
+                    button_id,
+                    action: "pressed",
+                    boot: boot_id,
+                    timestamp: timestamp_ms,
+                    security: SECURITY_ENABLED,
+                });
+            },
+            Ok(Event::LedStates(states)) => {
+                led_states = states;
+                if shadow.report(states) {
+                    publish_reported(&publisher, encoding, &security_config.device_id, &shadow, clock.now_ms());
+                    publish_delta(&publisher, encoding, &security_config.device_id, &mut shadow, clock.now_ms());
+                }
+            },
+            Ok(Event::DesiredState(update)) => {
+                // El documento retenido vuelve en cada suscripción: ya está
+                // comprobado, basta con reconciliar
+                if !shadow.is_current(&update) {
+                    if let Err(reason) = shadow.accept(&update, &security_config.command_keys, &security_config.device_id) {
+                        println!("🚫 Estado deseado v{} de {} descartado: {}", update.version, update.from, reason);
+                        publish_telemetry(&publisher, encoding, &topics::event(&security_config.device_id, "desired_rejected"), &DesiredRejected {
+                            v: SCHEMA_VERSION,
+                            device: &security_config.device_id,
+                            from: &update.from,
+                            version: update.version,
+                            reason: reason.code(),
+                            timestamp: clock.now_ms(),
+                        });
+                        continue;
                     }
-                },
-                "LED_ALL_ON" => {
-                    led_controller.turn_on_all();
-                    println!("💡 Todos los LEDs encendidos");
-                },
-                "LED_ALL_OFF" => {
-                    led_controller.turn_off_all();
-                    println!("💡 Todos los LEDs apagados");
-                },
-                "BUZZER" => {
-                    let duration = command.duration.unwrap_or(500);
-                    let _ = buzzer.beep(1000, duration);
-                    println!("🔊 Buzzer activado por {}ms", duration);
-                },
-                "BUZZER_TRIPLE" => {
-                    let _ = buzzer.triple_beep();
-                    println!("🔊 Triple beep ejecutado");
-                },
-                _ => {
-                    println!("❓ Comando desconocido: {}", command.command);
+                    println!("🪞 Estado deseado v{} de {} aplicado", update.version, update.from);
+                    if let Some(Err(e)) = desired_store.as_mut().map(|store| store.save(&update)) {
+                        println!("⚠️ No se pudo guardar el estado deseado: {:?}", e);
+                    }
+                    shadow.set_desired(*update);
+                }
+
+                let commands = shadow.reconcile(&security_config.device_id);
+                if !commands.is_empty() {
+                    println!("🔀 Reconciliando {} LEDs con el estado deseado", commands.len());
+                }
+                // Por la misma cola que los comandos recibidos: deduplicación,
+                // validador, rate limiting y cuotas a nombre de quien firmó
+                for command in commands {
+                    if !events.post(Event::CommandReceived(command)) {
+                        println!("⚠️ Cola de eventos llena, reconciliación incompleta");
+                    }
+                }
+                publish_delta(&publisher, encoding, &security_config.device_id, &mut shadow, clock.now_ms());
+            },
+            Ok(Event::MqttConnected) => {
+                // En cada (re)conexión: sustituye el "offline" retenido
+                let mut online = Presence::online(&security_config.device_id, FIRMWARE_VERSION, boot_reason);
+                online.reconnects = Some(link.reconnects());
+                online.boot = boot_id;
+                match Publication::encode_as(&presence_topic, &online, encoding) {
+                    Ok(publication) => {
+                        publisher.post(publication.retained());
+                        println!("🟢 Presencia online publicada (firmware {}, arranque: {})", FIRMWARE_VERSION, boot_reason);
+                    },
+                    Err(e) => println!("❌ Presencia descartada: {}", e),
+                }
+
+                // Los cambios sin conexión no llegaron al broker
+                publish_reported(&publisher, encoding, &security_config.device_id, &shadow, clock.now_ms());
+            },
+            Ok(Event::CommandRejected { command, reason }) => {
+                publish_telemetry(&publisher, encoding, &topics::event(&security_config.device_id, "command_rejected"), &CommandRejected {
+                    v: SCHEMA_VERSION,
+                    device: &security_config.device_id,
+                    from: &command.from,
+                    command: &command.command,
+                    request_id: command.request_id.as_deref(),
+                    reason: reason.code(),
+                    timestamp: clock.now_ms(),
+                });
+            },
+            #[cfg(feature = "secure")]
+            Ok(Event::PolicyReceived(raw)) => {
+                let update = match decode_policy(&raw) {
+                    Ok(update) => update,
+                    Err(e) => {
+                        println!("❌ Política inválida: {}", e);
+                        continue;
+                    }
+                };
+                // El documento retenido vuelve en cada suscripción
+                if update.policy == *command_validator.policy() {
+                    continue;
+                }
+                match command_validator.policy().accept(&update, &security_config.command_keys, policy_floor) {
+                    Ok(()) => {
+                        println!("📜 Política v{} de {} aplicada ({} reglas)", update.policy.version, update.from, update.policy.rules.len());
+                        let accepted = update.accepted();
+                        if let Some(Err(e)) = policy_store.as_mut().map(|store| store.save(&raw, &accepted)) {
+                            println!("⚠️ Política no guardada en la NVS, se perderá al reiniciar: {:?}", e);
+                        }
+                        policy_floor = accepted.version;
+                        command_validator.set_policy(update.policy);
+                    },
+                    Err(reason) => {
+                        println!("🚫 Política v{} de {} descartada: {}", update.policy.version, update.from, reason);
+                        publish_telemetry(&publisher, encoding, &topics::event(&security_config.device_id, "policy_rejected"), &PolicyRejected {
+                            v: SCHEMA_VERSION,
+                            device: &security_config.device_id,
+                            from: &update.from,
+                            version: update.policy.version,
+                            reason: reason.code(),
+                            timestamp: clock.now_ms(),
+                        });
+                    },
                 }
+            },
+            Ok(_) => {},
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => {
+                panic!("Todas las tareas productoras terminaron");
             }
         }
 
-        // 2. Verificar botones locales
-        if let Some(button_id) = button_controller.check_buttons() {
-            println!("🔘 Botón {} presionado!", button_id);
-            
-            // Botón 1: Toggle LED 1
-            if button_id == 1 {
-                let _ = led_controller.toggle_led(1);
-                println!("🔄 LED 1 toggleado por botón local");
-            }
-            
-            // Botón 2: Activar buzzer y enviar comando a ESP32 #1
-            if button_id == 2 {
-                let _ = buzzer.beep(750, 300);
-                
-                // Enviar comando de vuelta a ESP32 #1
-                let mut msg_buf = [0u8; 128];
-                let msg_len = {
-                    let mut cursor = ArrayWriter::new(&mut msg_buf);
-                    write!(
-                        cursor,
-                        r#"{{"from":"esp32-actuator-01","to":"esp32-sensor-01","command":"ACKNOWLEDGE","timestamp":{}}}"#,
-                        esp_idf_svc::sys::esp_timer_get_time() / 1000
-                    ).unwrap();
-                    cursor.pos()
-                };
+        let current_time = clock.now_ms();
+
+        // Enviar estado de LEDs periódicamente
+        if current_time - last_status_time > STATUS_INTERVAL_MS && link.is_connected() {
+            publish(&publisher, encoding, &topics::state(&security_config.device_id), &LedStatus {
+                v: SCHEMA_VERSION,
+                device: &security_config.device_id,
+                led1: led_states[0],
+                led2: led_states[1],
+                led3: led_states[2],
+                timestamp: current_time,
+                security: SECURITY_ENABLED,
+            });
 
-                let _ = mqtt.publish(
-                    "esp32/commands",
-                    QoS::AtLeastOnce,
-                    false,
-                    &msg_buf[..msg_len],
-                );
-                
-                println!("🔊 Buzzer + comando ACKNOWLEDGE enviado");
+            last_status_time = current_time;
+        }
+
+        // Cuotas: cambio de día y guardado en la NVS, agrupando escrituras
+        if current_time - quota_save_time > QUOTA_SAVE_INTERVAL_MS {
+            if quotas.roll(wall_clock_ms()) {
+                println!("🌅 Día nuevo: cuotas reiniciadas");
+                let status = quotas.status(wall_clock_ms());
+                publish_quota(&publisher, encoding, &security_config.device_id, &status, current_time);
             }
-            
-            // Publicar evento de botón
-            let mut event_buf = [0u8; 128];
-            let event_len = {
-                let mut cursor = ArrayWriter::new(&mut event_buf);
-                write!(
-                    cursor,
-                    r#"{{"device":"esp32-actuator-01","button_id":{},"action":"pressed","timestamp":{}}}"#,
-                    button_id,
-                    esp_idf_svc::sys::esp_timer_get_time() / 1000
-                ).unwrap();
-                cursor.pos()
-            };
-
-            let _ = mqtt.publish(
-                "esp32/button/events",
-                QoS::AtLeastOnce,
-                false,
-                &event_buf[..event_len],
-            );
-            
-            FreeRtos::delay_ms(300); // Debounce
+            if quotas.take_dirty() {
+                if let Some(Err(e)) = quota_store.as_mut().map(|store| store.save(&quotas.state())) {
+                    println!("⚠️ Cuotas no guardadas en la NVS: {:?}", e);
+                }
+            }
+            quota_save_time = current_time;
         }
 
-        // 3. Enviar estado de LEDs periódicamente
-        static mut LAST_STATUS_TIME: u64 = 0;
-        let current_time = esp_idf_svc::sys::esp_timer_get_time() / 1000;
-        
-        unsafe {
-            if current_time - LAST_STATUS_TIME > 10000 { // Cada 10 segundos
-                let mut status_buf = [0u8; 200];
-                let status_len = {
-                    let mut cursor = ArrayWriter::new(&mut status_buf);
-                    write!(
-                        cursor,
-                        r#"{{"device":"esp32-actuator-01","led1":{},"led2":{},"led3":{},"timestamp":{}}}"#,
-                        led_controller.get_state(1),
-                        led_controller.get_state(2), 
-                        led_controller.get_state(3),
-                        current_time
-                    ).unwrap();
-                    cursor.pos()
-                };
+        // Heartbeat de seguridad
+        #[cfg(feature = "secure")]
+        if current_time - heartbeat_time > 30000 && link.is_connected() { // Cada 30 segundos
+            publish(&publisher, encoding, &topics::heartbeat(&security_config.device_id), &Heartbeat {
+                v: SCHEMA_VERSION,
+                device: &security_config.device_id,
+                status: "healthy",
+                uptime: current_time / 1000,
+                security: SECURITY_ENABLED,
+                commands_processed: Some(command_validator.command_count()),
+                dropped_events: Some(actions.dropped() + publisher.dropped()),
+                reconnects: Some(link.reconnects()),
+                outbox_pending: outbox_stats.as_ref().map(|stats| stats.pending()),
+                outbox_dropped: outbox_stats.as_ref().map(|stats| stats.dropped()),
+                outbox_policy: outbox_stats.as_ref().map(|_| security_config.outbox_policy.name()),
+                policy_version: Some(command_validator.policy().version),
+            });
 
-                let _ = mqtt.publish(
-                    "esp32/status",
-                    QoS::AtLeastOnce,
-                    false,
-                    &status_buf[..status_len],
-                );
-                
-                LAST_STATUS_TIME = current_time;
-            }
+            heartbeat_time = current_time;
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(now) = cert_check.due(wall_clock_ms().map(|ms| ms / 1000)) {
+            check_certificates(now);
         }
-        
-        FreeRtos::delay_ms(50);
     }
-}
\ No newline at end of file
+}
@@ -47,6 +47,20 @@ openssl genrsa -out esp32-device-2.key 2048
 openssl req -new -key esp32-device-2.key -subj "/C=CO/ST=Risaralda/L=Pereira/O=ESP32-IoT/OU=Device/CN=esp32-actuator-01" -out esp32-device-2.csr
 openssl x509 -req -in esp32-device-2.csr -CA ca.crt -CAkey ca.key -CAcreateserial -out esp32-device-2.crt -days 365 -sha256
 
+# 4b. Certificado del dashboard de Node-RED: su CN es el emisor de los
+#     comandos firmados (node-red-dashboard) y su usuario en la ACL
+echo "📊 Generando certificado de Node-RED..."
+openssl genrsa -out node-red-dashboard.key 2048
+openssl req -new -key node-red-dashboard.key -subj "/C=CO/ST=Risaralda/L=Pereira/O=ESP32-IoT/OU=Operator/CN=node-red-dashboard" -out node-red-dashboard.csr
+openssl x509 -req -in node-red-dashboard.csr -CA ca.crt -CAkey ca.key -CAcreateserial -out node-red-dashboard.crt -days 365 -sha256
+
+# 4c. Certificado del bot de Telegram: como el dashboard, su CN es su id de
+#     emisor (telegram-bot) y su usuario en la ACL
+echo "🤖 Generando certificado del bot de Telegram..."
+openssl genrsa -out telegram-bot.key 2048
+openssl req -new -key telegram-bot.key -subj "/C=CO/ST=Risaralda/L=Pereira/O=ESP32-IoT/OU=Operator/CN=telegram-bot" -out telegram-bot.csr
+openssl x509 -req -in telegram-bot.csr -CA ca.crt -CAkey ca.key -CAcreateserial -out telegram-bot.crt -days 365 -sha256
+
 # 5. Convertir certificados a formato DER para ESP32
 echo "🔄 Convirtiendo certificados a formato DER..."
 openssl x509 -outform der -in ca.crt -out ca.der
@@ -55,96 +69,79 @@ openssl x509 -outform der -in esp32-device-2.crt -out esp32-device-2.der
 openssl rsa -in esp32-device-1.key -outform der -out esp32-device-1.der.key
 openssl rsa -in esp32-device-2.key -outform der -out esp32-device-2.der.key
 
-# 6. Crear archivos C header para ESP32
-echo "📄 Generando archivos header para ESP32..."
-
-# CA Certificate header
-echo "🔗 Creando ca_cert.h..."
-cat > ca_cert.h << EOF
-#ifndef CA_CERT_H
-#define CA_CERT_H
-
-extern const uint8_t ca_cert_der_start[] asm("_binary_ca_der_start");
-extern const uint8_t ca_cert_der_end[] asm("_binary_ca_der_end");
-
-#endif
-EOF
-
-# ESP32 Device 1 certificate header
-echo "🔗 Creando device1_cert.h..."
-cat > device1_cert.h << EOF
-#ifndef DEVICE1_CERT_H  
-#define DEVICE1_CERT_H
-
-extern const uint8_t device1_cert_der_start[] asm("_binary_esp32_device_1_der_start");
-extern const uint8_t device1_cert_der_end[] asm("_binary_esp32_device_1_der_end");
-extern const uint8_t device1_key_der_start[] asm("_binary_esp32_device_1_der_key_start");
-extern const uint8_t device1_key_der_end[] asm("_binary_esp32_device_1_der_key_end");
-
-#endif
-EOF
-
-# ESP32 Device 2 certificate header
-echo "🔗 Creando device2_cert.h..."
-cat > device2_cert.h << EOF
-#ifndef DEVICE2_CERT_H
-#define DEVICE2_CERT_H
+# 6. Los firmwares embeben ca.der, esp32-device-N.der y esp32-device-N.der.key
+#    directamente desde este directorio al compilar con la feature "tls"
+#    (build.rs de cada dispositivo; CERTS_DIR permite usar otro directorio)
 
-extern const uint8_t device2_cert_der_start[] asm("_binary_esp32_device_2_der_start");
-extern const uint8_t device2_cert_der_end[] asm("_binary_esp32_device_2_der_end");
-extern const uint8_t device2_key_der_start[] asm("_binary_esp32_device_2_der_key_start");
-extern const uint8_t device2_key_der_end[] asm("_binary_esp32_device_2_der_key_end");
-
-#endif
-EOF
-
-# 7. Configuración para Mosquitto MQTT Broker local
+# 7. Configuración para Mosquitto MQTT Broker local. Solo hay listener TLS:
+#    sin certificado de cliente no hay usuario al que aplicar la ACL, y un
+#    listener anónimo en 1883 la saltaría por completo
 echo "🦟 Generando configuración para Mosquitto..."
 cat > mosquitto.conf << EOF
 # Configuración Mosquitto con TLS
-listener 1883
-allow_anonymous true
+per_listener_settings true
 
 listener 8883
 cafile $PWD/ca.crt
-certfile $PWD/server.crt  
+certfile $PWD/server.crt
 keyfile $PWD/server.key
 require_certificate true
 use_identity_as_username true
+allow_anonymous false
+acl_file $PWD/mosquitto.acl
 
 log_dest stdout
 log_type all
 EOF
 
-# 8. Script para copiar certificados a proyectos ESP32
-cat > copy_certs_to_esp32.sh << 'EOF'
-#!/bin/bash
-
-echo "📋 Copiando certificados a proyectos ESP32..."
-
-# Crear directorios de certificados en proyectos ESP32
-mkdir -p ../../esp32-device-1/main/certs
-mkdir -p ../../esp32-device-2/main/certs
-
-# Copiar certificados CA y de dispositivos
-cp ca.der ../../esp32-device-1/main/certs/
-cp esp32-device-1.der ../../esp32-device-1/main/certs/
-cp esp32-device-1.der.key ../../esp32-device-1/main/certs/
-cp ca_cert.h ../../esp32-device-1/main/
-cp device1_cert.h ../../esp32-device-1/main/
-
-cp ca.der ../../esp32-device-2/main/certs/
-cp esp32-device-2.der ../../esp32-device-2/main/certs/
-cp esp32-device-2.der.key ../../esp32-device-2/main/certs/
-cp ca_cert.h ../../esp32-device-2/main/
-cp device2_cert.h ../../esp32-device-2/main/
-
-echo "✅ Certificados copiados a proyectos ESP32"
-echo "📝 Recuerda actualizar CMakeLists.txt para incluir los archivos .der"
+# 8. ACL: el usuario es el CN del certificado, que es el DEVICE_ID
+#    (esp32-sensor-01, esp32-actuator-01) o el emisor de los comandos
+#    (node-red-dashboard, telegram-bot). Cada dispositivo publica solo en sus
+#    tópicos de telemetría y estado, nunca en su desired ni en su política, y
+#    solo envía comandos al dispositivo con el que trabaja. Los resultados no
+#    van firmados, así que solo el actuador, que es quien los publica, puede
+#    escribir en los buzones de respuestas
+cat > mosquitto.acl << EOF
+# Todos los usuarios: su propio espacio esp32/{usuario}/
+pattern write esp32/%u/telemetry/#
+pattern write esp32/%u/events/#
+pattern write esp32/%u/state
+pattern write esp32/%u/presence
+pattern write esp32/%u/heartbeat
+pattern write esp32/%u/quota
+pattern write esp32/%u/shadow/reported
+pattern write esp32/%u/shadow/delta
+pattern read esp32/%u/cmd
+pattern read esp32/%u/responses
+pattern read esp32/%u/shadow/desired
+pattern read esp32/%u/config/policy
+pattern read esp32/all/cmd
+pattern read esp32/group/+/cmd
+
+# ESP32 #1 envía BUZZER/LED a ESP32 #2
+user esp32-sensor-01
+topic write esp32/esp32-actuator-01/cmd
+
+# ESP32 #2 envía ACKNOWLEDGE a ESP32 #1 y publica el resultado de cada
+# comando en el buzón de quien lo envió
+user esp32-actuator-01
+topic write esp32/esp32-sensor-01/cmd
+topic write esp32/+/responses
+
+# Bot de Telegram: envía comandos al actuador y lee sus respuestas
+user telegram-bot
+topic write esp32/esp32-actuator-01/cmd
+
+# Operadores: leen todo y son los únicos que publican comandos de grupo,
+# el desired y la política
+user node-red-dashboard
+topic read esp32/#
+topic write esp32/+/cmd
+topic write esp32/group/+/cmd
+topic write esp32/+/shadow/desired
+topic write esp32/+/config/policy
 EOF
 
-chmod +x copy_certs_to_esp32.sh
-
 # 9. Limpiar archivos temporales
 rm -f *.csr *.ext
 
@@ -157,14 +154,15 @@ echo "   • ca.crt / ca.key - Certificate Authority"
 echo "   • server.crt / server.key - Certificado del servidor"  
 echo "   • esp32-device-1.crt / esp32-device-1.key - Device #1"
 echo "   • esp32-device-2.crt / esp32-device-2.key - Device #2"
+echo "   • node-red-dashboard.crt / node-red-dashboard.key - Node-RED"
+echo "   • telegram-bot.crt / telegram-bot.key - Bot de Telegram"
 echo "   • *.der - Formato binario para ESP32"
-echo "   • *.h - Headers para incluir en código C"
 echo "   • mosquitto.conf - Configuración MQTT broker"
+echo "   • mosquitto.acl - Permisos por dispositivo"
 echo ""
 echo "🔧 Siguientes pasos:"
-echo "   1. Ejecutar: ./copy_certs_to_esp32.sh"
-echo "   2. Actualizar código ESP32 para usar TLS"
-echo "   3. Configurar servidor Rust con certificados"
-echo "   4. Ejecutar Mosquitto: mosquitto -c mosquitto.conf"
+echo "   1. Compilar los firmwares con --features tls (o secure)"
+echo "   2. Ejecutar Mosquitto: mosquitto -c mosquitto.conf"
+echo "   3. Arrancar Node-RED desde la raíz del repositorio (usa certs/node-red-dashboard.*)"
 echo ""
 echo "🔒 Sistema de seguridad TLS configurado!"
\ No newline at end of file